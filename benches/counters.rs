@@ -0,0 +1,78 @@
+//! Throughput of the counting hot paths over in-memory inputs of a few
+//! shapes. Run with `cargo bench`.
+
+use std::hint::black_box;
+use std::io::{self, Cursor};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gfwc::{count_str, Columns, CountType, Counter};
+
+/// Roughly how large each generated input is.
+const INPUT_LEN: usize = 4 * 1024 * 1024;
+
+/// Repeats `unit` until the result is at least [`INPUT_LEN`] bytes long.
+fn repeat_to_len(unit: &str) -> String {
+    unit.repeat(INPUT_LEN.div_ceil(unit.len()))
+}
+
+/// The inputs every counter is run over, by name.
+fn inputs() -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "ascii",
+            repeat_to_len("The quick brown fox jumps over the lazy dog.\n"),
+        ),
+        ("emoji", repeat_to_len("🦀 héllo 🌍 wörld ✨ 日本語\n")),
+        ("long-line", repeat_to_len("lorem ipsum dolor sit amet ")),
+        ("short-lines", repeat_to_len("a\nbb\n\nccc\n")),
+    ]
+}
+
+/// One of the `*_from_reader` counters, run over a byte slice.
+type ReaderCount = fn(&[u8]) -> io::Result<usize>;
+
+fn bench_readers(c: &mut Criterion) {
+    let counters: [(&str, ReaderCount); 4] = [
+        ("bytes", |input| {
+            Counter::count_bytes_from_reader(Cursor::new(input))
+        }),
+        ("lines", |input| {
+            Counter::count_lines_from_reader(Cursor::new(input))
+        }),
+        ("words", |input| {
+            Counter::count_words_from_reader(Cursor::new(input))
+        }),
+        ("chars", |input| {
+            Counter::count_chars_from_reader(Cursor::new(input))
+        }),
+    ];
+    for (counter_name, count) in counters {
+        let mut group = c.benchmark_group(counter_name);
+        for (input_name, input) in inputs() {
+            group.throughput(Throughput::Bytes(input.len() as u64));
+            group.bench_with_input(
+                BenchmarkId::from_parameter(input_name),
+                input.as_bytes(),
+                |b, input| b.iter(|| count(black_box(input)).unwrap()),
+            );
+        }
+        group.finish();
+    }
+}
+
+/// The default lines, words and bytes, counted in one pass over the text.
+fn bench_count_all(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count_all");
+    for (input_name, input) in inputs() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(input_name),
+            input.as_str(),
+            |b, input| b.iter(|| count_str(black_box(input), CountType::Plain(Columns::DEFAULT))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_readers, bench_count_all);
+criterion_main!(benches);
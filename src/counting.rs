@@ -0,0 +1,412 @@
+//! Counting over byte slices in memory, with no I/O. Nothing here needs
+//! `std`, so the same logic can run where there is no filesystem; the
+//! reader-based counters in the crate root are built on it.
+
+/// Counts newline bytes, as `wc -l` does, so a final line without a
+/// terminator isn't counted.
+///
+/// ```
+/// assert_eq!(gfwc::counting::count_newlines(b"one\ntwo\nthree"), 2);
+/// ```
+pub fn count_newlines(bytes: &[u8]) -> usize {
+    bytecount::count(bytes, b'\n')
+}
+
+/// Counts words as `-w` does: maximal runs of bytes outside the C locale's
+/// whitespace set.
+///
+/// ```
+/// assert_eq!(gfwc::counting::count_words(b"one\x0btwo  three\n"), 3);
+/// ```
+pub fn count_words(bytes: &[u8]) -> usize {
+    let mut counter = WordCounter::new();
+    counter.push(bytes);
+    counter.finish()
+}
+
+/// Counts the characters of UTF-8 `bytes` as `-m` does. Bytes that don't
+/// form a valid character aren't counted.
+///
+/// ```
+/// assert_eq!(gfwc::counting::count_chars("héllo".as_bytes()), 5);
+/// assert_eq!(gfwc::counting::count_chars(b"a\xffb"), 2);
+/// ```
+pub fn count_chars(bytes: &[u8]) -> usize {
+    let mut state = CountState::new();
+    state.update(bytes);
+    state.finish().chars as usize
+}
+
+/// Approximates the number of sentences in `text`, for `--sentences`. A run
+/// of `.`, `!` and `?` ends a sentence when it is followed, after any closing
+/// quotes or brackets, by the end of the text or by whitespace and an
+/// uppercase letter. So the point in `3.14` doesn't count, nor does an
+/// ellipsis mid-sentence, and `...` or `?!` count once. A lone `.` after a
+/// common abbreviation such as `Mr` or `e.g` doesn't count either. Text
+/// after the last terminator isn't counted as a sentence.
+///
+/// ```
+/// use gfwc::counting::count_sentences;
+///
+/// assert_eq!(count_sentences("Mr. Smith paid 3.14 dollars. Then he left!"), 2);
+/// ```
+pub fn count_sentences(text: &str) -> usize {
+    let mut counter = SentenceCounter::new();
+    text.chars().for_each(|c| counter.push(c));
+    counter.finish()
+}
+
+/// Incremental [`count_sentences`], fed one character at a time, so text
+/// arriving in pieces needn't be held. Only the word before the latest
+/// terminator is kept, and only as far as an abbreviation could reach.
+///
+/// ```
+/// use gfwc::counting::SentenceCounter;
+///
+/// let mut counter = SentenceCounter::new();
+/// "Ask Dr. Jones. He kno".chars().for_each(|c| counter.push(c));
+/// "ws!".chars().for_each(|c| counter.push(c));
+/// assert_eq!(counter.finish(), 2);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SentenceCounter {
+    count: usize,
+    /// The letters and dots since the last other character, up to one more
+    /// than the longest abbreviation; any longer and none can match.
+    word: [char; MAX_ABBREVIATION_LEN + 1],
+    word_len: usize,
+    /// The run of terminators being looked past, if any.
+    run: Option<TerminatorRun>,
+}
+
+/// A run of `.`, `!` and `?`, and what has followed it so far.
+#[derive(Clone, Copy, Debug)]
+struct TerminatorRun {
+    /// A lone `.` after an abbreviation, which never ends a sentence.
+    abbreviated: bool,
+    /// Whether a character other than a terminator came after the run.
+    ended: bool,
+    /// Whether whitespace came after the run and any closing quotes.
+    spaced: bool,
+}
+
+impl SentenceCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, c: char) {
+        if let Some(run) = &mut self.run {
+            let decided = if !run.ended && is_terminator(c) {
+                run.abbreviated = false;
+                false
+            } else {
+                run.ended = true;
+                if !run.spaced && matches!(c, '"' | '\'' | ')' | ']' | '\u{201D}' | '\u{2019}') {
+                    false
+                } else if c.is_whitespace() {
+                    run.spaced = true;
+                    false
+                } else {
+                    if run.spaced && c.is_uppercase() && !run.abbreviated {
+                        self.count += 1;
+                    }
+                    true
+                }
+            };
+            if !decided {
+                self.push_word(c);
+                return;
+            }
+            self.run = None;
+        }
+        if is_terminator(c) {
+            self.run = Some(TerminatorRun {
+                abbreviated: c == '.' && self.is_abbreviation(),
+                ended: false,
+                spaced: false,
+            });
+        }
+        self.push_word(c);
+    }
+
+    /// The number of sentences pushed. A terminator run at the very end
+    /// ends one.
+    pub fn finish(self) -> usize {
+        match self.run {
+            Some(run) if !run.abbreviated => self.count + 1,
+            _ => self.count,
+        }
+    }
+
+    fn push_word(&mut self, c: char) {
+        if !(c.is_alphabetic() || c == '.') {
+            self.word_len = 0;
+        } else if self.word_len < self.word.len() {
+            self.word[self.word_len] = c;
+            self.word_len += 1;
+        }
+    }
+
+    /// Whether the word so far is one that is usually abbreviated, so a `.`
+    /// after it doesn't end a sentence.
+    fn is_abbreviation(&self) -> bool {
+        let word = &self.word[..self.word_len];
+        ABBREVIATIONS.iter().any(|abbreviation| {
+            abbreviation.chars().count() == word.len()
+                && abbreviation
+                    .chars()
+                    .zip(word)
+                    .all(|(a, b)| a.eq_ignore_ascii_case(b))
+        })
+    }
+}
+
+fn is_terminator(c: char) -> bool {
+    matches!(c, '.' | '!' | '?')
+}
+
+const ABBREVIATIONS: [&str; 12] = [
+    "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "vs", "etc", "e.g", "i.e",
+];
+
+/// Characters in the longest of [`ABBREVIATIONS`].
+const MAX_ABBREVIATION_LEN: usize = 4;
+
+/// The C locale's `isspace` set. Note that `u8::is_ascii_whitespace` leaves
+/// out vertical tab, so it can't be used here.
+pub(crate) const fn is_c_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | 0x0B | 0x0C | b'\r')
+}
+
+/// [`is_c_space`] for every byte value, so that the word walk in
+/// [`WordCounter::push`] is a single lookup per byte.
+const C_SPACES: [bool; 256] = {
+    let mut spaces = [false; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        spaces[byte] = is_c_space(byte as u8);
+        byte += 1;
+    }
+    spaces
+};
+
+/// Totals produced by [`CountState::finish`].
+///
+/// Fields are `u64` regardless of pointer width so that a 32-bit build can
+/// count streams larger than 4 GiB. Reaching `u64::MAX` would take centuries
+/// of input at any realistic throughput, so the additions are not checked.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CountSummary {
+    pub bytes: u64,
+    pub chars: u64,
+    pub words: u64,
+    pub lines: u64,
+}
+
+impl core::ops::AddAssign for CountSummary {
+    fn add_assign(&mut self, other: CountSummary) {
+        self.bytes += other.bytes;
+        self.chars += other.chars;
+        self.words += other.words;
+        self.lines += other.lines;
+    }
+}
+
+/// Incremental word counter that can be fed successive chunks of input, such
+/// as packets from a network stream, without holding the whole input.
+///
+/// Whether the last chunk ended inside a word is carried over, so a word
+/// split across two chunks is counted once. Words are separated by the C
+/// locale's whitespace set, the same as `-w`.
+///
+/// ```
+/// use gfwc::WordCounter;
+///
+/// let mut wc = WordCounter::new();
+/// wc.push(b"one tw");
+/// wc.push(b"o three\n");
+/// assert_eq!(wc.finish(), 3);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WordCounter {
+    words: usize,
+    in_word: bool,
+}
+
+impl WordCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) {
+        // Branch-free, as word starts are too irregular to predict.
+        let mut in_word = self.in_word;
+        for &byte in chunk {
+            let space = C_SPACES[byte as usize];
+            self.words += usize::from(!in_word && !space);
+            in_word = !space;
+        }
+        self.in_word = in_word;
+    }
+
+    /// The number of words pushed. A word still open at the end counts.
+    pub fn finish(self) -> usize {
+        self.words
+    }
+}
+
+/// Incremental single-pass counter that can be fed successive chunks of input.
+///
+/// Word boundaries and partially received UTF-8 sequences are carried between
+/// `update` calls, so splitting the input at any
+/// byte offset yields the same result as feeding it in one piece. Words are
+/// separated by the C locale's whitespace set, the same as `-w`. Invalid UTF-8
+/// bytes count towards `bytes` and are treated as word characters, but are not
+/// counted as `chars`.
+#[derive(Clone, Debug, Default)]
+pub struct CountState {
+    pub(crate) summary: CountSummary,
+    in_word: bool,
+    decoder: Utf8Decoder,
+}
+
+impl CountState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        for &byte in chunk {
+            self.push_byte(byte);
+        }
+        self.summary.bytes += chunk.len() as u64;
+    }
+
+    pub fn finish(mut self) -> CountSummary {
+        if self.decoder.finish() {
+            self.invalid_sequence();
+        }
+        self.summary
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        if byte == b'\n' {
+            self.summary.lines += 1;
+        }
+        let (cut_short, decoded) = self.decoder.push(byte);
+        if cut_short {
+            self.invalid_sequence();
+        }
+        match decoded {
+            Decoded::Char(c) => self.push_char(c),
+            Decoded::Pending => {}
+            Decoded::Invalid => self.invalid_sequence(),
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        self.summary.chars += 1;
+        if c.is_ascii() && is_c_space(c as u8) {
+            self.in_word = false;
+        } else if !self.in_word {
+            self.in_word = true;
+            self.summary.words += 1;
+        }
+    }
+
+    fn invalid_sequence(&mut self) {
+        if !self.in_word {
+            self.in_word = true;
+            self.summary.words += 1;
+        }
+    }
+}
+
+/// What a byte pushed to a [`Utf8Decoder`] completed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Decoded {
+    Char(char),
+    /// More bytes of the current character are needed.
+    Pending,
+    /// The byte can't start a character, or can't continue the one begun.
+    Invalid,
+}
+
+/// Decodes UTF-8 a byte at a time, accepting exactly what
+/// `String::from_utf8` does: overlong forms, surrogates and code points past
+/// U+10FFFF are invalid.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Utf8Decoder {
+    /// Code point bits gathered so far.
+    code_point: u32,
+    /// Continuation bytes still expected.
+    pending: u8,
+    /// The range the next continuation byte must be in. Some lead bytes
+    /// narrow it for the byte straight after them.
+    lower: u8,
+    upper: u8,
+}
+
+impl Default for Utf8Decoder {
+    fn default() -> Self {
+        Utf8Decoder {
+            code_point: 0,
+            pending: 0,
+            lower: 0x80,
+            upper: 0xBF,
+        }
+    }
+}
+
+impl Utf8Decoder {
+    /// Decodes `byte`. The flag is set when a character begun earlier was
+    /// cut short, in which case `byte` was decoded as the start of a new one.
+    pub(crate) fn push(&mut self, byte: u8) -> (bool, Decoded) {
+        if self.pending > 0 {
+            if (self.lower..=self.upper).contains(&byte) {
+                self.code_point = (self.code_point << 6) | u32::from(byte & 0x3F);
+                self.pending -= 1;
+                self.lower = 0x80;
+                self.upper = 0xBF;
+                if self.pending > 0 {
+                    return (false, Decoded::Pending);
+                }
+                return match char::from_u32(self.code_point) {
+                    Some(c) => (false, Decoded::Char(c)),
+                    None => (false, Decoded::Invalid),
+                };
+            }
+            *self = Utf8Decoder::default();
+            return (true, self.start(byte));
+        }
+        (false, self.start(byte))
+    }
+
+    /// Whether input ended part way through a character.
+    pub(crate) fn finish(&mut self) -> bool {
+        let cut_short = self.pending > 0;
+        *self = Utf8Decoder::default();
+        cut_short
+    }
+
+    fn start(&mut self, byte: u8) -> Decoded {
+        let (bits, pending, lower, upper) = match byte {
+            0x00..=0x7F => return Decoded::Char(char::from(byte)),
+            0xC2..=0xDF => (byte & 0x1F, 1, 0x80, 0xBF),
+            0xE0 => (byte & 0x0F, 2, 0xA0, 0xBF),
+            0xED => (byte & 0x0F, 2, 0x80, 0x9F),
+            0xE1..=0xEF => (byte & 0x0F, 2, 0x80, 0xBF),
+            0xF0 => (byte & 0x07, 3, 0x90, 0xBF),
+            0xF4 => (byte & 0x07, 3, 0x80, 0x8F),
+            0xF1..=0xF3 => (byte & 0x07, 3, 0x80, 0xBF),
+            _ => return Decoded::Invalid,
+        };
+        self.code_point = u32::from(bits);
+        self.pending = pending;
+        self.lower = lower;
+        self.upper = upper;
+        Decoded::Pending
+    }
+}
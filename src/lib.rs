@@ -1,335 +1,9256 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+#[cfg(feature = "hash")]
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
 use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead, Cursor, Read};
-use std::sync::Arc;
+use std::io::{self, BufRead, Cursor, IsTerminal, Read, Seek, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "threads")]
 use std::thread;
+use std::time::{Duration, Instant};
 
+pub mod counting;
+
+use counting::{is_c_space, Decoded, SentenceCounter, Utf8Decoder};
+pub use counting::{CountState, CountSummary, WordCounter};
+
+/// What a [`Counter`] reports for each input.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CountType {
+    /// Any combination of the `-l`, `-w`, `-m` and `-c` counts.
+    Plain(Columns),
+    MaxLineLength,
+    BytesPerChar,
+    /// Longest run of blank lines, optionally failing above a limit.
+    MaxBlankRun(Option<usize>),
+    BracketBalance,
+    #[cfg(feature = "categories")]
+    ByCategory,
+    TrailingBlankLines,
+    /// Separate counts before and after the first `--split-at` marker.
+    SplitAt,
+    #[cfg(feature = "gzip")]
+    CompressionRatio,
+    TabAfterSpace,
+    /// Characters in the given Unicode plane (0 = BMP, 1 = SMP, ...).
+    Plane(u32),
+    TrailingNulls,
+    DistinctLengths(LengthUnit),
+    TrimSavings,
+    IndentChanges {
+        include_blank: bool,
+    },
+    AllUpperLines,
+    AllLowerLines,
+    EncodingReport,
+    FirstTokenFreq,
+    /// Every distinct word with its count (`--freq`).
+    WordFreq,
+    ShortestLine,
+    VocabCoverage,
+    /// Blocks of non-blank lines separated by blank lines.
+    ParagraphCount,
+    /// Sentences, approximated as in [`counting::count_sentences`].
+    Sentences,
+    /// Occurrences of the `--match` substring or `--match-regex` pattern.
+    Matches,
+    /// Lines with at least one non-whitespace character.
+    NonEmptyLines,
+    /// Lines that are empty or only whitespace.
+    BlankLines,
+    /// Shortest, longest and mean line width, measured as for `-L`.
+    LineStats,
+    /// Line terminators of each kind: `\n`, `\r\n` and a lone `\r`.
+    LineEndings,
+    /// Different lines, as `sort -u | wc -l` would count them.
+    UniqueLines,
+    /// Different lines that occur more than once, as `sort | uniq -d | wc -l`
+    /// would count them.
+    DuplicateLines,
+    /// Complete records of the given number of bytes, then the length of any
+    /// partial record left over at the end.
+    Records(usize),
+    /// Extended grapheme clusters, what a reader sees as one character: an
+    /// accented letter built from a combining mark, or a flag emoji, is one
+    /// grapheme but several of the code points `-m` counts.
+    #[cfg(feature = "segmentation")]
+    Graphemes,
+    #[cfg(feature = "json")]
+    ValidJsonLines,
+}
+
+/// The plain counts to print. Whatever order the flags come in, the columns
+/// are always printed in `wc`'s order: lines, words, chars, bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Columns {
+    pub lines: bool,
+    pub words: bool,
+    pub chars: bool,
+    pub bytes: bool,
+}
+
+impl Columns {
+    pub const LINES: Columns = Columns {
+        lines: true,
+        words: false,
+        chars: false,
+        bytes: false,
+    };
+    pub const WORDS: Columns = Columns {
+        words: true,
+        ..Columns::NONE
+    };
+    pub const CHARS: Columns = Columns {
+        chars: true,
+        ..Columns::NONE
+    };
+    pub const BYTES: Columns = Columns {
+        bytes: true,
+        ..Columns::NONE
+    };
+    pub const NONE: Columns = Columns {
+        lines: false,
+        words: false,
+        chars: false,
+        bytes: false,
+    };
+    /// What `wc` prints when no count is selected.
+    pub const DEFAULT: Columns = Columns {
+        lines: true,
+        words: true,
+        chars: false,
+        bytes: true,
+    };
+    /// Every plain count, as `--all` selects.
+    pub const ALL: Columns = Columns {
+        lines: true,
+        words: true,
+        chars: true,
+        bytes: true,
+    };
+
+    /// Parses the letters of a short flag such as `-lw`.
+    fn from_flags(letters: &str) -> Option<Columns> {
+        if letters.is_empty() {
+            return None;
+        }
+        letters.chars().try_fold(Columns::NONE, |columns, letter| {
+            let column = match letter {
+                'l' => Columns::LINES,
+                'w' => Columns::WORDS,
+                'm' => Columns::CHARS,
+                'c' => Columns::BYTES,
+                _ => return None,
+            };
+            Some(columns.union(column))
+        })
+    }
+
+    /// The selected columns of `counts` with their names, in output order.
+    fn entries(self, counts: Counts) -> impl Iterator<Item = (&'static str, usize)> {
+        [
+            (self.lines, "lines", counts.lines),
+            (self.words, "words", counts.words),
+            (self.chars, "chars", counts.chars),
+            (self.bytes, "bytes", counts.bytes),
+        ]
+        .into_iter()
+        .filter_map(|(selected, name, count)| selected.then_some((name, count)))
+    }
+
+    /// The selected column names, in output order.
+    fn names(self) -> impl Iterator<Item = &'static str> {
+        self.entries(Counts::default()).map(|(name, _)| name)
+    }
+
+    pub fn union(self, other: Columns) -> Columns {
+        Columns {
+            lines: self.lines || other.lines,
+            words: self.words || other.words,
+            chars: self.chars || other.chars,
+            bytes: self.bytes || other.bytes,
+        }
+    }
+}
+
+/// How the byte column is rendered.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ByteUnits {
+    Exact,
+    /// 1000-based units (kB, MB, GB, ...).
+    Si,
+    /// 1024-based units (K, M, G, ...).
+    Iec,
+}
+
+/// When the `total` line is printed (`--total=WHEN`).
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum TotalMode {
+    /// Only when there is more than one input.
+    Auto,
+    Always,
+    Never,
+    /// Just the total, without the per-input lines or a `total` label.
+    Only,
+    /// Just the total, labelled and led by the number of inputs counted
+    /// (`--summary`).
+    Summary,
+}
+
+impl TotalMode {
+    fn parse(when: &str) -> Option<TotalMode> {
+        match when {
+            "auto" => Some(TotalMode::Auto),
+            "always" => Some(TotalMode::Always),
+            "never" => Some(TotalMode::Never),
+            "only" => Some(TotalMode::Only),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the total line is highlighted (`--color=WHEN`).
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ColorMode {
+    /// Only when writing to a terminal and `NO_COLOR` isn't set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    fn parse(when: &str) -> Option<ColorMode> {
+        match when {
+            "auto" => Some(ColorMode::Auto),
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+
+    /// Settles `Auto` for output going to a terminal or not, following
+    /// <https://no-color.org>: a non-empty `NO_COLOR` turns it off.
+    fn resolve(self, terminal: bool) -> ColorMode {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        match self {
+            ColorMode::Auto if terminal && !no_color => ColorMode::Always,
+            ColorMode::Auto => ColorMode::Never,
+            mode => mode,
+        }
+    }
+}
+
+/// The order several inputs are printed in (`--sort=KEY`), rather than
+/// the order they were given. Ties go by name, so the output doesn't depend
+/// on the argument order.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SortOrder {
+    /// Paths compared as strings.
+    Name,
+    /// Smallest file first.
+    Size,
+    /// Fewest lines first.
+    Lines,
+}
+
+impl SortOrder {
+    fn parse(key: &str) -> Option<SortOrder> {
+        match key {
+            "name" => Some(SortOrder::Name),
+            "size" => Some(SortOrder::Size),
+            "lines" => Some(SortOrder::Lines),
+            _ => None,
+        }
+    }
+
+    /// The key `path` sorts by before its name. Inputs that couldn't be
+    /// counted or measured sort as if empty.
+    fn key(self, path: &str, outcome: &Result<Option<Counts>, String>) -> u64 {
+        match self {
+            SortOrder::Name => 0,
+            SortOrder::Size => std::fs::metadata(path).map_or(0, |metadata| metadata.len()),
+            SortOrder::Lines => match outcome {
+                Ok(Some(counts)) => counts.lines as u64,
+                _ => 0,
+            },
+        }
+    }
+}
+
+/// How plain counts are laid out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum OutputFormat {
+    /// `wc`'s tab-separated columns followed by the name.
+    Text,
+    /// A header row, then one RFC 4180 row per input (`--csv`).
+    Csv,
+    /// A `name=value` line per count, then `file=NAME`, with a blank line
+    /// between inputs (`--format=keyval`).
+    KeyVal,
+}
+
+impl OutputFormat {
+    fn parse(name: &str) -> Option<OutputFormat> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "csv" => Some(OutputFormat::Csv),
+            "keyval" => Some(OutputFormat::KeyVal),
+            _ => None,
+        }
+    }
+}
+
+/// Inclusive, 1-based range of character columns within each line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct ColumnRange {
+    start: usize,
+    end: usize,
+}
+
+impl ColumnRange {
+    fn parse(spec: &str) -> Option<ColumnRange> {
+        let (start, end) = spec.split_once(':')?;
+        let start = start.parse().ok()?;
+        let end = end.parse().ok()?;
+        if start == 0 || end < start {
+            return None;
+        }
+        Some(ColumnRange { start, end })
+    }
+
+    /// Returns the part of `line` covered by the range, indexing by character
+    /// rather than byte. Lines shorter than `start` yield an empty slice.
+    fn slice<'a>(&self, line: &'a str) -> &'a str {
+        let mut indices = line.char_indices().map(|(i, _)| i).chain([line.len()]);
+        let Some(from) = indices.nth(self.start - 1) else {
+            return "";
+        };
+        let to = indices.nth(self.end - self.start).unwrap_or(line.len());
+        &line[from..to]
+    }
+}
+
+/// Which lines `--odd-lines` and `--even-lines` keep. Lines are numbered
+/// from 1, so the first line is odd.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum LineParity {
+    Odd,
+    Even,
+}
+
+/// Inclusive `MIN:MAX` bounds for `--expect-*-between`. Either side may be
+/// left empty (`100:`, `:500`) to leave that end open.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct CountBounds {
+    min: Option<usize>,
+    max: Option<usize>,
+}
+
+impl CountBounds {
+    fn parse(spec: &str) -> Option<CountBounds> {
+        let (min, max) = spec.split_once(':')?;
+        let bound = |s: &str| match s {
+            "" => Some(None),
+            s => s.parse().ok().map(Some),
+        };
+        let bounds = CountBounds {
+            min: bound(min)?,
+            max: bound(max)?,
+        };
+        match (bounds.min, bounds.max) {
+            (None, None) => None,
+            (Some(min), Some(max)) if min > max => None,
+            _ => Some(bounds),
+        }
+    }
+
+    /// Describes the violation when `found` is out of bounds.
+    fn check(&self, what: &str, found: usize) -> Result<(), String> {
+        let expected = match (self.min, self.max) {
+            (Some(min), Some(max)) if found < min || found > max => {
+                format!("between {} and {}", min, max)
+            }
+            (Some(min), None) if found < min => format!("at least {}", min),
+            (None, Some(max)) if found > max => format!("at most {}", max),
+            _ => return Ok(()),
+        };
+        Err(format!("expected {} {}, found {}", expected, what, found))
+    }
+}
+
+/// Bounds given with `--expect-lines-between` and friends.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+struct Expectations {
+    lines: Option<CountBounds>,
+    words: Option<CountBounds>,
+    bytes: Option<CountBounds>,
+}
+
+impl Expectations {
+    fn is_empty(&self) -> bool {
+        *self == Expectations::default()
+    }
+
+    /// Checks each count that was produced against its bounds, if any.
+    fn check(
+        &self,
+        lines: Option<usize>,
+        words: Option<usize>,
+        bytes: Option<usize>,
+    ) -> Result<(), String> {
+        for (bounds, what, found) in [
+            (self.lines, "lines", lines),
+            (self.words, "words", words),
+            (self.bytes, "bytes", bytes),
+        ] {
+            if let (Some(bounds), Some(found)) = (bounds, found) {
+                bounds.check(what, found)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How input text is split into words.
+#[derive(Clone, Debug)]
+enum Tokenizer {
+    /// Maximal runs of non-whitespace, as `wc` counts them.
+    Whitespace,
+    /// Non-empty, non-overlapping matches of a user-supplied regex. Each line
+    /// is scanned separately, so matches never span a newline. Cost grows with
+    /// pattern complexity: large Unicode classes (`\w` is Unicode-aware) or
+    /// long alternations can be several times slower than whitespace splitting
+    /// on big files.
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+    /// Words as found by Unicode word segmentation (UAX #29), skipping
+    /// punctuation and spaces. Scripts written without spaces, such as
+    /// Chinese, split into one word per ideograph.
+    #[cfg(feature = "segmentation")]
+    UnicodeWords,
+    /// Whitespace-separated words, lowercased, with anything that isn't a
+    /// letter or digit stripped from either end (`--normalize-words`), so
+    /// `Hello,` and `hello` are the same word. A token that is all
+    /// punctuation, such as `--`, is not a word at all.
+    Normalized,
+}
+
+impl Tokenizer {
+    /// The tokens of `text`, in order. Only [`Tokenizer::Normalized`] ever
+    /// has to allocate.
+    fn tokens<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Cow<'a, str>> + 'a> {
+        let words = text
+            .split(|c: char| c.is_ascii() && is_c_space(c as u8))
+            .filter(|word| !word.is_empty());
+        match self {
+            Tokenizer::Whitespace => Box::new(words.map(Cow::Borrowed)),
+            #[cfg(feature = "regex")]
+            Tokenizer::Regex(re) => Box::new(
+                re.find_iter(text)
+                    .map(|m| m.as_str())
+                    .filter(|token| !token.is_empty())
+                    .map(Cow::Borrowed),
+            ),
+            #[cfg(feature = "segmentation")]
+            Tokenizer::UnicodeWords => {
+                use unicode_segmentation::UnicodeSegmentation;
+                Box::new(text.unicode_words().map(Cow::Borrowed))
+            }
+            Tokenizer::Normalized => Box::new(words.filter_map(normalize_word)),
+        }
+    }
+
+    fn count(&self, text: &str) -> usize {
+        match self {
+            Tokenizer::Whitespace => text
+                .split(|c: char| c.is_ascii() && is_c_space(c as u8))
+                .filter(|word| !word.is_empty())
+                .count(),
+            #[cfg(feature = "regex")]
+            Tokenizer::Regex(re) => re.find_iter(text).filter(|m| !m.is_empty()).count(),
+            #[cfg(feature = "segmentation")]
+            Tokenizer::UnicodeWords => {
+                use unicode_segmentation::UnicodeSegmentation;
+                text.unicode_words().count()
+            }
+            Tokenizer::Normalized => self.tokens(text).count(),
+        }
+    }
+}
+
+/// Lowercases `word` and strips non-alphanumeric characters from its ends,
+/// or returns `None` if nothing is left.
+fn normalize_word(word: &str) -> Option<Cow<'_, str>> {
+    let trimmed = word.trim_matches(|c: char| !c.is_alphanumeric());
+    if trimmed.is_empty() {
+        None
+    } else if trimmed.chars().any(char::is_uppercase) {
+        Some(Cow::Owned(trimmed.to_lowercase()))
+    } else {
+        Some(Cow::Borrowed(trimmed))
+    }
+}
+
+/// What `--match` and `--match-regex` look for. Matches never overlap: each
+/// search resumes after the end of the previous match, so `aa` occurs twice
+/// in `aaaaa`.
+#[derive(Clone, Debug)]
+enum Matcher {
+    /// A fixed substring. With `--ignore-case` it is stored lowercased and
+    /// matched against lowercased text, a character at a time, so a final
+    /// `Σ` is the same as any other.
+    Substring { needle: String, ignore_case: bool },
+    /// Non-empty matches of a regex, which may span lines.
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    fn substring(needle: &str, ignore_case: bool) -> Matcher {
+        let mut folded = String::new();
+        needle
+            .chars()
+            .for_each(|c| push_folded(&mut folded, c, ignore_case));
+        Matcher::Substring {
+            needle: folded,
+            ignore_case,
+        }
+    }
+
+    /// Counts the matches in the text `reader` yields. A substring is
+    /// searched as the text streams by, keeping only the end that a match
+    /// could still start in, so matches can span lines and buffers. A regex
+    /// is run over all of the text.
+    fn count<R: BufRead>(&self, mut reader: R) -> Result<usize, io::Error> {
+        match self {
+            Matcher::Substring {
+                needle,
+                ignore_case,
+            } => {
+                let mut count = 0;
+                let mut window = String::new();
+                let mut search = |window: &mut String| {
+                    let mut end = 0;
+                    for (start, found) in window.match_indices(needle.as_str()) {
+                        count += 1;
+                        end = start + found.len();
+                    }
+                    let mut keep = end.max(window.len().saturating_sub(needle.len() - 1));
+                    while !window.is_char_boundary(keep) {
+                        keep += 1;
+                    }
+                    window.drain(..keep);
+                };
+                // Searching once twice the needle has built up keeps the
+                // window small without going over the same text many times.
+                for_each_char(&mut reader, |c| {
+                    push_folded(&mut window, c, *ignore_case);
+                    if window.len() >= 2 * needle.len() {
+                        search(&mut window);
+                    }
+                })?;
+                search(&mut window);
+                Ok(count)
+            }
+            #[cfg(feature = "regex")]
+            Matcher::Regex(re) => {
+                let mut text = String::new();
+                reader.read_to_string(&mut text)?;
+                Ok(re.find_iter(&text).filter(|m| !m.is_empty()).count())
+            }
+        }
+    }
+}
+
+/// Compiles a user-supplied pattern. Every option that takes a pattern goes
+/// through here so `--ignore-case` behaves the same for all of them.
+#[cfg(feature = "regex")]
+fn build_regex(pattern: &str, ignore_case: bool) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+}
+
+/// Unit for measuring line length.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LengthUnit {
+    Chars,
+    Bytes,
+}
+
+impl LengthUnit {
+    fn measure(self, line: &str) -> usize {
+        match self {
+            LengthUnit::Chars => line.chars().count(),
+            LengthUnit::Bytes => line.len(),
+        }
+    }
+}
+
+/// Text encodings that can be told apart by [`Encoding::detect`], and that
+/// `--encoding` can decode.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Encoding {
+    Ascii,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    fn parse(name: &str) -> Option<Encoding> {
+        match name {
+            "utf8" => Some(Encoding::Utf8),
+            "utf16le" => Some(Encoding::Utf16Le),
+            "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    /// Decodes `bytes` as text in this encoding, dropping a leading
+    /// byte-order mark. For UTF-16 a BOM also decides the byte order, so a
+    /// file marked big-endian decodes correctly even with `utf16le`.
+    fn decode(self, bytes: Vec<u8>) -> Result<String, io::Error> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        match self {
+            Encoding::Ascii | Encoding::Utf8 => {
+                let bom = if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+                    3
+                } else {
+                    0
+                };
+                std::str::from_utf8(&bytes[bom..])
+                    .map(str::to_string)
+                    .map_err(|e| {
+                        invalid(Utf8Location::of(&bytes, bom + e.valid_up_to()).to_string())
+                    })
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let (big_endian, body) = match bytes.get(..2) {
+                    Some([0xFF, 0xFE]) => (false, &bytes[2..]),
+                    Some([0xFE, 0xFF]) => (true, &bytes[2..]),
+                    _ => (self == Encoding::Utf16Be, &bytes[..]),
+                };
+                if body.len() % 2 != 0 {
+                    return Err(invalid(
+                        "UTF-16 input has an odd number of bytes".to_string(),
+                    ));
+                }
+                let units = body.chunks_exact(2).map(|pair| {
+                    let pair = [pair[0], pair[1]];
+                    if big_endian {
+                        u16::from_be_bytes(pair)
+                    } else {
+                        u16::from_le_bytes(pair)
+                    }
+                });
+                char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .map_err(|e| {
+                        invalid(format!(
+                            "invalid UTF-16: unpaired surrogate {:#06x}",
+                            e.unpaired_surrogate()
+                        ))
+                    })
+            }
+            Encoding::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+        }
+    }
+
+    /// Best guess at the encoding of `bytes`. A byte-order mark wins when
+    /// present; otherwise pure 7-bit data is ASCII, valid UTF-8 is UTF-8, data
+    /// in which most even (or odd) bytes are NUL is UTF-16, and anything else
+    /// is assumed to be Latin-1. This is a heuristic: short inputs in
+    /// particular can be misdetected.
+    fn detect(bytes: &[u8]) -> Encoding {
+        if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            return Encoding::Utf8;
+        }
+        if bytes.starts_with(&[0xFF, 0xFE]) {
+            return Encoding::Utf16Le;
+        }
+        if bytes.starts_with(&[0xFE, 0xFF]) {
+            return Encoding::Utf16Be;
+        }
+        if bytes.len() >= 2 {
+            let pairs = bytes.len() / 2;
+            let nul_at = |parity: usize| {
+                bytes
+                    .chunks_exact(2)
+                    .filter(|pair| pair[parity] == 0)
+                    .count()
+            };
+            if nul_at(1) * 2 > pairs && nul_at(0) * 4 < pairs {
+                return Encoding::Utf16Le;
+            }
+            if nul_at(0) * 2 > pairs && nul_at(1) * 4 < pairs {
+                return Encoding::Utf16Be;
+            }
+        }
+        if bytes.is_ascii() {
+            Encoding::Ascii
+        } else if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Latin1
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Ascii => "ASCII",
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+        })
+    }
+}
+
+/// Content hash printed by `--with-hash`.
+#[cfg(feature = "hash")]
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum HashAlgo {
+    Sha256,
+    Xxh64,
+}
+
+#[cfg(feature = "hash")]
+impl HashAlgo {
+    fn parse(name: &str) -> Option<HashAlgo> {
+        match name {
+            "sha256" => Some(HashAlgo::Sha256),
+            "xxh64" => Some(HashAlgo::Xxh64),
+            _ => None,
+        }
+    }
+
+    /// Lowercase hex digest of `bytes`.
+    #[cfg(test)]
+    fn hex_digest(self, bytes: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(bytes);
+        hasher.finish()
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            HashAlgo::Sha256 => Hasher::Sha256(Default::default()),
+            HashAlgo::Xxh64 => Hasher::Xxh64(xxhash_rust::xxh64::Xxh64::new(0)),
+        }
+    }
+}
+
+/// A [`HashAlgo`] part way through its input, fed a chunk at a time.
+#[cfg(feature = "hash")]
+#[derive(Clone)]
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Xxh64(xxhash_rust::xxh64::Xxh64),
+}
+
+#[cfg(feature = "hash")]
+impl Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        use sha2::Digest;
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+            Hasher::Xxh64(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// Lowercase hex digest of everything passed to `update`.
+    fn finish(self) -> String {
+        use sha2::Digest;
+        match self {
+            Hasher::Sha256(hasher) => hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            Hasher::Xxh64(hasher) => format!("{:016x}", hasher.digest()),
+        }
+    }
+}
+
+/// Unicode normalization form applied by `--normalize`.
+#[cfg(feature = "normalize")]
 #[derive(Clone, Copy, PartialEq, Debug)]
-enum CountType {
-    ByteCount,
-    CharCount,
-    WordCount,
-    LineCount,
-    AllCount,
+enum Normalization {
+    Nfc,
+    Nfd,
+}
+
+#[cfg(feature = "normalize")]
+impl Normalization {
+    fn parse(name: &str) -> Option<Normalization> {
+        match name {
+            "nfc" => Some(Normalization::Nfc),
+            "nfd" => Some(Normalization::Nfd),
+            _ => None,
+        }
+    }
+
+    fn apply(self, text: &str) -> String {
+        use unicode_normalization::UnicodeNormalization;
+        match self {
+            Normalization::Nfc => text.nfc().collect(),
+            Normalization::Nfd => text.nfd().collect(),
+        }
+    }
+}
+
+/// Everything that can go wrong parsing a command line or counting.
+#[derive(Debug)]
+pub enum CcwcError {
+    /// An option that isn't recognised.
+    InvalidFlag(String),
+    /// Options or option values that can't be used as given.
+    Usage(&'static str),
+    /// Reading an input or writing the results failed.
+    Io(io::Error),
+    /// Reading failed partway; the counts up to that point were printed.
+    Partial(io::Error),
+    /// The input was counted, but a check requested on it failed, such as
+    /// `--bracket-balance` or `--expect-lines-between`.
+    Check(String),
+    /// Some of several inputs could not be counted. Each was reported as it
+    /// failed.
+    FilesFailed { failed: usize, total: usize },
+    /// Counting the named input failed.
+    File { path: String, error: Box<CcwcError> },
+    /// Characters were asked for, but the named input looks like binary
+    /// data. The message leaves the path out, as it is printed in front.
+    NotText(String),
+    /// The mode has to hold the whole input in memory, and it is bigger
+    /// than the `--max-memory` budget of `limit` bytes.
+    TooLarge { limit: u64 },
+}
+
+impl CcwcError {
+    /// The status the `gfwc` binary exits with for this error: 2 when the
+    /// command line itself is wrong, so nothing was counted, and 1 when an
+    /// input couldn't be counted or a check on it failed.
+    ///
+    /// ```
+    /// use gfwc::CcwcError;
+    ///
+    /// assert_eq!(CcwcError::InvalidFlag("-z".to_string()).exit_code(), 2);
+    /// assert_eq!(CcwcError::FilesFailed { failed: 1, total: 2 }.exit_code(), 1);
+    /// ```
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CcwcError::InvalidFlag(_) | CcwcError::Usage(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for CcwcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CcwcError::InvalidFlag(flag) => write!(
+                f,
+                "Invalid flag {}. Use 'c' for byte count, 'l' for line count, 'w' for word count, or 'm' for character count.",
+                flag
+            ),
+            CcwcError::Usage(message) => f.write_str(message),
+            CcwcError::Io(error) => write_io_error(f, error),
+            CcwcError::Partial(error) => write!(f, "{} (counts above are partial)", error),
+            CcwcError::Check(message) => f.write_str(message),
+            CcwcError::FilesFailed { failed, total } => {
+                write!(f, "{} of {} files could not be counted", failed, total)
+            }
+            CcwcError::File { path, error } => write!(f, "{}: {}", path, error),
+            CcwcError::NotText(_) => {
+                f.write_str("binary data has no characters to count; use -c to count its bytes")
+            }
+            CcwcError::TooLarge { limit } => write!(
+                f,
+                "input is larger than --max-memory {} and this count has to hold all of it",
+                limit
+            ),
+        }
+    }
 }
 
-#[derive(Clone)]
-pub struct Config {
-    count_type: CountType,
-    file_path: Option<String>,
-}
+impl Error for CcwcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CcwcError::Io(error) | CcwcError::Partial(error) => Some(error),
+            CcwcError::File { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Writes an I/O error the way `wc` does: an OS error is described without
+/// Rust's ` (os error N)` suffix, e.g. `No such file or directory`.
+fn write_io_error(f: &mut fmt::Formatter, error: &io::Error) -> fmt::Result {
+    let message = error.to_string();
+    match error.raw_os_error() {
+        Some(code) => f.write_str(
+            message
+                .strip_suffix(&format!(" (os error {})", code))
+                .unwrap_or(&message),
+        ),
+        None => f.write_str(&message),
+    }
+}
+
+impl From<io::Error> for CcwcError {
+    /// Unwraps a [`CcwcError`] that had to travel through an I/O path, such
+    /// as [`CcwcError::TooLarge`] from a read.
+    fn from(error: io::Error) -> Self {
+        match error.downcast::<CcwcError>() {
+            Ok(error) => error,
+            Err(error) => CcwcError::Io(error),
+        }
+    }
+}
+
+impl From<&'static str> for CcwcError {
+    fn from(message: &'static str) -> Self {
+        CcwcError::Usage(message)
+    }
+}
+
+/// Bytes read at a time while streaming, unless `--read-chunk-size` says
+/// otherwise.
+const DEFAULT_READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Files whose reported size is a multiple of this, including 0, may hold
+/// more than that: `/proc` and `/sys` files report 0 or a page. As GNU `wc`
+/// does, the bytes past the reported size are read rather than trusted.
+const PAGE_SIZE: u64 = 4096;
+
+/// A file argument that means standard input. It is also the label printed
+/// for that input.
+const STDIN_PATH: &str = "-";
+
+/// The name to use in messages: the basename of `args[0]`, so that the
+/// binary reports itself correctly when installed or symlinked as e.g. `wc`.
+/// Falls back to `gfwc` when `args[0]` is missing or empty.
+pub fn program_name(args: &[String]) -> String {
+    basename(args.first().map(String::as_str))
+}
+
+fn basename(arg0: Option<&str>) -> String {
+    arg0.and_then(|arg0| std::path::Path::new(arg0).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "gfwc".to_string())
+}
+
+/// The environment variable whose options are parsed ahead of the command
+/// line's.
+pub const DEFAULT_FLAGS_VAR: &str = "GFWC_DEFAULT_FLAGS";
+
+const DEFAULT_FLAGS_FILE: &str = "GFWC_DEFAULT_FLAGS can only hold options, not files.";
+
+const MAX_LINE_LENGTH_ALONE: &str = "-L can't be combined with -l, -w, -c or -m.";
+
+/// The options from `GFWC_DEFAULT_FLAGS` followed by those of the command
+/// line, noting which of the two the last one came from.
+struct LayeredArgs<D, A> {
+    defaults: D,
+    args: A,
+    from_defaults: bool,
+}
+
+impl<D, A> Iterator for LayeredArgs<D, A>
+where
+    D: Iterator<Item = String>,
+    A: Iterator<Item = String>,
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if let Some(arg) = self.defaults.next() {
+            self.from_defaults = true;
+            return Some(arg);
+        }
+        self.from_defaults = false;
+        self.args.next()
+    }
+}
+
+/// The crate version, as printed by `--version`.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The text printed by `--help`. Callers substitute `<program>`.
+pub const USAGE: &str = "\
+Usage: <program> [count] [OPTION]... [--] [FILE]...
+
+Print line, word and byte counts for each FILE, and a total line if more
+than one FILE is given. With no FILE, or when FILE is -, read standard input;
+- may be given once, among other FILEs.
+Every argument after -- is a FILE, even one that starts with a dash. An
+argument @LIST before -- names a file of FILEs, one per line; blank lines and
+lines starting with # are skipped.
+
+Counts (short flags combine, e.g. -lw; the default is -lwc):
+  -c, --bytes            print the byte count
+  -m, --chars            print the character count
+  -l, --lines            print the newline count
+  -w, --words            print the word count
+      --all              print every one of the counts above, as -lwmc
+  -L, --max-line-length  print the display width of the longest line; it
+                         can't be combined with the counts above
+      --tab-width N      columns between tab stops for -L, --line-stats and
+                         --indent-changes (default 8)
+
+Other modes:
+  --bytes-per-char, --max-blank-run[=N], --bracket-balance, --by-category,
+  --no-trailing-blank-lines, --split-at MARKER, --compression-ratio,
+  --tab-after-space, --plane N, --trailing-nulls,
+  --distinct-lengths[=chars|bytes], --trim-savings,
+  --indent-changes[=include-blank], --all-upper, --all-lower,
+  --encoding-report, --first-token-freq, --freq, --shortest-line,
+  --paragraphs, --vocab-coverage FILE, --valid-json-lines, --non-empty-lines,
+  --blank-lines, --line-stats, --sentences, --line-endings
+
+  --unique-lines         print the number of different lines
+  --duplicate-lines      print the number of different lines that occur more
+                         than once; both keep every different line in memory
+
+  --graphemes            print the number of user-perceived characters, so
+                         that e + a combining accent is 1 where -m says 2
+
+  --record-size N        print the number of complete N-byte records, then
+                         the bytes left over in a partial last record
+
+  --diff FILE1 FILE2     print how FILE2's lines, words, bytes and chars differ
+                         from FILE1's, as in Δlines:+3 Δwords:-1
+  --merge FILE...        print the total of the rows of earlier --csv outputs,
+                         which must all have the same columns
+  --match STR            count the occurrences of STR; matches don't overlap
+  --match-regex PATTERN  count the non-empty matches of PATTERN
+
+Modifiers:
+  -r, --recursive        count the regular files under each directory
+  --follow-symlinks      with -r, also descend into symlinked directories,
+                         each at most once
+  --max-depth N          with -r, descend at most N directories below each
+                         one named; 0 counts only the files directly inside
+  --files0-from=FILE     count the NUL-separated paths in FILE (- for stdin)
+  --total=WHEN           print a total line: auto, always, never or only
+  --color=WHEN           bold the total line: auto (when stdout is a terminal
+                         and NO_COLOR is unset), always or never
+  --summary              print only the number of inputs and their labelled
+                         totals, as in files:3 lines:40 words:200 bytes:900
+  --sort=KEY             print the inputs ordered by name, size or lines,
+                         smallest first, rather than in the order given
+  -0, --null             end each output record with NUL, not newline
+  --decompress           gunzip the input first (implied for *.gz files)
+  --read-chunk-size N    read N bytes at a time while streaming (default 65536)
+  --max-memory N         hold at most N bytes of input in memory, failing a
+                         count that needs all of a larger input
+  --head-bytes N         count only the first N bytes of each FILE
+  --head-lines N         count only the first N lines of each FILE
+  --no-tty-stdin         fail rather than wait when stdin is a terminal
+  --check                only check that every FILE can be read: report the
+                         ones that can't and print no counts
+  --progress             show the bytes read so far on stderr, if it is a
+                         terminal
+  --encoding NAME        decode the input as utf8, utf16le, utf16be or latin1;
+                         byte counts stay those of the raw input
+  --separator STR        put STR between the columns, unpadded
+  --bare                 print only the numbers, one space apart, without
+                         padding, FILE names or the word total
+  --no-align             print each FILE's counts unpadded as soon as they are
+                         ready, in the order the FILEs finish; without it the
+                         FILEs print in order, waiting on the slower ones
+  --labels               name each count, as in lines:3 words:10, unpadded
+  --format=FORMAT        lay out plain counts as text, csv (also --csv) or
+                         keyval (name=value lines, blank line between inputs)
+  --csv, --si, --human-readable, --tee, --base64-decode, --partial-on-error,
+  --odd-lines, --even-lines, --line-numbers-from FILE, --alert-lines N,
+  --top N, --freq-top N, --min-count N,
+  --expect-lines-between MIN:MAX, --expect-words-between MIN:MAX,
+  --expect-bytes-between MIN:MAX, --fail-on-empty[=bytes|lines],
+  --column-range START:END,
+  --token-regex PATTERN, --words-unicode, --ignore-case,
+  --with-hash (or --hash), --hash-algo NAME, --normalize FORM,
+  --normalize-words, --invalid-line-numbers
+
+  -h, --help             print this help and exit
+  -V, --version          print the version and exit
+
+Options in the GFWC_DEFAULT_FLAGS environment variable, e.g. \"-l -w -m\",
+are read before the command line's; count flags given on the command line
+replace the ones it selects.
+
+Exit status is 0 on success, 1 if an input couldn't be counted or a check on
+it failed, and 2 if the options are invalid.
+
+Some modes and modifiers need the matching cargo feature.
+";
+
+/// What a command line asks for.
+///
+/// Informational flags like `--help` and `--version` are honoured wherever
+/// they appear before any `--` and win over everything else on the line, so
+/// no input is opened for them. The first one given is the one acted on.
+pub enum Command {
+    /// Count the inputs described by the `Config`.
+    Count(Box<Config>),
+    /// Print `USAGE` and exit.
+    Help,
+    /// Print the version and exit.
+    Version,
+}
+
+impl Command {
+    /// Parses a full command line, `args[0]` being the program name.
+    pub fn parse(args: &[String]) -> Result<Command, CcwcError> {
+        let options = args.get(1..).unwrap_or_default();
+        for arg in options {
+            match arg.as_str() {
+                "--help" | "-h" => return Ok(Command::Help),
+                "--version" | "-V" => return Ok(Command::Version),
+                "--" => break,
+                _ => {}
+            }
+        }
+        Config::build(args).map(|config| Command::Count(Box::new(config)))
+    }
+}
+
+/// Settings that change how each input is read and counted, rather than
+/// what is printed. The defaults count as `wc` does; build one once and
+/// hand it to [`ConfigBuilder::options`] or [`Counter::with_options`] to
+/// reuse it across inputs.
+///
+/// ```
+/// use gfwc::{CountOptions, CountType, Counter};
+///
+/// let options = CountOptions::default().head_lines(1).tab_width(4);
+/// let counter = Counter::from_reader(&b"\tab\nmore\n"[..], CountType::MaxLineLength)
+///     .with_options(options);
+/// let mut out = Vec::new();
+/// counter.count_into(&mut out).unwrap();
+/// assert_eq!(out, b"6\n");
+/// ```
+#[derive(Clone, Debug)]
+pub struct CountOptions {
+    column_range: Option<ColumnRange>,
+    tokenizer: Tokenizer,
+    ignore_case: bool,
+    head: Option<Head>,
+    encoding: Option<Encoding>,
+    tab_width: Option<usize>,
+    read_chunk_size: Option<usize>,
+    max_memory: Option<u64>,
+    #[cfg(feature = "normalize")]
+    normalization: Option<Normalization>,
+}
+
+impl Default for CountOptions {
+    fn default() -> Self {
+        CountOptions {
+            column_range: None,
+            tokenizer: Tokenizer::Whitespace,
+            ignore_case: false,
+            head: None,
+            encoding: None,
+            tab_width: None,
+            read_chunk_size: None,
+            max_memory: None,
+            #[cfg(feature = "normalize")]
+            normalization: None,
+        }
+    }
+}
+
+impl CountOptions {
+    /// Columns a tab advances to the next multiple of, for
+    /// [`CountType::MaxLineLength`] and [`CountType::LineStats`]. Defaults to
+    /// 8.
+    ///
+    /// # Panics
+    ///
+    /// If `width` is 0.
+    pub fn tab_width(mut self, width: usize) -> Self {
+        assert!(width > 0, "tab width must be positive");
+        self.tab_width = Some(width);
+        self
+    }
+
+    /// Counts only the first `n` bytes of each input.
+    pub fn head_bytes(mut self, n: u64) -> Self {
+        self.head = Some(Head::Bytes(n));
+        self
+    }
+
+    /// Counts only the first `n` lines of each input.
+    pub fn head_lines(mut self, n: usize) -> Self {
+        self.head = Some(Head::Lines(n));
+        self
+    }
+
+    /// How many bytes each read asks for while streaming. Defaults to 65536.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is 0.
+    pub fn read_chunk_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "read chunk size must be positive");
+        self.read_chunk_size = Some(size);
+        self
+    }
+
+    /// Most bytes of input held in memory at once. Streamed counts read in
+    /// chunks no bigger than this; counts that need the whole input fail with
+    /// [`CcwcError::TooLarge`] rather than read more. Unlimited by default.
+    ///
+    /// # Panics
+    ///
+    /// If `bytes` is 0.
+    pub fn max_memory(mut self, bytes: u64) -> Self {
+        assert!(bytes > 0, "memory budget must be positive");
+        self.max_memory = Some(bytes);
+        self
+    }
+
+    /// Whether words differing only in case are the same, where words are
+    /// compared.
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    fn tab_stop(&self) -> usize {
+        self.tab_width.unwrap_or(8)
+    }
+
+    fn chunk_size(&self) -> usize {
+        let chunk = self.read_chunk_size.unwrap_or(DEFAULT_READ_CHUNK_SIZE);
+        match self.max_memory {
+            Some(limit) => chunk.min(usize::try_from(limit).unwrap_or(usize::MAX)),
+            None => chunk,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Config {
+    count_type: CountType,
+    options: CountOptions,
+    file_paths: Vec<String>,
+    program: String,
+    byte_units: ByteUnits,
+    output_format: OutputFormat,
+    separator: Option<String>,
+    labels: bool,
+    total: TotalMode,
+    color: ColorMode,
+    null_terminated: bool,
+    tee: bool,
+    base64_decode: bool,
+    alert_lines: Option<usize>,
+    progress: bool,
+    no_tty_stdin: bool,
+    check: bool,
+    sort: Option<SortOrder>,
+    no_align: bool,
+    bare: bool,
+    diff: bool,
+    merge: bool,
+    split_marker: Option<String>,
+    matcher: Option<Matcher>,
+    vocab_path: Option<String>,
+    partial_on_error: bool,
+    recursive: bool,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    files0_from: Option<String>,
+    line_numbers_from: Option<String>,
+    line_parity: Option<LineParity>,
+    top: Option<usize>,
+    freq_top: Option<usize>,
+    min_count: Option<usize>,
+    expectations: Expectations,
+    #[cfg(feature = "hash")]
+    hash_algo: Option<HashAlgo>,
+    #[cfg(feature = "json")]
+    show_invalid_lines: bool,
+    #[cfg(feature = "gzip")]
+    decompress: bool,
+}
+
+impl Config {
+    /// Parses a full command line, `args[0]` being the program name.
+    ///
+    /// If the first argument names a subcommand, the rest of the line is
+    /// handed to that subcommand's parser. Otherwise the whole line is parsed
+    /// as `count` options, so `gfwc file.txt` behaves exactly like
+    /// `gfwc count file.txt`. A file that happens to be called `count` must
+    /// be given with a path prefix, e.g. `./count`.
+    ///
+    /// Options in the `GFWC_DEFAULT_FLAGS` environment variable are parsed
+    /// first, as if they began the command line, and count flags on the
+    /// command line replace the ones it selects.
+    pub fn build(args: &[String]) -> Result<Config, CcwcError> {
+        Self::build_from(args.iter().cloned())
+    }
+
+    /// Like [`Config::build`], but takes the command line as any sequence of
+    /// strings, so `env::args()` can be passed straight in.
+    ///
+    /// ```
+    /// use gfwc::Config;
+    ///
+    /// let args = ["gfwc", "-l", "notes.txt"].map(String::from);
+    /// assert!(Config::build_from(args).is_ok());
+    /// ```
+    pub fn build_from<I: IntoIterator<Item = String>>(args: I) -> Result<Config, CcwcError> {
+        let mut args = args.into_iter().peekable();
+        let program = basename(args.next().as_deref());
+        if args.peek().is_some_and(|arg| arg == "count") {
+            args.next();
+        }
+        let defaults = env::var(DEFAULT_FLAGS_VAR).unwrap_or_default();
+        let mut config = Self::build_count(&defaults, args)?;
+        config.program = program;
+        Ok(config)
+    }
+
+    /// Starts a `Config` for library use, without going through argv. It
+    /// begins as the bare `gfwc` invocation: the default counts of stdin.
+    pub fn builder() -> ConfigBuilder {
+        let mut config =
+            Self::build_count("", std::iter::empty()).expect("no options is a valid command line");
+        config.program = "gfwc".to_string();
+        ConfigBuilder { config }
+    }
+
+    /// Parses the options of the `count` subcommand (the default), after the
+    /// whitespace-separated options in `defaults`.
+    fn build_count(
+        defaults: &str,
+        args: impl Iterator<Item = String>,
+    ) -> Result<Config, CcwcError> {
+        let mut args = LayeredArgs {
+            defaults: defaults.split_whitespace().map(String::from),
+            args,
+            from_defaults: false,
+        };
+        let mut config = Config {
+            count_type: CountType::Plain(Columns::DEFAULT),
+            options: CountOptions::default(),
+            file_paths: Vec::new(),
+            program: String::new(),
+            byte_units: ByteUnits::Exact,
+            output_format: OutputFormat::Text,
+            separator: None,
+            labels: false,
+            total: TotalMode::Auto,
+            color: ColorMode::Auto,
+            null_terminated: false,
+            tee: false,
+            base64_decode: false,
+            alert_lines: None,
+            progress: false,
+            no_tty_stdin: false,
+            check: false,
+            sort: None,
+            no_align: false,
+            bare: false,
+            diff: false,
+            merge: false,
+            split_marker: None,
+            matcher: None,
+            vocab_path: None,
+            partial_on_error: false,
+            recursive: false,
+            follow_symlinks: false,
+            max_depth: None,
+            files0_from: None,
+            line_numbers_from: None,
+            line_parity: None,
+            top: None,
+            freq_top: None,
+            min_count: None,
+            expectations: Expectations::default(),
+            #[cfg(feature = "hash")]
+            hash_algo: None,
+            #[cfg(feature = "json")]
+            show_invalid_lines: false,
+            #[cfg(feature = "gzip")]
+            decompress: false,
+        };
+
+        // Patterns are compiled once all flags are seen, so that modifiers
+        // like --ignore-case apply regardless of their position.
+        #[cfg(feature = "regex")]
+        let mut token_pattern: Option<String> = None;
+        let mut match_substring: Option<String> = None;
+        #[cfg(feature = "regex")]
+        let mut match_pattern: Option<String> = None;
+        // Plain count flags accumulate, so `-l -w` selects both.
+        let mut columns = Columns::NONE;
+        let mut columns_from_command_line = false;
+        // Whether -L and plain counts were asked for, by the command line
+        // and by the defaults.
+        let mut max_line_length_given = [false; 2];
+        let mut plain_given = [false; 2];
+        let mut normalize_words = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--si" | "--human-readable" => {
+                    let units = match arg.as_str() {
+                        "--si" => ByteUnits::Si,
+                        _ => ByteUnits::Iec,
+                    };
+                    if config.byte_units != ByteUnits::Exact && config.byte_units != units {
+                        return Err("--si can't be combined with --human-readable.".into());
+                    }
+                    config.byte_units = units;
+                }
+                "--csv" => config.output_format = OutputFormat::Csv,
+                "--labels" => config.labels = true,
+                "--summary" => config.total = TotalMode::Summary,
+                "--separator" => {
+                    let separator = args.next().ok_or("--separator requires a string")?;
+                    if separator.is_empty() {
+                        return Err("--separator must not be empty.".into());
+                    }
+                    config.separator = Some(separator);
+                }
+                "--tee" => config.tee = true,
+                "--progress" => config.progress = true,
+                "--no-tty-stdin" => config.no_tty_stdin = true,
+                "--check" => config.check = true,
+                "--no-align" => config.no_align = true,
+                "--bare" => config.bare = true,
+                "--diff" => config.diff = true,
+                "--merge" => config.merge = true,
+                "-0" | "--null" => config.null_terminated = true,
+                "--base64-decode" => config.base64_decode = true,
+                "--partial-on-error" => config.partial_on_error = true,
+                "-r" | "--recursive" => config.recursive = true,
+                "--follow-symlinks" => config.follow_symlinks = true,
+                "--max-depth" => {
+                    let n = args.next().ok_or("--max-depth requires a depth")?;
+                    match n.parse() {
+                        Ok(n) => config.max_depth = Some(n),
+                        _ => return Err("--max-depth must be a non-negative integer.".into()),
+                    }
+                }
+                "--files0-from" => {
+                    let list = args.next().ok_or("--files0-from requires a file")?;
+                    config.files0_from = Some(list);
+                }
+                flag if flag.starts_with("--total=") => {
+                    config.total = TotalMode::parse(&flag["--total=".len()..])
+                        .ok_or("Unknown --total. Use auto, always, never or only.")?;
+                }
+                flag if flag.starts_with("--color=") => {
+                    config.color = ColorMode::parse(&flag["--color=".len()..])
+                        .ok_or("Unknown --color. Use auto, always or never.")?;
+                }
+                flag if flag.starts_with("--sort=") => {
+                    config.sort = Some(
+                        SortOrder::parse(&flag["--sort=".len()..])
+                            .ok_or("Unknown --sort. Use name, size or lines.")?,
+                    );
+                }
+                flag if flag.starts_with("--format=") => {
+                    config.output_format = OutputFormat::parse(&flag["--format=".len()..])
+                        .ok_or("Unknown --format. Use text, csv or keyval.")?;
+                }
+                flag if flag.starts_with("--files0-from=") => {
+                    config.files0_from = Some(flag["--files0-from=".len()..].to_string());
+                }
+                "--encoding" => {
+                    let name = args.next().ok_or("--encoding requires an encoding")?;
+                    config.options.encoding = Some(
+                        Encoding::parse(&name)
+                            .ok_or("Unknown --encoding. Use utf8, utf16le, utf16be or latin1.")?,
+                    );
+                }
+                "--odd-lines" => config.line_parity = Some(LineParity::Odd),
+                "--even-lines" => config.line_parity = Some(LineParity::Even),
+                "--line-numbers-from" => {
+                    let path = args.next().ok_or("--line-numbers-from requires a file")?;
+                    config.line_numbers_from = Some(path);
+                }
+                "--split-at" => {
+                    let marker = args.next().ok_or("--split-at requires a marker")?;
+                    let marker = unescape_marker(&marker);
+                    if marker.is_empty() {
+                        return Err("--split-at marker must not be empty.".into());
+                    }
+                    config.count_type = CountType::SplitAt;
+                    config.split_marker = Some(marker);
+                }
+                "--record-size" => {
+                    let size = args
+                        .next()
+                        .ok_or("--record-size requires a size in bytes")?;
+                    match size.parse() {
+                        Ok(size) if size > 0 => config.count_type = CountType::Records(size),
+                        _ => return Err("--record-size must be a positive integer.".into()),
+                    }
+                }
+                "--plane" => {
+                    let plane = args.next().ok_or("--plane requires a plane number")?;
+                    match plane.parse() {
+                        Ok(plane) if plane <= 16 => config.count_type = CountType::Plane(plane),
+                        _ => return Err("--plane must be an integer from 0 to 16.".into()),
+                    }
+                }
+                "--head-bytes" => {
+                    let n = args.next().ok_or("--head-bytes requires a byte count")?;
+                    match n.parse() {
+                        Ok(n) if n > 0 => config.options.head = Some(Head::Bytes(n)),
+                        _ => return Err("--head-bytes must be a positive integer.".into()),
+                    }
+                }
+                "--head-lines" => {
+                    let n = args.next().ok_or("--head-lines requires a line count")?;
+                    match n.parse() {
+                        Ok(n) if n > 0 => config.options.head = Some(Head::Lines(n)),
+                        _ => return Err("--head-lines must be a positive integer.".into()),
+                    }
+                }
+                "--alert-lines" => {
+                    let every = args.next().ok_or("--alert-lines requires a line count")?;
+                    match every.parse() {
+                        Ok(every) if every > 0 => config.alert_lines = Some(every),
+                        _ => return Err("--alert-lines must be a positive integer.".into()),
+                    }
+                }
+                "--top" => {
+                    let n = args.next().ok_or("--top requires a count")?;
+                    match n.parse() {
+                        Ok(n) if n > 0 => config.top = Some(n),
+                        _ => return Err("--top must be a positive integer.".into()),
+                    }
+                }
+                "--tab-width" => {
+                    let n = args.next().ok_or("--tab-width requires a width")?;
+                    match n.parse() {
+                        Ok(n) if n > 0 => config.options.tab_width = Some(n),
+                        _ => return Err("--tab-width must be a positive integer.".into()),
+                    }
+                }
+                "--read-chunk-size" => {
+                    let n = args
+                        .next()
+                        .ok_or("--read-chunk-size requires a size in bytes")?;
+                    match n.parse() {
+                        Ok(n) if n > 0 => config.options.read_chunk_size = Some(n),
+                        _ => return Err("--read-chunk-size must be a positive integer.".into()),
+                    }
+                }
+                "--max-memory" => {
+                    let n = args.next().ok_or("--max-memory requires a size in bytes")?;
+                    match n.parse() {
+                        Ok(n) if n > 0 => config.options.max_memory = Some(n),
+                        _ => return Err("--max-memory must be a positive integer.".into()),
+                    }
+                }
+                "--freq-top" => {
+                    let n = args.next().ok_or("--freq-top requires a count")?;
+                    match n.parse() {
+                        Ok(n) if n > 0 => config.freq_top = Some(n),
+                        _ => return Err("--freq-top must be a positive integer.".into()),
+                    }
+                }
+                "--min-count" => {
+                    let n = args.next().ok_or("--min-count requires a count")?;
+                    match n.parse() {
+                        Ok(n) if n > 0 => config.min_count = Some(n),
+                        _ => return Err("--min-count must be a positive integer.".into()),
+                    }
+                }
+                "--expect-lines-between" | "--expect-words-between" | "--expect-bytes-between" => {
+                    let bounds = args
+                        .next()
+                        .and_then(|spec| CountBounds::parse(&spec))
+                        .ok_or("--expect-*-between requires MIN:MAX, MIN: or :MAX.")?;
+                    match arg.as_str() {
+                        "--expect-lines-between" => config.expectations.lines = Some(bounds),
+                        "--expect-words-between" => config.expectations.words = Some(bounds),
+                        _ => config.expectations.bytes = Some(bounds),
+                    }
+                }
+                // Shorthand for a lower bound of 1 on that count.
+                "--fail-on-empty" | "--fail-on-empty=bytes" | "--fail-on-empty=lines" => {
+                    let bounds = match arg.as_str() {
+                        "--fail-on-empty=lines" => &mut config.expectations.lines,
+                        _ => &mut config.expectations.bytes,
+                    }
+                    .get_or_insert(CountBounds {
+                        min: None,
+                        max: None,
+                    });
+                    bounds.min = Some(bounds.min.unwrap_or(0).max(1));
+                }
+                "--column-range" => {
+                    let spec = args.next().ok_or("--column-range requires START:END")?;
+                    config.options.column_range =
+                        Some(ColumnRange::parse(&spec).ok_or(
+                            "Invalid --column-range. Use START:END with 1 <= START <= END.",
+                        )?);
+                }
+                "--match" => {
+                    let needle = args.next().ok_or("--match requires a substring")?;
+                    if needle.is_empty() {
+                        return Err("--match substring must not be empty.".into());
+                    }
+                    config.count_type = CountType::Matches;
+                    match_substring = Some(needle);
+                }
+                #[cfg(feature = "regex")]
+                "--match-regex" => {
+                    config.count_type = CountType::Matches;
+                    match_pattern = Some(args.next().ok_or("--match-regex requires a pattern")?);
+                }
+                #[cfg(not(feature = "regex"))]
+                "--match-regex" => {
+                    return Err("Pattern options require building with the `regex` feature.".into());
+                }
+                #[cfg(feature = "regex")]
+                "--token-regex" => {
+                    token_pattern = Some(args.next().ok_or("--token-regex requires a pattern")?);
+                }
+                #[cfg(not(feature = "regex"))]
+                "--token-regex" => {
+                    return Err("Pattern options require building with the `regex` feature.".into());
+                }
+                #[cfg(feature = "segmentation")]
+                "--words-unicode" => config.options.tokenizer = Tokenizer::UnicodeWords,
+                #[cfg(not(feature = "segmentation"))]
+                "--words-unicode" => {
+                    return Err(
+                        "--words-unicode requires building with the `segmentation` feature.".into(),
+                    );
+                }
+                "--ignore-case" => config.options.ignore_case = true,
+                "--normalize-words" => normalize_words = true,
+                "--vocab-coverage" => {
+                    let path = args.next().ok_or("--vocab-coverage requires a word list")?;
+                    config.count_type = CountType::VocabCoverage;
+                    config.vocab_path = Some(path);
+                }
+                #[cfg(feature = "hash")]
+                "--with-hash" | "--hash" => {
+                    config.hash_algo.get_or_insert(HashAlgo::Sha256);
+                }
+                #[cfg(feature = "hash")]
+                "--hash-algo" => {
+                    let name = args.next().ok_or("--hash-algo requires an algorithm")?;
+                    config.hash_algo = Some(
+                        HashAlgo::parse(&name)
+                            .ok_or("Unknown --hash-algo. Use sha256 or xxh64.")?,
+                    );
+                }
+                #[cfg(not(feature = "hash"))]
+                "--with-hash" | "--hash" | "--hash-algo" => {
+                    return Err("Hashing requires building with the `hash` feature.".into());
+                }
+                #[cfg(feature = "normalize")]
+                "--normalize" => {
+                    let form = args.next().ok_or("--normalize requires nfc or nfd")?;
+                    config.options.normalization = Some(
+                        Normalization::parse(&form)
+                            .ok_or("Unknown --normalize form. Use nfc or nfd.")?,
+                    );
+                }
+                #[cfg(not(feature = "normalize"))]
+                "--normalize" => {
+                    return Err(
+                        "--normalize requires building with the `normalize` feature.".into(),
+                    );
+                }
+                #[cfg(feature = "json")]
+                "--invalid-line-numbers" => config.show_invalid_lines = true,
+                #[cfg(feature = "gzip")]
+                "--decompress" => config.decompress = true,
+                #[cfg(not(feature = "gzip"))]
+                "--decompress" => {
+                    return Err("--decompress requires building with the `gzip` feature.".into());
+                }
+                "--compression-ratio" if cfg!(not(feature = "gzip")) => {
+                    return Err(
+                        "--compression-ratio requires building with the `gzip` feature.".into(),
+                    );
+                }
+                "--by-category" if cfg!(not(feature = "categories")) => {
+                    return Err(
+                        "--by-category requires building with the `categories` feature.".into(),
+                    );
+                }
+                "--graphemes" if cfg!(not(feature = "segmentation")) => {
+                    return Err(
+                        "--graphemes requires building with the `segmentation` feature.".into(),
+                    );
+                }
+                "--valid-json-lines" if cfg!(not(feature = "json")) => {
+                    return Err("JSON validation requires building with the `json` feature.".into());
+                }
+                _ if args.from_defaults && (arg == STDIN_PATH || !arg.starts_with('-')) => {
+                    return Err(DEFAULT_FLAGS_FILE.into());
+                }
+                "--" if args.from_defaults => return Err(DEFAULT_FLAGS_FILE.into()),
+                STDIN_PATH => config.file_paths.push(arg),
+                // Everything after `--` is a file, even if it looks like a flag.
+                "--" => {
+                    config.file_paths.extend(&mut args);
+                    break;
+                }
+                flag if flag.starts_with('-') => {
+                    let Some(count_type) = Self::_parse_type(flag) else {
+                        // `-lL` is -L fused with plain counts, not a typo.
+                        let fused = flag.strip_prefix('-').filter(|letters| {
+                            letters.contains('L')
+                                && Columns::from_flags(&letters.replace('L', "")).is_some()
+                        });
+                        if fused.is_some() {
+                            return Err(MAX_LINE_LENGTH_ALONE.into());
+                        }
+                        return Err(CcwcError::InvalidFlag(flag.to_string()));
+                    };
+                    // -L is one number per line, with no room for the plain
+                    // columns, so asking for both is an error rather than
+                    // one silently replacing the other.
+                    let source = usize::from(args.from_defaults);
+                    max_line_length_given[source] |= count_type == CountType::MaxLineLength;
+                    plain_given[source] |= matches!(count_type, CountType::Plain(_));
+                    if max_line_length_given[source] && plain_given[source] {
+                        return Err(MAX_LINE_LENGTH_ALONE.into());
+                    }
+                    config.count_type = match count_type {
+                        CountType::Plain(selected) => {
+                            // Counts on the command line replace the defaults'.
+                            if !args.from_defaults && !columns_from_command_line {
+                                columns_from_command_line = true;
+                                columns = Columns::NONE;
+                            }
+                            columns = columns.union(selected);
+                            CountType::Plain(columns)
+                        }
+                        count_type => count_type,
+                    };
+                }
+                // `@FILE` lists more FILEs, one per line. After `--` it is
+                // just a file name.
+                argfile if argfile.len() > 1 && argfile.starts_with('@') => {
+                    let list = &argfile[1..];
+                    let paths = read_argfile(list).map_err(|error| CcwcError::File {
+                        path: list.to_string(),
+                        error: Box::new(error.into()),
+                    })?;
+                    config.file_paths.extend(paths);
+                }
+                path => config.file_paths.push(path.to_string()),
+            }
+        }
+
+        #[cfg(feature = "regex")]
+        if let Some(pattern) = token_pattern {
+            if !matches!(config.options.tokenizer, Tokenizer::Whitespace) {
+                return Err("--token-regex can't be combined with --words-unicode.".into());
+            }
+            let re = build_regex(&pattern, config.options.ignore_case)
+                .map_err(|_| "Invalid --token-regex pattern.")?;
+            config.options.tokenizer = Tokenizer::Regex(re);
+        }
+
+        if let Some(needle) = match_substring {
+            config.matcher = Some(Matcher::substring(&needle, config.options.ignore_case));
+        }
+        #[cfg(feature = "regex")]
+        if let Some(pattern) = match_pattern {
+            if config.matcher.is_some() {
+                return Err("--match can't be combined with --match-regex.".into());
+            }
+            let re = build_regex(&pattern, config.options.ignore_case)
+                .map_err(|_| "Invalid --match-regex pattern.")?;
+            config.matcher = Some(Matcher::Regex(re));
+        }
+
+        if normalize_words {
+            if !matches!(config.options.tokenizer, Tokenizer::Whitespace) {
+                return Err(
+                    "--normalize-words can't be combined with --token-regex or --words-unicode."
+                        .into(),
+                );
+            }
+            config.options.tokenizer = Tokenizer::Normalized;
+        }
+
+        if config.partial_on_error
+            && (!matches!(config.count_type, CountType::Plain(_))
+                || config.options.column_range.is_some()
+                || config.line_numbers_from.is_some()
+                || config.line_parity.is_some()
+                || !matches!(config.options.tokenizer, Tokenizer::Whitespace))
+        {
+            return Err(
+                "--partial-on-error only applies to the plain -c, -l, -w and -m counts.".into(),
+            );
+        }
+
+        #[cfg(feature = "normalize")]
+        if config.options.normalization.is_some() && config.partial_on_error {
+            return Err("--normalize can't be combined with --partial-on-error.".into());
+        }
+
+        #[cfg(feature = "hash")]
+        if config.hash_algo.is_some()
+            && (config.partial_on_error || !matches!(config.count_type, CountType::Plain(_)))
+        {
+            return Err("--with-hash only applies to the plain -c, -l, -w and -m counts.".into());
+        }
+
+        if config.options.encoding.is_some() && config.partial_on_error {
+            return Err("--encoding can't be combined with --partial-on-error.".into());
+        }
+
+        if config.files0_from.is_some() && !config.file_paths.is_empty() {
+            return Err("File operands can't be combined with --files0-from.".into());
+        }
+
+        if config.output_format == OutputFormat::Csv
+            && !matches!(config.count_type, CountType::Plain(_))
+        {
+            return Err("--csv only applies to the plain -c, -l, -w and -m counts.".into());
+        }
+        if config.output_format == OutputFormat::KeyVal
+            && !matches!(config.count_type, CountType::Plain(_))
+        {
+            return Err(
+                "--format=keyval only applies to the plain -c, -l, -w and -m counts.".into(),
+            );
+        }
+        if config.separator.is_some() {
+            if !matches!(config.count_type, CountType::Plain(_)) {
+                return Err(
+                    "--separator only applies to the plain -c, -l, -w and -m counts.".into(),
+                );
+            }
+            if config.output_format != OutputFormat::Text {
+                return Err("--separator only applies to the text output.".into());
+            }
+        }
+        if config.sort == Some(SortOrder::Lines)
+            && !matches!(config.count_type, CountType::Plain(columns) if columns.lines)
+        {
+            return Err("--sort=lines needs the line count in the output (default or -l).".into());
+        }
+        if config.diff && config.count_type != CountType::Plain(Columns::DEFAULT) {
+            return Err(
+                "--diff always compares lines, words, bytes and chars, so it can't be \
+                 combined with count flags or another mode."
+                    .into(),
+            );
+        }
+        if config.merge {
+            if config.count_type != CountType::Plain(Columns::DEFAULT) {
+                return Err(
+                    "--merge takes its columns from the CSV header, so it can't be combined \
+                     with count flags or another mode."
+                        .into(),
+                );
+            }
+            if config.file_paths.is_empty() {
+                return Err("--merge needs at least one CSV file.".into());
+            }
+        }
+        // The first `-` would read stdin to the end, leaving nothing for
+        // the others.
+        if config
+            .file_paths
+            .iter()
+            .filter(|path| *path == STDIN_PATH)
+            .count()
+            > 1
+        {
+            return Err(
+                "- can only be given once, as standard input can only be read once.".into(),
+            );
+        }
+        if config.follow_symlinks && !config.recursive {
+            return Err("--follow-symlinks only applies to --recursive (-r).".into());
+        }
+        if config.max_depth.is_some() && !config.recursive {
+            return Err("--max-depth only applies to --recursive (-r).".into());
+        }
+        if config.total == TotalMode::Summary {
+            if !matches!(config.count_type, CountType::Plain(_)) {
+                return Err("--summary only applies to the plain -c, -l, -w and -m counts.".into());
+            }
+            if config.output_format != OutputFormat::Text {
+                return Err("--summary only applies to the text output.".into());
+            }
+        }
+        if config.bare && config.output_format != OutputFormat::Text {
+            return Err("--bare only applies to the text output.".into());
+        }
+        if config.bare && config.labels {
+            return Err("--bare can't be combined with --labels.".into());
+        }
+        if config.bare && config.total == TotalMode::Summary {
+            return Err("--bare can't be combined with --summary.".into());
+        }
+        if config.labels {
+            if !matches!(config.count_type, CountType::Plain(_)) {
+                return Err("--labels only applies to the plain -c, -l, -w and -m counts.".into());
+            }
+            if config.output_format != OutputFormat::Text {
+                return Err("--labels only applies to the text output.".into());
+            }
+        }
+        #[cfg(feature = "hash")]
+        if config.output_format == OutputFormat::Csv && config.hash_algo.is_some() {
+            return Err("--csv can't be combined with --with-hash.".into());
+        }
+
+        let expectations = config.expectations;
+        let unchecked = match config.count_type {
+            CountType::Plain(columns) => {
+                (expectations.lines.is_some() && !columns.lines)
+                    || (expectations.words.is_some() && !columns.words)
+                    || (expectations.bytes.is_some() && !columns.bytes)
+            }
+            _ => !expectations.is_empty(),
+        };
+        if unchecked || (config.partial_on_error && !expectations.is_empty()) {
+            return Err(
+                "--expect-*-between and --fail-on-empty need that count in the output \
+                 (default, -l, -w or -c)."
+                    .into(),
+            );
+        }
+
+        if config.line_parity.is_some() && config.line_numbers_from.is_some() {
+            return Err(
+                "--odd-lines/--even-lines can't be combined with --line-numbers-from.".into(),
+            );
+        }
+
+        if config.top.is_some() && config.count_type != CountType::FirstTokenFreq {
+            return Err("--top only applies to --first-token-freq.".into());
+        }
+        if config.options.tab_width.is_some()
+            && !matches!(
+                config.count_type,
+                CountType::MaxLineLength | CountType::LineStats | CountType::IndentChanges { .. }
+            )
+        {
+            return Err(
+                "--tab-width only applies to --max-line-length (-L), --line-stats and \
+                 --indent-changes."
+                    .into(),
+            );
+        }
+        if config.freq_top.is_some() && config.count_type != CountType::WordFreq {
+            return Err("--freq-top only applies to --freq.".into());
+        }
+
+        if config.min_count.is_some() && config.count_type != CountType::WordFreq {
+            return Err("--min-count only applies to --freq.".into());
+        }
+
+        if config.options.column_range.is_some()
+            && !matches!(
+                config.count_type,
+                CountType::Plain(columns) if !columns.lines && !columns.bytes
+            )
+        {
+            return Err(
+                "--column-range only applies to word (-w) and character (-m) counts.".into(),
+            );
+        }
+
+        Ok(config)
+    }
+
+    fn _parse_type(arg: &str) -> Option<CountType> {
+        if let Some(long) = arg.strip_prefix("--") {
+            if let Some(limit) = long.strip_prefix("max-blank-run=") {
+                return Some(CountType::MaxBlankRun(Some(limit.parse().ok()?)));
+            }
+            return match long {
+                "lines" => Some(CountType::Plain(Columns::LINES)),
+                "words" => Some(CountType::Plain(Columns::WORDS)),
+                "chars" => Some(CountType::Plain(Columns::CHARS)),
+                "bytes" => Some(CountType::Plain(Columns::BYTES)),
+                "all" => Some(CountType::Plain(Columns::ALL)),
+                "max-line-length" => Some(CountType::MaxLineLength),
+                "bytes-per-char" => Some(CountType::BytesPerChar),
+                "max-blank-run" => Some(CountType::MaxBlankRun(None)),
+                "bracket-balance" => Some(CountType::BracketBalance),
+                #[cfg(feature = "categories")]
+                "by-category" => Some(CountType::ByCategory),
+                "no-trailing-blank-lines" => Some(CountType::TrailingBlankLines),
+                #[cfg(feature = "gzip")]
+                "compression-ratio" => Some(CountType::CompressionRatio),
+                "tab-after-space" => Some(CountType::TabAfterSpace),
+                "trailing-nulls" => Some(CountType::TrailingNulls),
+                "distinct-lengths" | "distinct-lengths=chars" => {
+                    Some(CountType::DistinctLengths(LengthUnit::Chars))
+                }
+                "distinct-lengths=bytes" => Some(CountType::DistinctLengths(LengthUnit::Bytes)),
+                "trim-savings" => Some(CountType::TrimSavings),
+                "indent-changes" => Some(CountType::IndentChanges {
+                    include_blank: false,
+                }),
+                "indent-changes=include-blank" => Some(CountType::IndentChanges {
+                    include_blank: true,
+                }),
+                "all-upper" => Some(CountType::AllUpperLines),
+                "all-lower" => Some(CountType::AllLowerLines),
+                "encoding-report" => Some(CountType::EncodingReport),
+                "first-token-freq" => Some(CountType::FirstTokenFreq),
+                "freq" => Some(CountType::WordFreq),
+                "shortest-line" => Some(CountType::ShortestLine),
+                "paragraphs" => Some(CountType::ParagraphCount),
+                "sentences" => Some(CountType::Sentences),
+                "non-empty-lines" => Some(CountType::NonEmptyLines),
+                "blank-lines" => Some(CountType::BlankLines),
+                "line-stats" => Some(CountType::LineStats),
+                "line-endings" => Some(CountType::LineEndings),
+                "unique-lines" => Some(CountType::UniqueLines),
+                "duplicate-lines" => Some(CountType::DuplicateLines),
+                #[cfg(feature = "segmentation")]
+                "graphemes" => Some(CountType::Graphemes),
+                #[cfg(feature = "json")]
+                "valid-json-lines" => Some(CountType::ValidJsonLines),
+                _ => None,
+            };
+        }
+        if arg == "-L" {
+            return Some(CountType::MaxLineLength);
+        }
+        Columns::from_flags(arg.strip_prefix('-')?).map(CountType::Plain)
+    }
+
+    fn get_count_type(&self) -> CountType {
+        self.count_type
+    }
+    fn get_file_paths(&self) -> Vec<String> {
+        self.file_paths.clone()
+    }
+}
+
+/// Builds a [`Config`] programmatically. See [`Config::builder`].
+#[derive(Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn count_type(mut self, count_type: CountType) -> Self {
+        self.config.count_type = count_type;
+        self
+    }
+
+    /// Adds an input. Inputs are counted in the order they are added, and
+    /// `-` means stdin.
+    pub fn file_path(mut self, path: impl Into<String>) -> Self {
+        self.config.file_paths.push(path.into());
+        self
+    }
+
+    /// Replaces the [`CountOptions`] every input is counted with.
+    pub fn options(mut self, options: CountOptions) -> Self {
+        self.config.options = options;
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+/// The buffered report for one input and how counting it went.
+type CountedPath = (Vec<u8>, Result<Option<Counts>, String>);
+
+#[derive(Clone)]
+pub struct Counter {
+    count_type: CountType,
+    options: CountOptions,
+    file_paths: Vec<String>,
+    /// The input currently being counted.
+    source: Source,
+    program: String,
+    byte_units: ByteUnits,
+    output_format: OutputFormat,
+    separator: Option<String>,
+    labels: bool,
+    total: TotalMode,
+    color: ColorMode,
+    null_terminated: bool,
+    tee: bool,
+    base64_decode: bool,
+    alert_lines: Option<usize>,
+    progress: bool,
+    no_tty_stdin: bool,
+    check: bool,
+    sort: Option<SortOrder>,
+    no_align: bool,
+    bare: bool,
+    diff: bool,
+    merge: bool,
+    split_marker: Option<String>,
+    matcher: Option<Matcher>,
+    vocab_path: Option<String>,
+    partial_on_error: bool,
+    recursive: bool,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    files0_from: Option<String>,
+    line_numbers_from: Option<String>,
+    line_parity: Option<LineParity>,
+    top: Option<usize>,
+    freq_top: Option<usize>,
+    min_count: Option<usize>,
+    expectations: Expectations,
+    #[cfg(feature = "hash")]
+    hash_algo: Option<HashAlgo>,
+    #[cfg(feature = "json")]
+    show_invalid_lines: bool,
+    #[cfg(feature = "gzip")]
+    decompress: bool,
+    /// Digest of the raw input, filled in by `read_input` when hashing.
+    #[cfg(feature = "hash")]
+    digest: RefCell<Option<String>>,
+    /// Length of the raw input, recorded by `read_raw` so that byte counts
+    /// can ignore `--encoding`.
+    raw_len: Cell<usize>,
+    /// How many inputs were detected as each encoding, for
+    /// `--encoding-report`. Shared by the clones that count each input.
+    encodings: Arc<Mutex<HashMap<String, usize>>>,
+    /// Width every plain count is right-aligned to, set once the inputs are
+    /// known.
+    number_width: usize,
+}
+
+impl Counter {
+    /// Counts `reader` instead of a file or stdin, with every other option at
+    /// its command-line default. Results are unlabelled, like those for
+    /// stdin.
+    ///
+    /// ```
+    /// use gfwc::{Columns, CountType, Counter};
+    ///
+    /// let input = &b"one two\nthree\n"[..];
+    /// let counter = Counter::from_reader(input, CountType::Plain(Columns::DEFAULT));
+    /// let mut out = Vec::new();
+    /// counter.count_into(&mut out).unwrap();
+    /// assert_eq!(out, b"      2       3      14\n");
+    /// ```
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, count_type: CountType) -> Counter {
+        Counter {
+            source: Source::Reader(SharedReader(Arc::new(Mutex::new(Box::new(reader))))),
+            ..Counter::from(Config::builder().count_type(count_type).build())
+        }
+    }
+
+    /// Counts with `options` in place of those the counter was built with.
+    pub fn with_options(self, options: CountOptions) -> Counter {
+        Counter { options, ..self }
+    }
+
+    /// Counts and prints the results to stdout. See [`Counter::count_into`],
+    /// or [`Counter::count_stats`] to get the counts without printing.
+    pub fn count(mut self) -> Result<(), CcwcError> {
+        let terminal = if self.tee {
+            io::stderr().is_terminal()
+        } else {
+            io::stdout().is_terminal()
+        };
+        self.color = self.color.resolve(terminal);
+        self.count_into(io::stdout().lock())
+    }
+
+    /// Counts each input in turn, or stdin if none were given, writing the
+    /// results to `out`. With several inputs a `total` line follows for the
+    /// plain `-c`/`-l`/`-w`/`-m` counts. As with `wc`, an input that can't be
+    /// counted is reported on stderr and skipped, and the run fails once the
+    /// rest are done. With `--tee` the input itself goes to stdout, so the
+    /// results are written to stderr instead of `out`.
+    pub fn count_into<W: Write>(mut self, mut out: W) -> Result<(), CcwcError> {
+        let result = self.count_inputs(&mut out);
+        out.flush()?;
+        result
+    }
+
+    fn count_inputs(&mut self, out: &mut dyn Write) -> Result<(), CcwcError> {
+        let mut paths = std::mem::take(&mut self.file_paths);
+        if let Some(list) = &self.files0_from {
+            paths = read_files0(list).map_err(|error| CcwcError::File {
+                path: list.clone(),
+                error: Box::new(error.into()),
+            })?;
+        }
+        // Inputs were named but none are left, e.g. an empty --files0-from
+        // list or an empty directory: there is nothing to count, not stdin.
+        let named = !paths.is_empty() || self.files0_from.is_some();
+        #[cfg(feature = "glob")]
+        if self.files0_from.is_none() {
+            paths = expand_globs(paths, &self.program);
+        }
+        if self.recursive {
+            paths = expand_directories(paths, self.follow_symlinks, self.max_depth, &self.program);
+        }
+        if named && paths.is_empty() {
+            return Ok(());
+        }
+        if self.check {
+            return self.check_inputs(&paths);
+        }
+        if self.diff {
+            return self.count_diff(out, &paths);
+        }
+        if self.merge {
+            return self.merge_csv(out, &paths);
+        }
+        self.number_width = if self.no_align || self.bare {
+            1
+        } else {
+            self.number_width(&paths)
+        };
+        if self.output_format == OutputFormat::Csv {
+            let header = self.selected_columns().names().chain(["filename"]);
+            self.report(
+                out,
+                format_args!("{}", header.collect::<Vec<_>>().join(",")),
+            )?;
+        }
+        let show_total = match self.total {
+            TotalMode::Auto => paths.len() > 1,
+            TotalMode::Always | TotalMode::Only | TotalMode::Summary => true,
+            TotalMode::Never => false,
+        };
+        if paths.len() <= 1 && !show_total {
+            if let Some(path) = paths.first() {
+                self.source = Source::from_path(path);
+            }
+            let counted =
+                self.count_file(out)
+                    .map(|_| ())
+                    .map_err(|error| match self.source.name() {
+                        Some(path) => CcwcError::File {
+                            path: path.into_owned(),
+                            error: Box::new(error),
+                        },
+                        None => error,
+                    });
+            self.report_encodings(out)?;
+            return counted;
+        }
+        let mut total = Counts::default();
+        let mut failed = 0;
+        let mut counted = 0;
+        // Key/value blocks are told apart by the blank line between them.
+        let block_gap: &[u8] = match self.output_format {
+            OutputFormat::KeyVal => b"\n",
+            _ => b"",
+        };
+        let mut written = false;
+        // Each input is printed as soon as it is counted, so a long run
+        // shows progress rather than nothing until the end.
+        let mut print = |path: &str, (report, outcome): CountedPath| -> io::Result<()> {
+            let per_input = !matches!(self.total, TotalMode::Only | TotalMode::Summary);
+            if per_input && !report.is_empty() {
+                if written {
+                    out.write_all(block_gap)?;
+                }
+                out.write_all(&report)?;
+                out.flush()?;
+                written = true;
+            }
+            match outcome {
+                Ok(Some(counts)) => {
+                    total += counts;
+                    counted += 1;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("{}: {}: {}", self.program, path, e);
+                    failed += 1;
+                }
+            }
+            Ok(())
+        };
+        if paths.is_empty() {
+            print(STDIN_PATH, self.count_path(None))?;
+        } else if let Some(order) = self.sort {
+            let mut results = Vec::new();
+            self.count_each(&paths, &mut |index, counted| {
+                results.push((paths[index].as_str(), counted));
+                Ok(())
+            })?;
+            results.sort_by_cached_key(|(path, (_, outcome))| {
+                (order.key(path, outcome), path.to_string())
+            });
+            for (path, counted) in results {
+                print(path, counted)?;
+            }
+        } else {
+            self.count_each(&paths, &mut |index, counted| print(&paths[index], counted))?;
+        }
+        let label = if self.bare || self.total == TotalMode::Only {
+            ""
+        } else {
+            " total"
+        };
+        if self.total == TotalMode::Summary {
+            let fields: Vec<String> = self
+                .selected_columns()
+                .entries(total)
+                .map(|(name, count)| match name {
+                    "bytes" => format!("{}:{}", name, self.format_bytes(count as u64)),
+                    _ => format!("{}:{}", name, count),
+                })
+                .collect();
+            self.report_total(out, format_args!("files:{} {}", counted, fields.join(" ")))?;
+        } else if show_total && self.is_plain() {
+            if written {
+                out.write_all(block_gap)?;
+            }
+            let line = self.format_summary(&total, "", label);
+            self.report_total(out, format_args!("{}", line))?;
+        } else if show_total && self.count_type == CountType::MaxLineLength {
+            self.report_total(out, format_args!("{}{}", total.max_line_length, label))?;
+        }
+        self.report_encodings(out)?;
+        if failed > 0 {
+            return Err(CcwcError::FilesFailed {
+                failed,
+                total: paths.len().max(1),
+            });
+        }
+        Ok(())
+    }
+
+    /// Prints the `--encoding-report` tally, one `ENCODING INPUTS` row per
+    /// encoding, most common first.
+    fn report_encodings(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.count_type != CountType::EncodingReport {
+            return Ok(());
+        }
+        let tally = std::mem::take(&mut *self.encodings.lock().unwrap());
+        for (encoding, inputs) in sort_by_frequency(tally) {
+            self.report(out, format_args!("{} {}", encoding, inputs))?;
+        }
+        Ok(())
+    }
+
+    /// Opens each input and reads its first byte, for `--check`. Any that
+    /// can't be read are reported on stderr as they would be when counting;
+    /// nothing is printed for the rest. Stdin is always taken to be readable.
+    fn check_inputs(&self, paths: &[String]) -> Result<(), CcwcError> {
+        let mut failed = 0;
+        for path in paths.iter().filter(|path| *path != STDIN_PATH) {
+            let readable = File::open(path).and_then(|mut file| file.read(&mut [0; 1]));
+            if let Err(error) = readable {
+                eprintln!("{}: {}: {}", self.program, path, CcwcError::from(error));
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(CcwcError::FilesFailed {
+                failed,
+                total: paths.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Prints the total of the rows of earlier `--csv` outputs, for
+    /// `--merge`, in the columns of their header. The header is the schema:
+    /// every input must have the same one. A last row named `total` is the
+    /// input's own total, so it is left out of the sum.
+    fn merge_csv(&self, out: &mut dyn Write, paths: &[String]) -> Result<(), CcwcError> {
+        let mut schema: Option<(&str, String)> = None;
+        let mut total = Counts::default();
+        for path in paths {
+            let failed = |message: String| CcwcError::File {
+                path: path.clone(),
+                error: Box::new(CcwcError::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    message,
+                ))),
+            };
+            let text = std::fs::read_to_string(path).map_err(|error| CcwcError::File {
+                path: path.clone(),
+                error: Box::new(error.into()),
+            })?;
+            let (header, rows) = text.split_once('\n').unwrap_or((&text, ""));
+            let columns = parse_csv_header(header)
+                .ok_or_else(|| failed(format!("not gfwc --csv output: header {:?}", header)))?;
+            match &schema {
+                Some((first, expected)) if expected != header => {
+                    return Err(failed(format!(
+                        "columns {} don't match {} in {}",
+                        header, expected, first
+                    )));
+                }
+                Some(_) => {}
+                None => schema = Some((path, header.to_string())),
+            }
+            let mut rows = parse_csv_rows(rows, columns).map_err(failed)?;
+            if rows.len() > 1 && rows.last().is_some_and(|(_, name)| name == "total") {
+                rows.pop();
+            }
+            for (counts, _) in rows {
+                total += counts;
+            }
+        }
+        let Some((_, header)) = schema else {
+            return Ok(());
+        };
+        let mut counter = self.clone();
+        counter.count_type = CountType::Plain(parse_csv_header(&header).unwrap_or_default());
+        if counter.output_format == OutputFormat::Csv {
+            counter.report(out, format_args!("{}", header))?;
+        }
+        let label = if self.bare { "" } else { " total" };
+        let line = counter.format_summary(&total, "", label);
+        counter.report_total(out, format_args!("{}", line))?;
+        Ok(())
+    }
+
+    /// Prints how the counts of the second input differ from those of the
+    /// first, for `--diff`, as in `Δlines:+3 Δwords:-1 Δbytes:+20 Δchars:+20`.
+    fn count_diff(&self, out: &mut dyn Write, paths: &[String]) -> Result<(), CcwcError> {
+        let [before, after] = paths else {
+            return Err("--diff needs exactly two files.".into());
+        };
+        let counts = |path: &String| {
+            let mut counter = self.clone();
+            counter.source = Source::from_path(path);
+            counter.count_all().map_err(|error| CcwcError::File {
+                path: path.clone(),
+                error: Box::new(error.into()),
+            })
+        };
+        let (before, after) = (counts(before)?, counts(after)?);
+        let delta = |before: usize, after: usize| after as i64 - before as i64;
+        self.report(
+            out,
+            format_args!(
+                "Δlines:{:+} Δwords:{:+} Δbytes:{:+} Δchars:{:+}",
+                delta(before.lines, after.lines),
+                delta(before.words, after.words),
+                delta(before.bytes, after.bytes),
+                delta(before.chars, after.chars)
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Counts `path` into a buffer of its own, returning the buffered report
+    /// alongside the outcome so callers can emit both in argument order.
+    fn count_path(&self, path: Option<&str>) -> CountedPath {
+        let mut counter = self.clone();
+        if let Some(path) = path {
+            counter.source = Source::from_path(path);
+        }
+        let mut report = Vec::new();
+        let outcome = counter.count_file(&mut report).map_err(|e| e.to_string());
+        (report, outcome)
+    }
+
+    /// Counts every path on a bounded pool of worker threads, each taking the
+    /// next uncounted path until none are left, and passes each result to
+    /// `each` with the index of its path. Results are passed in the order of
+    /// `paths`, each as soon as it and those before it are done, or with
+    /// `--no-align` in whatever order they finish. A worker that panics only
+    /// loses the path it was on, which is reported as an error. With `--tee`
+    /// the reports go to stderr as they are made, so the paths are counted
+    /// one after another instead. Stops at the first error from `each`.
+    #[cfg(feature = "threads")]
+    fn count_each(
+        &self,
+        paths: &[String],
+        each: &mut dyn FnMut(usize, CountedPath) -> io::Result<()>,
+    ) -> io::Result<()> {
+        use std::panic::{self, AssertUnwindSafe};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::mpsc;
+
+        if self.tee {
+            for (index, path) in paths.iter().enumerate() {
+                each(index, self.count_path(Some(path)))?;
+            }
+            return Ok(());
+        }
+        let workers = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(paths.len());
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            // Owned here, so that returning early hangs up on the workers.
+            let receiver = receiver;
+            for _ in 0..workers {
+                let counter = self.clone();
+                let sender = sender.clone();
+                let next = &next;
+                scope.spawn(move || loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        break;
+                    };
+                    let counted =
+                        panic::catch_unwind(AssertUnwindSafe(|| counter.count_path(Some(path))))
+                            .unwrap_or_else(|_| (Vec::new(), Err("counting panicked".to_string())));
+                    if sender.send((index, counted)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            let mut pending = BTreeMap::new();
+            let mut next_in_order = 0;
+            for (index, counted) in receiver {
+                if self.no_align {
+                    each(index, counted)?;
+                    continue;
+                }
+                pending.insert(index, counted);
+                while let Some(counted) = pending.remove(&next_in_order) {
+                    each(next_in_order, counted)?;
+                    next_in_order += 1;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Counts every path on the calling thread, one after another, for builds
+    /// without the `threads` feature. Results are passed to `each` in the
+    /// order of `paths`, the same as the pooled version without `--no-align`.
+    /// Stops at the first error from `each`.
+    #[cfg(not(feature = "threads"))]
+    fn count_each(
+        &self,
+        paths: &[String],
+        each: &mut dyn FnMut(usize, CountedPath) -> io::Result<()>,
+    ) -> io::Result<()> {
+        for (index, path) in paths.iter().enumerate() {
+            each(index, self.count_path(Some(path)))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the mode is one of the summable `-c`/`-l`/`-w`/`-m` counts.
+    fn is_plain(&self) -> bool {
+        matches!(self.count_type, CountType::Plain(_))
+    }
+
+    /// Counts and reports the current input. Plain counts are returned so
+    /// they can be added to the total.
+    fn count_file(&self, out: &mut dyn Write) -> Result<Option<Counts>, CcwcError> {
+        let label = self.label();
+        if self.partial_on_error {
+            return self.count_partial(out, &label).map(Some);
+        }
+        if self.is_plain() {
+            return self.count_plain(out, &label).map(Some);
+        }
+        match self.count_type {
+            CountType::BytesPerChar => {
+                let (bytes, chars) = if self.needs_whole_input() {
+                    let input_data = self.read_input()?;
+                    let chars = Self::count_chars_from_reader(Cursor::new(input_data.as_str()))?;
+                    (input_data.len(), chars)
+                } else {
+                    let counts = self.stream_text_counts(self.stream_text(None)?)?;
+                    (counts.bytes, counts.chars)
+                };
+                self.report(
+                    out,
+                    format_args!("{}{}", format_bytes_per_char(bytes, chars), label),
+                )?;
+            }
+            CountType::MaxBlankRun(limit) => {
+                let run = Self::count_max_blank_run_from_reader(self.text_lines()?)?;
+                self.report(out, format_args!("{}{}", run, label))?;
+                if let Some(limit) = limit.filter(|&limit| run > limit) {
+                    return Err(CcwcError::Check(format!(
+                        "longest run of blank lines ({}) exceeds the limit of {}",
+                        run, limit
+                    )));
+                }
+            }
+            CountType::BracketBalance => {
+                let balance = Self::count_bracket_balance_from_reader(self.stream_text(None)?)?;
+                self.report(
+                    out,
+                    format_args!(
+                        "()={} []={} {{}}={}{}",
+                        balance.parens, balance.brackets, balance.braces, label
+                    ),
+                )?;
+                if !balance.is_balanced() {
+                    return Err(CcwcError::Check("brackets are not balanced".to_string()));
+                }
+            }
+            #[cfg(feature = "categories")]
+            CountType::ByCategory => {
+                let tally = Self::count_by_category_from_reader(self.stream_text(None)?)?;
+                for (category, count) in tally {
+                    self.report(out, format_args!("{} {}", category, count))?;
+                }
+            }
+            CountType::TrailingBlankLines => {
+                let count = Self::count_trailing_blank_lines_from_reader(self.text_lines()?)?;
+                self.report(out, format_args!("{}{}", count, label))?;
+                if count > 0 {
+                    return Err(CcwcError::Check(format!(
+                        "{} trailing blank line(s) at end of input",
+                        count
+                    )));
+                }
+            }
+            CountType::SplitAt => {
+                let input_data = self.read_input()?;
+                let marker = self.split_marker.as_deref().unwrap_or_default();
+                let (before, after) = split_at_marker(&input_data, marker);
+                for (part, segment) in [("before", before), ("after", after)] {
+                    let bytes = Self::count_bytes_from_reader(Cursor::new(segment))?;
+                    let lines = Self::count_lines_from_reader(Cursor::new(segment))?;
+                    let words = Self::count_tokens_from_reader(
+                        Cursor::new(segment),
+                        &self.options.tokenizer,
+                    )?;
+                    self.report(
+                        out,
+                        format_args!(
+                            "{}\t{}\t{}\t{}{}",
+                            part,
+                            lines,
+                            words,
+                            self.format_bytes(bytes as u64),
+                            label
+                        ),
+                    )?;
+                }
+            }
+            #[cfg(feature = "gzip")]
+            CountType::CompressionRatio => {
+                let sizes = Self::measure_compression(self.buffered(self.open_input()?))?;
+                match sizes.decompressed {
+                    Some(decompressed) => self.report(
+                        out,
+                        format_args!(
+                            "{} {} {:.2}{}",
+                            sizes.compressed,
+                            decompressed,
+                            decompressed as f64 / sizes.compressed as f64,
+                            label
+                        ),
+                    )?,
+                    None => {
+                        self.report(out, format_args!("{} n/a n/a{}", sizes.compressed, label))?
+                    }
+                }
+            }
+            CountType::TabAfterSpace => {
+                let offending = Self::find_tab_after_space_from_reader(self.text_lines()?)?;
+                self.report(out, format_args!("{}{}", offending.len(), label))?;
+                for line_number in &offending {
+                    self.report(out, format_args!("line {}", line_number))?;
+                }
+                if !offending.is_empty() {
+                    return Err(CcwcError::Check(
+                        "tab after space in indentation".to_string(),
+                    ));
+                }
+            }
+            CountType::Plane(plane) => {
+                let count = Self::count_plane_chars_from_reader(self.stream_text(None)?, plane)?;
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            CountType::TrailingNulls => {
+                let count =
+                    Self::count_trailing_nulls_from_reader(self.buffered(self.open_input()?))?;
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            CountType::LineEndings => {
+                let endings =
+                    Self::count_line_endings_from_reader(self.buffered(self.open_input()?))?;
+                self.report(out, format_args!("{}{}", endings, label))?;
+            }
+            CountType::DistinctLengths(unit) => {
+                let count = Self::count_distinct_lengths_from_reader(self.text_lines()?, unit)?;
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            CountType::TrimSavings => {
+                let count = Self::count_trim_savings_from_reader(self.text_lines()?)?;
+                self.report(
+                    out,
+                    format_args!("{}{}", self.format_bytes(count as u64), label),
+                )?;
+            }
+            CountType::IndentChanges { include_blank } => {
+                let count = Self::count_indent_changes_from_reader(
+                    self.text_lines()?,
+                    include_blank,
+                    self.options.tab_stop(),
+                )?;
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            CountType::AllUpperLines | CountType::AllLowerLines => {
+                let (upper, lower) = Self::count_case_lines_from_reader(self.text_lines()?)?;
+                let count = if self.count_type == CountType::AllUpperLines {
+                    upper
+                } else {
+                    lower
+                };
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            CountType::NonEmptyLines | CountType::BlankLines => {
+                let blank = self.count_type == CountType::BlankLines;
+                let counted = |line: &str| line.trim().is_empty() == blank;
+                let count = Self::count_lines_where_from_reader(self.text_lines()?, counted)?;
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            #[cfg(feature = "segmentation")]
+            CountType::Graphemes => {
+                let lines = self.stream_checked_text(self.options.max_memory)?;
+                let count = Self::count_graphemes_from_reader(lines)?;
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            CountType::LineStats => {
+                let stats =
+                    Self::measure_line_stats_from_reader(self.text_lines()?, &self.options)?;
+                self.report(out, format_args!("{}{}", stats, label))?;
+            }
+            CountType::UniqueLines | CountType::DuplicateLines => {
+                let tally = if self.needs_whole_input() {
+                    Self::tally_lines_from_reader(Cursor::new(self.read_input()?), &self.options)?
+                } else {
+                    Self::tally_lines_from_reader(self.buffered(self.open_input()?), &self.options)?
+                };
+                let count = match self.count_type {
+                    CountType::UniqueLines => tally.len(),
+                    _ => tally.values().filter(|&&seen| seen > 1).count(),
+                };
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            CountType::Records(size) => {
+                let bytes = self.count_bytes()?;
+                self.report(
+                    out,
+                    format_args!("{} {}{}", bytes / size, bytes % size, label),
+                )?;
+            }
+            CountType::EncodingReport => {
+                let encoding = Encoding::detect(&self.read_raw()?).to_string();
+                // The tally is printed once every input is counted.
+                *self.encodings.lock().unwrap().entry(encoding).or_insert(0) += 1;
+            }
+            CountType::FirstTokenFreq => {
+                let freq = Self::count_first_token_freq_from_reader(self.text_lines()?)?;
+                let top = self.top.unwrap_or(usize::MAX);
+                for (token, count) in freq.iter().take(top) {
+                    self.report(out, format_args!("{} {}", count, token))?;
+                }
+            }
+            CountType::WordFreq => {
+                let mut tally =
+                    Self::count_word_freq_from_reader(self.text_lines()?, &self.options.tokenizer)?;
+                if let Some(min) = self.min_count {
+                    tally.retain(|_, count| *count >= min);
+                }
+                let top = self.freq_top.unwrap_or(usize::MAX);
+                for (word, count) in sort_by_frequency(tally).iter().take(top) {
+                    self.report(out, format_args!("{} {}", count, word))?;
+                }
+            }
+            CountType::ShortestLine => {
+                match Self::find_shortest_line_from_reader(self.text_lines()?)? {
+                    Some((length, line_number)) => {
+                        self.report(out, format_args!("{} {}{}", length, line_number, label))?
+                    }
+                    None => self.report(out, format_args!("n/a n/a{}", label))?,
+                }
+            }
+            CountType::VocabCoverage => {
+                let path = self.vocab_path.as_deref().unwrap_or_default();
+                let vocab = read_vocabulary(path, self.options.ignore_case)?;
+                let (matched, distinct) = Self::measure_vocab_coverage_from_reader(
+                    self.text_lines()?,
+                    &vocab,
+                    &self.options.tokenizer,
+                    self.options.ignore_case,
+                )?;
+                if distinct == 0 {
+                    self.report(out, format_args!("0 0 n/a{}", label))?;
+                } else {
+                    let percent = matched as f64 * 100.0 / distinct as f64;
+                    self.report(
+                        out,
+                        format_args!("{} {} {:.1}%{}", matched, distinct, percent, label),
+                    )?;
+                }
+            }
+            CountType::MaxLineLength => {
+                let counts = self.count_stats()?;
+                self.report(out, format_args!("{}{}", counts.max_line_length, label))?;
+                return Ok(Some(counts));
+            }
+            CountType::ParagraphCount => {
+                let count = Self::count_paragraphs_from_reader(self.text_lines()?)?;
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            CountType::Sentences => {
+                let mut sentences = SentenceCounter::new();
+                for_each_char(self.stream_text(None)?, |c| sentences.push(c))?;
+                let count = sentences.finish();
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            CountType::Matches => {
+                let matcher = self.matcher.as_ref().expect("--match sets a matcher");
+                let count = match matcher {
+                    Matcher::Substring { .. } => matcher.count(self.stream_text(None)?)?,
+                    // A regex match may span any number of lines, so it
+                    // needs all of the input.
+                    #[cfg(feature = "regex")]
+                    Matcher::Regex(_) => matcher.count(Cursor::new(self.read_input()?))?,
+                };
+                self.report(out, format_args!("{}{}", count, label))?;
+            }
+            #[cfg(feature = "json")]
+            CountType::ValidJsonLines => {
+                let counts = Self::count_json_lines_from_reader(self.text_lines()?)?;
+                self.report(
+                    out,
+                    format_args!("{} {}{}", counts.valid, counts.invalid, label),
+                )?;
+                if self.show_invalid_lines {
+                    for line_number in counts.invalid_lines {
+                        self.report(out, format_args!("invalid line {}", line_number))?;
+                    }
+                }
+            }
+            CountType::Plain(_) => unreachable!("plain counts are handled by count_plain"),
+        }
+        Ok(None)
+    }
+
+    fn count_plain(&self, out: &mut dyn Write, label: &str) -> Result<Counts, CcwcError> {
+        let summary = self.count_stats()?;
+        self.report_summary(out, &summary, &self.hash_column(), label)?;
+        // Only counts that are shown can have bounds, so the others are
+        // never checked.
+        self.expectations
+            .check(
+                Some(summary.lines),
+                Some(summary.words),
+                Some(summary.bytes),
+            )
+            .map_err(CcwcError::Check)?;
+        Ok(summary)
+    }
+
+    /// Counts the current input without printing anything. A plain count
+    /// fills in its selected columns, and `-L` fills in `max_line_length`;
+    /// other fields may be left at 0. The other modes report results that
+    /// don't fit in [`Counts`], so they fail with [`CcwcError::Usage`].
+    ///
+    /// ```
+    /// use gfwc::{Columns, CountType, Counter};
+    ///
+    /// let counter = Counter::from_reader(&b"one two\n"[..], CountType::Plain(Columns::WORDS));
+    /// assert_eq!(counter.count_stats().unwrap().words, 2);
+    /// ```
+    pub fn count_stats(&self) -> Result<Counts, CcwcError> {
+        let columns = match self.count_type {
+            CountType::Plain(columns) => columns,
+            CountType::MaxLineLength => {
+                // Only the width so far is kept, so a line of any length
+                // fits in `--max-memory`.
+                let width = Self::count_max_line_length_from_reader(
+                    self.stream_text(None)?,
+                    &self.options,
+                )?;
+                return Ok(Counts {
+                    max_line_length: width,
+                    ..Counts::default()
+                });
+            }
+            _ => {
+                return Err(
+                    "count_stats only supports the plain counts and --max-line-length.".into(),
+                )
+            }
+        };
+        let mut summary = Counts::default();
+        match columns {
+            Columns::BYTES => summary.bytes = self.count_bytes()?,
+            Columns::LINES => summary.lines = self.count_lines()?,
+            Columns::WORDS => summary.words = self.count_words()?,
+            Columns::DEFAULT => summary = self.count_all()?,
+            _ if !columns.chars && !self.needs_text() => summary = self.stream_counts()?,
+            _ if !self.needs_text() => {
+                let text = self.stream_checked_text(None)?;
+                summary = self.stream_text_counts(text)?;
+            }
+            _ if !self.needs_whole_input() => {
+                let lines = if columns.chars {
+                    self.stream_checked_text(self.options.max_memory)?
+                } else {
+                    self.text_lines()?
+                };
+                summary = self.count_text_by_line(lines, columns)?;
+            }
+            _ => {
+                let input_data = if columns.chars {
+                    self.read_text()?
+                } else {
+                    self.read_input()?
+                };
+                if columns.lines {
+                    summary.lines =
+                        Self::count_lines_from_reader(Cursor::new(input_data.as_str()))?;
+                }
+                if columns.words {
+                    summary.words = self.words_in(&input_data)?;
+                }
+                if columns.chars {
+                    summary.chars = self.chars_in(&input_data)?;
+                }
+                summary.bytes = self.bytes_of(&input_data);
+            }
+        }
+        Ok(summary)
+    }
+
+    /// The name printed after the counts, with its leading space. Like `wc`,
+    /// nothing at all is printed for stdin when no file was named.
+    fn label(&self) -> String {
+        match self.source.name() {
+            Some(name) if !self.bare => format!(" {}", name),
+            _ => String::new(),
+        }
+    }
+
+    fn report_summary(
+        &self,
+        out: &mut dyn Write,
+        summary: &Counts,
+        hash: &str,
+        label: &str,
+    ) -> io::Result<()> {
+        self.report(
+            out,
+            format_args!("{}", self.format_summary(summary, hash, label)),
+        )
+    }
+
+    /// The width plain counts are padded to, chosen as GNU `wc` does before
+    /// anything is read. A lone count for a single input isn't padded.
+    /// Otherwise the width fits the combined size of the regular files,
+    /// which bounds every count including the total. Stdin, pipes and other
+    /// inputs of unknown size get at least 7 columns.
+    fn number_width(&self, paths: &[String]) -> usize {
+        if paths.len() <= 1 && self.selected_columns().names().count() == 1 {
+            return 1;
+        }
+        let mut minimum = if paths.is_empty() { 7 } else { 1 };
+        let mut total: u64 = 0;
+        for path in paths {
+            if path == STDIN_PATH {
+                minimum = 7;
+                continue;
+            }
+            match std::fs::metadata(path) {
+                Ok(metadata) if metadata.is_file() => total += metadata.len(),
+                Ok(_) => minimum = 7,
+                // It will be reported when counted.
+                Err(_) => {}
+            }
+        }
+        total.to_string().len().max(minimum)
+    }
+
+    /// The columns a plain count prints.
+    fn selected_columns(&self) -> Columns {
+        match self.count_type {
+            CountType::Plain(columns) => columns,
+            _ => Columns::DEFAULT,
+        }
+    }
+
+    /// The columns of a plain count. `hash` and `label` carry their own
+    /// leading separators.
+    fn format_summary(&self, summary: &Counts, hash: &str, label: &str) -> String {
+        let columns = self.selected_columns();
+        let fields: Vec<String> = columns
+            .entries(*summary)
+            .map(|(name, count)| match name {
+                "bytes" => self.format_bytes(count as u64),
+                _ => count.to_string(),
+            })
+            .collect();
+        match self.output_format {
+            OutputFormat::Text => {
+                let fields: Vec<String> = if self.labels {
+                    columns
+                        .names()
+                        .zip(&fields)
+                        .map(|(name, field)| format!("{}:{}", name, field))
+                        .collect()
+                } else {
+                    fields
+                };
+                match &self.separator {
+                    Some(separator) => join_columns(&fields, hash, label, separator),
+                    // Labelled counts differ in length, so padding can't line them up.
+                    None if self.labels => join_columns(&fields, hash, label, " "),
+                    None => {
+                        let width = self.number_width;
+                        let fields: Vec<String> = fields
+                            .iter()
+                            .map(|field| format!("{:>width$}", field))
+                            .collect();
+                        join_columns(&fields, hash, label, " ")
+                    }
+                }
+            }
+            OutputFormat::Csv => {
+                let name = label.strip_prefix(' ').unwrap_or(label);
+                format!("{},{}", fields.join(","), csv_field(name))
+            }
+            OutputFormat::KeyVal => {
+                let mut pairs: Vec<String> = columns
+                    .names()
+                    .zip(&fields)
+                    .map(|(name, field)| format!("{}={}", name, field))
+                    .collect();
+                if let Some(digest) = hash.strip_prefix(' ') {
+                    pairs.push(format!("hash={}", digest));
+                }
+                if let Some(name) = label.strip_prefix(' ') {
+                    pairs.push(format!("file={}", name));
+                }
+                pairs.join("\n")
+            }
+        }
+    }
+
+    /// Counts bytes, lines and words. The raw byte stream is counted in one
+    /// buffered pass without holding it in memory. When an option needs the
+    /// decoded text, that text is read once and counted in a single pass.
+    /// Either way it runs on the calling thread: the `threads` feature only
+    /// decides whether several FILEs are counted side by side.
+    pub fn count_all(&self) -> Result<Counts, io::Error> {
+        if !self.needs_text() {
+            return self.stream_counts();
+        }
+        if !self.needs_whole_input() {
+            return self.count_text_by_line(self.text_lines()?, Columns::DEFAULT);
+        }
+        let input_data = self.read_input()?;
+        let bytes = self.bytes_of(&input_data);
+        let counts = Self::count_all_from_input(input_data.as_bytes(), &self.options)?;
+        Ok(Counts { bytes, ..counts })
+    }
+
+    /// Counts the raw input through a [`CountState`], a buffer at a time.
+    fn stream_counts(&self) -> Result<Counts, io::Error> {
+        self.stream_text_counts(self.open_input()?)
+    }
+
+    /// Counts `input` through a [`CountState`], a buffer at a time.
+    fn stream_text_counts<R: Read>(&self, input: R) -> Result<Counts, io::Error> {
+        CountState::count_reader_in_chunks(input, self.options.chunk_size())
+            .map(Counts::from)
+            .map_err(|partial| partial.error)
+    }
+
+    /// The selected plain counts of `text`, a line at a time, for options
+    /// that count words or characters within each line.
+    fn count_text_by_line<R: BufRead>(
+        &self,
+        mut text: R,
+        columns: Columns,
+    ) -> Result<Counts, io::Error> {
+        let mut counts = Counts::default();
+        let mut line = String::new();
+        while text.read_line(&mut line)? > 0 {
+            counts.bytes += line.len();
+            counts.lines += usize::from(line.ends_with('\n'));
+            if columns.words {
+                counts.words += self.words_in(&line)?;
+            }
+            if columns.chars {
+                counts.chars += self.chars_in(&line)?;
+            }
+            line.clear();
+        }
+        Ok(counts)
+    }
+
+    /// Counts bytes without holding the input in memory. A regular file with
+    /// no input adapters is measured from its metadata. Anything else is
+    /// streamed through a fixed buffer, unless an option needs the whole
+    /// decoded text first.
+    pub fn count_bytes(&self) -> Result<usize, io::Error> {
+        if self.needs_whole_input() {
+            let input_data = self.read_input()?;
+            return Ok(self.bytes_of(&input_data));
+        }
+        if let Source::File(path) = &self.source {
+            if !self.base64_decode
+                && !self.tee
+                && self.alert_lines.is_none()
+                && self.options.head.is_none()
+                && !self.decompresses()
+                && !self.hashes()
+            {
+                let mut file = File::open(path)?;
+                let metadata = file.metadata()?;
+                if metadata.is_file() {
+                    let size = metadata.len();
+                    if size % PAGE_SIZE != 0 {
+                        return Ok(size as usize);
+                    }
+                    file.seek(io::SeekFrom::Start(size))?;
+                    let rest = Self::count_bytes_from_reader(self.buffered(file))?;
+                    return Ok(size as usize + rest);
+                }
+                // Opening a FIFO again would block, so reuse this handle.
+                return Self::count_bytes_from_reader(self.buffered(file));
+            }
+        }
+        Self::count_bytes_from_reader(self.buffered(self.open_input()?))
+    }
+
+    /// Whether the input is hashed as it is read, for `--with-hash`.
+    fn hashes(&self) -> bool {
+        #[cfg(feature = "hash")]
+        if self.hash_algo.is_some() {
+            return true;
+        }
+        false
+    }
+
+    /// Whether the input is gunzipped before counting: with `--decompress`, or
+    /// for a file named `*.gz`. `--compression-ratio` always sees the raw
+    /// bytes, as it measures both sizes itself.
+    fn decompresses(&self) -> bool {
+        #[cfg(feature = "gzip")]
+        if self.count_type != CountType::CompressionRatio {
+            let named_gz = matches!(
+                &self.source,
+                Source::File(path) if path.to_string_lossy().ends_with(".gz")
+            );
+            return self.decompress || named_gz;
+        }
+        false
+    }
+
+    /// Whether the counts depend on more than the raw byte stream, so the
+    /// input has to go through `read_input`.
+    fn needs_whole_input(&self) -> bool {
+        #[cfg(feature = "normalize")]
+        if self.options.normalization.is_some() {
+            return true;
+        }
+        self.line_numbers_from.is_some()
+            || self.line_parity.is_some()
+            || self.options.encoding.is_some()
+    }
+
+    /// The byte count of the input that `read_input` returned as `text`.
+    /// With `--encoding` that is the size of the raw input, before decoding
+    /// or any line selection.
+    fn bytes_of(&self, text: &str) -> usize {
+        match self.options.encoding {
+            Some(_) => self.raw_len.get(),
+            None => text.len(),
+        }
+    }
+
+    /// Whether words have to be found in decoded text rather than raw bytes.
+    /// Plain whitespace splitting works on bytes, so only these options and
+    /// `-m` need the input to be valid UTF-8.
+    fn needs_text(&self) -> bool {
+        self.needs_whole_input()
+            || self.options.column_range.is_some()
+            || !matches!(self.options.tokenizer, Tokenizer::Whitespace)
+    }
+
+    pub fn count_lines(&self) -> Result<usize, io::Error> {
+        if self.needs_whole_input() {
+            let input_data = self.read_input()?;
+            return Self::count_lines_from_reader(Cursor::new(input_data.as_str()));
+        }
+        Self::count_lines_from_reader(self.buffered(self.open_input()?))
+    }
+
+    pub fn count_words(&self) -> Result<usize, io::Error> {
+        if self.needs_whole_input() {
+            return self.words_in(&self.read_input()?);
+        }
+        if self.needs_text() {
+            let counts = self.count_text_by_line(self.text_lines()?, Columns::WORDS)?;
+            return Ok(counts.words);
+        }
+        Self::count_words_from_reader(self.buffered(self.open_input()?))
+    }
+
+    pub fn count_chars(&self) -> Result<usize, io::Error> {
+        if self.needs_whole_input() {
+            return self.chars_in(&self.read_input()?);
+        }
+        if self.needs_text() {
+            let counts = self.count_text_by_line(self.text_lines()?, Columns::CHARS)?;
+            return Ok(counts.chars);
+        }
+        Ok(self.stream_text_counts(self.stream_text(None)?)?.chars)
+    }
+
+    /// Words in `input`, honouring `--column-range` and the tokenizer.
+    fn words_in(&self, input: &str) -> Result<usize, io::Error> {
+        if let Some(range) = self.options.column_range {
+            return Self::count_words_in_columns_from_reader(
+                Cursor::new(input),
+                range,
+                &self.options.tokenizer,
+            );
+        }
+        Self::count_tokens_from_reader(Cursor::new(input), &self.options.tokenizer)
+    }
+
+    /// Characters in `input`, honouring `--column-range`.
+    fn chars_in(&self, input: &str) -> Result<usize, io::Error> {
+        if let Some(range) = self.options.column_range {
+            return Self::count_chars_in_columns_from_reader(Cursor::new(input), range);
+        }
+        Self::count_chars_from_reader(Cursor::new(input))
+    }
+
+    /// Streams the input through a [`CountState`] so that, if reading fails
+    /// partway, the counts gathered so far are still printed before the error
+    /// is returned.
+    fn count_partial(&self, out: &mut dyn Write, label: &str) -> Result<Counts, CcwcError> {
+        let counted =
+            CountState::count_reader_in_chunks(self.open_input()?, self.options.chunk_size());
+        let (summary, error) = match counted {
+            Ok(summary) => (Counts::from(summary), None),
+            Err(partial) => (Counts::from(partial.partial), Some(partial.error)),
+        };
+        self.report_summary(out, &summary, "", label)?;
+        match error {
+            Some(error) => Err(CcwcError::Partial(error)),
+            None => Ok(summary),
+        }
+    }
+
+    /// Prints one record of results, ended by a newline or, with `--null`, a
+    /// NUL byte. With `--tee` stdout carries the input itself, so results go
+    /// to stderr instead.
+    fn report(&self, out: &mut dyn Write, line: fmt::Arguments) -> io::Result<()> {
+        let terminator = if self.null_terminated { '\0' } else { '\n' };
+        if self.tee {
+            write!(io::stderr(), "{}{}", line, terminator)
+        } else {
+            write!(out, "{}{}", line, terminator)
+        }
+    }
+
+    /// Reports the total line, in bold when `--color` is on for the text
+    /// output.
+    fn report_total(&self, out: &mut dyn Write, line: fmt::Arguments) -> io::Result<()> {
+        if self.color == ColorMode::Always && self.output_format == OutputFormat::Text {
+            self.report(out, format_args!("\x1b[1m{}\x1b[0m", line))
+        } else {
+            self.report(out, line)
+        }
+    }
+
+    /// The `--with-hash` column, including its leading separator, or nothing
+    /// when hashing is off.
+    fn hash_column(&self) -> String {
+        #[cfg(feature = "hash")]
+        if let Some(digest) = self.digest.borrow().as_ref() {
+            return format!(" {}", digest);
+        }
+        String::new()
+    }
+
+    fn format_bytes(&self, bytes: u64) -> String {
+        match self.byte_units {
+            ByteUnits::Exact => bytes.to_string(),
+            ByteUnits::Si => format_si(bytes),
+            ByteUnits::Iec => format_iec(bytes),
+        }
+    }
+
+    /// Reads the whole input as bytes, failing with [`CcwcError::TooLarge`]
+    /// past `--max-memory`.
+    fn read_raw(&self) -> Result<Vec<u8>, io::Error> {
+        let mut buffer = Vec::new();
+        match self.options.max_memory {
+            Some(limit) => {
+                self.open_input()?
+                    .take(limit.saturating_add(1))
+                    .read_to_end(&mut buffer)?;
+                if buffer.len() as u64 > limit {
+                    return Err(io::Error::other(CcwcError::TooLarge { limit }));
+                }
+            }
+            None => {
+                self.open_input()?.read_to_end(&mut buffer)?;
+            }
+        }
+        self.raw_len.set(buffer.len());
+        Ok(buffer)
+    }
+
+    /// Reads the whole input as text, decoded per `--encoding` (UTF-8 by
+    /// default), after the `--normalize` and line filtering passes.
+    fn read_input(&self) -> Result<String, io::Error> {
+        self.decode_input(self.read_raw()?)
+    }
+
+    /// Like [`Counter::read_input`], but fails with [`CcwcError::NotText`]
+    /// when the start of UTF-8 input looks binary, rather than counting
+    /// characters that aren't there.
+    fn read_text(&self) -> Result<String, CcwcError> {
+        let buffer = self.read_raw()?;
+        if self.options.encoding.is_none() && looks_binary(&buffer) {
+            return Err(CcwcError::NotText(self.source.display_name().into_owned()));
+        }
+        Ok(self.decode_input(buffer)?)
+    }
+
+    fn decode_input(&self, buffer: Vec<u8>) -> Result<String, io::Error> {
+        let text = match self.options.encoding {
+            Some(encoding) => encoding.decode(buffer)?,
+            None => String::from_utf8(buffer).map_err(|e| {
+                let location = Utf8Location::of(e.as_bytes(), e.utf8_error().valid_up_to());
+                io::Error::new(io::ErrorKind::InvalidData, location.to_string())
+            })?,
+        };
+        #[cfg(feature = "normalize")]
+        let text = match self.options.normalization {
+            Some(form) => form.apply(&text),
+            None => text,
+        };
+        if let Some(parity) = self.line_parity {
+            return Ok(select_parity(&text, parity));
+        }
+        match self.line_numbers_from {
+            Some(ref path) => {
+                let wanted = read_line_numbers(path)?;
+                let (selected, missing) = select_lines(&text, &wanted);
+                for line_number in missing {
+                    eprintln!(
+                        "{}: warning: input has no line {}",
+                        self.program, line_number
+                    );
+                }
+                Ok(selected)
+            }
+            None => Ok(text),
+        }
+    }
+
+    /// Buffers `reader` for streaming, reading `--read-chunk-size` bytes at a
+    /// time.
+    fn buffered<R: Read>(&self, reader: R) -> io::BufReader<R> {
+        io::BufReader::with_capacity(self.options.chunk_size(), reader)
+    }
+
+    /// The input as text, streamed unless [`Counter::needs_whole_input`].
+    /// Invalid UTF-8 fails as it does in [`Counter::read_input`], and a line
+    /// longer than `line_limit` bytes with [`CcwcError::TooLarge`].
+    fn stream_text(&self, line_limit: Option<u64>) -> Result<Box<dyn BufRead + '_>, io::Error> {
+        if self.needs_whole_input() {
+            return Ok(Box::new(Cursor::new(self.read_input()?)));
+        }
+        let text = CheckedUtf8::new(self.open_input()?, line_limit);
+        Ok(Box::new(self.buffered(text)))
+    }
+
+    /// Like [`Counter::stream_text`], but fails with [`CcwcError::NotText`]
+    /// as [`Counter::read_text`] does. Only the first buffer is sampled, so
+    /// the check holds no more input than counting does.
+    fn stream_checked_text(
+        &self,
+        line_limit: Option<u64>,
+    ) -> Result<Box<dyn BufRead + '_>, CcwcError> {
+        if self.needs_whole_input() {
+            return Ok(Box::new(Cursor::new(self.read_text()?)));
+        }
+        let mut input = self.buffered(self.open_input()?);
+        if looks_binary(input.fill_buf()?) {
+            return Err(CcwcError::NotText(self.source.display_name().into_owned()));
+        }
+        let text = CheckedUtf8::new(input, line_limit);
+        Ok(Box::new(self.buffered(text)))
+    }
+
+    /// The input for the modes that go through it line by line, holding one
+    /// line at a time, so no line may be longer than `--max-memory`.
+    fn text_lines(&self) -> Result<Box<dyn BufRead + '_>, io::Error> {
+        self.stream_text(self.options.max_memory)
+    }
+
+    /// Reading a terminal waits for the user to type the input, which looks
+    /// like a hang, so say how to end it first. With `--no-tty-stdin` a
+    /// terminal on stdin is an error instead.
+    fn check_terminal_stdin(&self, is_terminal: bool, mut err: impl Write) -> io::Result<()> {
+        if !is_terminal {
+            return Ok(());
+        }
+        if self.no_tty_stdin {
+            return Err(io::Error::other(
+                "standard input is a terminal; name a FILE or pipe the input in",
+            ));
+        }
+        let end = if cfg!(windows) {
+            "Ctrl-Z then Enter"
+        } else {
+            "Ctrl-D"
+        };
+        writeln!(
+            err,
+            "{}: reading from terminal; press {} to end",
+            self.program, end
+        )
+    }
+
+    /// Opens the file or stdin as a raw byte stream, with any pass-through
+    /// adapters (`--progress`, gzip, `--base64-decode`, `--head-*`,
+    /// `--alert-lines`, `--tee`) applied. The decoders come after
+    /// `--progress`, so it tracks the bytes actually read, and before the
+    /// rest, so they see the decoded payload.
+    fn open_input(&self) -> Result<Box<dyn Read + '_>, io::Error> {
+        let mut total = None;
+        let mut source: Box<dyn Read + '_> = match &self.source {
+            Source::Stdin { .. } => {
+                self.check_terminal_stdin(io::stdin().is_terminal(), io::stderr())?;
+                Box::new(io::stdin())
+            }
+            Source::File(path) => {
+                let file = File::open(path)?;
+                total = file
+                    .metadata()
+                    .ok()
+                    .filter(|metadata| metadata.is_file())
+                    .map(|metadata| metadata.len());
+                Box::new(file)
+            }
+            Source::Memory(text) => Box::new(Cursor::new(Arc::clone(text))),
+            Source::Reader(reader) => Box::new(reader.clone()),
+        };
+        if self.progress && io::stderr().is_terminal() {
+            let label = self.source.display_name();
+            source = Box::new(Progress::new(source, io::stderr(), &label, total));
+        }
+        #[cfg(feature = "gzip")]
+        if self.decompresses() {
+            source = Box::new(flate2::read::MultiGzDecoder::new(source));
+        }
+        if self.base64_decode {
+            source = Box::new(Base64Decode::new(source));
+        }
+        match self.options.head {
+            Some(Head::Bytes(n)) => source = Box::new(source.take(n)),
+            Some(Head::Lines(n)) => source = Box::new(HeadLines::new(source, n)),
+            None => {}
+        }
+        #[cfg(feature = "hash")]
+        if let Some(algo) = self.hash_algo {
+            source = Box::new(Hashing::new(source, algo, &self.digest));
+        }
+        if let Some(every) = self.alert_lines {
+            source = Box::new(LineAlert::new(source, io::stderr(), every));
+        }
+        if self.tee {
+            source = Box::new(Tee::new(source, io::stdout()));
+        }
+        Ok(source)
+    }
+
+    /// Counts the bytes `reader` yields, consuming its buffer as it goes
+    /// rather than copying it.
+    pub fn count_bytes_from_reader<R: BufRead>(mut reader: R) -> Result<usize, io::Error> {
+        let mut total_bytes = 0;
+        loop {
+            let len = reader.fill_buf()?.len();
+            if len == 0 {
+                return Ok(total_bytes);
+            }
+            total_bytes += len;
+            reader.consume(len);
+        }
+    }
+
+    /// Counts newline bytes, as `wc -l` does. A final line without a
+    /// terminator therefore isn't counted: `"a\nb"` has one line.
+    pub fn count_lines_from_reader<R: BufRead>(mut reader: R) -> Result<usize, io::Error> {
+        let mut count = 0;
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(count);
+            }
+            count += counting::count_newlines(buffer);
+            let len = buffer.len();
+            reader.consume(len);
+        }
+    }
+
+    /// Counts words the way `wc` does in the C locale: a word is a maximal
+    /// run of bytes that are not `isspace` (space, `\t`, `\n`, `\v`, `\f`,
+    /// `\r`). Non-ASCII whitespace such as U+00A0 does not separate words.
+    /// Unlike GNU `wc`, control bytes are ordinary word bytes rather than
+    /// being ignored.
+    ///
+    /// ```
+    /// use gfwc::Counter;
+    ///
+    /// let words = Counter::count_words_from_reader("one\x0btwo\u{a0}three\n".as_bytes());
+    /// assert_eq!(words.unwrap(), 2);
+    /// ```
+    pub fn count_words_from_reader<R: BufRead>(mut reader: R) -> Result<usize, io::Error> {
+        let mut counter = WordCounter::new();
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            counter.push(buffer);
+            let consumed = buffer.len();
+            reader.consume(consumed);
+        }
+        Ok(counter.finish())
+    }
+
+    /// Counts Unicode scalar values, decoding a buffer at a time. A character
+    /// split across two reads is counted once, and bytes that aren't valid
+    /// UTF-8 are not counted, as with [`CountState`].
+    pub fn count_chars_from_reader<R: BufRead>(mut reader: R) -> Result<usize, io::Error> {
+        let mut state = CountState::new();
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(state.finish().chars as usize);
+            }
+            state.update(buffer);
+            let len = buffer.len();
+            reader.consume(len);
+        }
+    }
+
+    /// Tallies lines that parse as JSON versus those that don't. Blank lines
+    /// are skipped rather than counted as invalid.
+    #[cfg(feature = "json")]
+    fn count_json_lines_from_reader<R: BufRead>(reader: R) -> Result<JsonLineCounts, io::Error> {
+        let mut counts = JsonLineCounts::default();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if serde_json::from_str::<serde::de::IgnoredAny>(&line).is_ok() {
+                counts.valid += 1;
+            } else {
+                counts.invalid += 1;
+                counts.invalid_lines.push(index + 1);
+            }
+        }
+        Ok(counts)
+    }
+
+    fn count_tokens_from_reader<R: BufRead>(
+        reader: R,
+        tokenizer: &Tokenizer,
+    ) -> Result<usize, io::Error> {
+        match tokenizer {
+            Tokenizer::Whitespace => Self::count_words_from_reader(reader),
+            #[cfg(feature = "regex")]
+            Tokenizer::Regex(_) => {
+                let mut count = 0;
+                for line in reader.lines() {
+                    count += tokenizer.count(&line?);
+                }
+                Ok(count)
+            }
+            #[cfg(feature = "segmentation")]
+            Tokenizer::UnicodeWords => Self::count_unicode_words_from_reader(reader),
+            Tokenizer::Normalized => {
+                let mut count = 0;
+                for line in reader.lines() {
+                    count += tokenizer.count(&line?);
+                }
+                Ok(count)
+            }
+        }
+    }
+
+    /// Counts words found by Unicode word segmentation, for `--words-unicode`.
+    /// Unlike [`Counter::count_words_from_reader`], punctuation on its own is
+    /// not a word, `café-au-lait` is three words and `世界` is two.
+    #[cfg(feature = "segmentation")]
+    pub fn count_unicode_words_from_reader<R: BufRead>(reader: R) -> Result<usize, io::Error> {
+        let mut count = 0;
+        for line in reader.lines() {
+            count += Tokenizer::UnicodeWords.count(&line?);
+        }
+        Ok(count)
+    }
+
+    /// Counts extended grapheme clusters, for `--graphemes`. No cluster spans
+    /// a newline other than `\r\n`, which stays within its line, so the
+    /// input is segmented a line at a time.
+    #[cfg(feature = "segmentation")]
+    pub fn count_graphemes_from_reader<R: BufRead>(mut reader: R) -> Result<usize, io::Error> {
+        use unicode_segmentation::UnicodeSegmentation;
+        let mut count = 0;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            count += line.graphemes(true).count();
+            line.clear();
+        }
+        Ok(count)
+    }
+
+    fn count_words_in_columns_from_reader<R: BufRead>(
+        reader: R,
+        range: ColumnRange,
+        tokenizer: &Tokenizer,
+    ) -> Result<usize, io::Error> {
+        let mut count = 0;
+        for line in reader.lines() {
+            count += tokenizer.count(range.slice(&line?));
+        }
+        Ok(count)
+    }
+
+    fn count_chars_in_columns_from_reader<R: BufRead>(
+        reader: R,
+        range: ColumnRange,
+    ) -> Result<usize, io::Error> {
+        let mut count = 0;
+        for line in reader.lines() {
+            count += range.slice(&line?).chars().count();
+        }
+        Ok(count)
+    }
+
+    /// Length of the longest run of consecutive blank (empty or
+    /// whitespace-only) lines.
+    fn count_max_blank_run_from_reader<R: BufRead>(reader: R) -> Result<usize, io::Error> {
+        let mut current = 0;
+        let mut longest = 0;
+        for line in reader.lines() {
+            if line?.trim().is_empty() {
+                current += 1;
+                longest = longest.max(current);
+            } else {
+                current = 0;
+            }
+        }
+        Ok(longest)
+    }
+
+    /// Net open count (openers minus closers) for each bracket type. This is
+    /// a plain tally: ordering and nesting are not checked, and brackets inside
+    /// strings or comments count like any other.
+    fn count_bracket_balance_from_reader<R: BufRead>(
+        reader: R,
+    ) -> Result<BracketBalance, io::Error> {
+        let mut balance = BracketBalance::default();
+        for_each_char(reader, |c| match c {
+            '(' => balance.parens += 1,
+            ')' => balance.parens -= 1,
+            '[' => balance.brackets += 1,
+            ']' => balance.brackets -= 1,
+            '{' => balance.braces += 1,
+            '}' => balance.braces -= 1,
+            _ => {}
+        })?;
+        Ok(balance)
+    }
+
+    /// Number of characters in each Unicode general category, keyed by the
+    /// two-letter abbreviation (`Lu`, `Nd`, `Zs`, ...). Categories with no
+    /// characters are omitted.
+    #[cfg(feature = "categories")]
+    fn count_by_category_from_reader<R: BufRead>(
+        reader: R,
+    ) -> Result<BTreeMap<&'static str, usize>, io::Error> {
+        let mut tally = BTreeMap::new();
+        for_each_char(reader, |c| {
+            let category = unicode_general_category::get_general_category(c);
+            *tally.entry(category.abbreviation()).or_insert(0) += 1;
+        })?;
+        Ok(tally)
+    }
+
+    /// Number of blank lines after the last non-blank line. The newline that
+    /// terminates the last line is not itself a blank line, so `"a\n"` has none
+    /// while `"a\n\n"` has one.
+    fn count_trailing_blank_lines_from_reader<R: BufRead>(reader: R) -> Result<usize, io::Error> {
+        let mut trailing = 0;
+        for line in reader.lines() {
+            if line?.trim().is_empty() {
+                trailing += 1;
+            } else {
+                trailing = 0;
+            }
+        }
+        Ok(trailing)
+    }
+
+    /// Compressed (raw) and decompressed byte sizes of the input. Gzip data is
+    /// recognised by its magic bytes rather than the file name, so piped input
+    /// works too; anything else reports no decompressed size.
+    #[cfg(feature = "gzip")]
+    fn measure_compression<R: BufRead>(mut reader: R) -> Result<CompressionSizes, io::Error> {
+        let is_gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+        let mut raw = ByteCounter::new(reader);
+        let decompressed = if is_gzip {
+            let mut decoder = flate2::bufread::MultiGzDecoder::new(&mut raw);
+            Some(io::copy(&mut decoder, &mut io::sink())?)
+        } else {
+            io::copy(&mut raw, &mut io::sink())?;
+            None
+        };
+        Ok(CompressionSizes {
+            compressed: raw.count,
+            decompressed,
+        })
+    }
+
+    /// 1-based numbers of lines whose leading indentation has a tab somewhere
+    /// after a space, a common sign of mixed indentation.
+    fn find_tab_after_space_from_reader<R: BufRead>(reader: R) -> Result<Vec<usize>, io::Error> {
+        let mut offending = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let indent = line.trim_start_matches([' ', '\t']);
+            let indent = &line[..line.len() - indent.len()];
+            if indent
+                .find(' ')
+                .is_some_and(|space| indent[space..].contains('\t'))
+            {
+                offending.push(index + 1);
+            }
+        }
+        Ok(offending)
+    }
+
+    fn count_plane_chars_from_reader<R: BufRead>(
+        reader: R,
+        plane: u32,
+    ) -> Result<usize, io::Error> {
+        let mut count = 0;
+        for_each_char(reader, |c| count += usize::from(c as u32 >> 16 == plane))?;
+        Ok(count)
+    }
+
+    /// Length of the run of `0x00` bytes at the very end of the input. Works on
+    /// raw bytes, so the input need not be valid UTF-8.
+    fn count_trailing_nulls_from_reader<R: BufRead>(mut reader: R) -> Result<usize, io::Error> {
+        let mut run = 0;
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            match buffer.iter().rposition(|&b| b != 0) {
+                Some(last) => run = buffer.len() - last - 1,
+                None => run += buffer.len(),
+            }
+            let consumed = buffer.len();
+            reader.consume(consumed);
+        }
+        Ok(run)
+    }
+
+    /// How many times each different line occurs, without its `\n` or
+    /// `\r\n`. A final line is the same whether or not it ends in a newline,
+    /// and a trailing newline doesn't start another, empty line. Lines are
+    /// compared as raw bytes, so the input need not be valid UTF-8. Every
+    /// different line is held in memory, so input with many distinct lines
+    /// takes about as much memory as its size, and fails with
+    /// [`CcwcError::TooLarge`] once they add up to more than `--max-memory`.
+    fn tally_lines_from_reader<R: BufRead>(
+        reader: R,
+        options: &CountOptions,
+    ) -> Result<HashMap<Vec<u8>, usize>, io::Error> {
+        let mut tally = HashMap::new();
+        let mut held = 0u64;
+        for line in reader.split(b'\n') {
+            let mut line = line?;
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            if let Some(seen) = tally.get_mut(&line) {
+                *seen += 1;
+                continue;
+            }
+            held += line.len() as u64;
+            if let Some(limit) = options.max_memory.filter(|&limit| held > limit) {
+                return Err(io::Error::other(CcwcError::TooLarge { limit }));
+            }
+            tally.insert(line, 1);
+        }
+        Ok(tally)
+    }
+
+    /// Tallies each kind of line terminator in the raw bytes. A `\r` that
+    /// ends one buffer is held until the next shows whether a `\n` follows
+    /// it, and one at the very end is a lone `\r`.
+    fn count_line_endings_from_reader<R: BufRead>(mut reader: R) -> Result<LineEndings, io::Error> {
+        let mut endings = LineEndings::default();
+        let mut after_cr = false;
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            for &byte in buffer {
+                match byte {
+                    b'\n' if after_cr => endings.crlf += 1,
+                    b'\n' => endings.lf += 1,
+                    _ if after_cr => endings.cr += 1,
+                    _ => {}
+                }
+                after_cr = byte == b'\r';
+            }
+            let consumed = buffer.len();
+            reader.consume(consumed);
+        }
+        if after_cr {
+            endings.cr += 1;
+        }
+        Ok(endings)
+    }
+
+    /// Number of different line lengths, excluding line terminators. A
+    /// fixed-width file reports 1.
+    fn count_distinct_lengths_from_reader<R: BufRead>(
+        reader: R,
+        unit: LengthUnit,
+    ) -> Result<usize, io::Error> {
+        let mut lengths = HashSet::new();
+        for line in reader.lines() {
+            lengths.insert(unit.measure(&line?));
+        }
+        Ok(lengths.len())
+    }
+
+    /// Bytes that stripping trailing whitespace from every line would remove.
+    /// Line terminators (`\n` or `\r\n`) are kept, so they don't count.
+    fn count_trim_savings_from_reader<R: BufRead>(reader: R) -> Result<usize, io::Error> {
+        let mut savings = 0;
+        for line in reader.lines() {
+            savings += trailing_whitespace_len(&line?);
+        }
+        Ok(savings)
+    }
+
+    /// Number of times the indentation width differs from the previous line's,
+    /// with tab stops every `tab_width` columns. Blank lines are skipped unless
+    /// `include_blank` is set, in which case they count as width 0.
+    fn count_indent_changes_from_reader<R: BufRead>(
+        reader: R,
+        include_blank: bool,
+        tab_width: usize,
+    ) -> Result<usize, io::Error> {
+        let mut changes = 0;
+        let mut previous = None;
+        for line in reader.lines() {
+            let line = line?;
+            let width = if line.trim().is_empty() {
+                if !include_blank {
+                    continue;
+                }
+                0
+            } else {
+                indent_width(&line, tab_width)
+            };
+            if previous.is_some_and(|previous| previous != width) {
+                changes += 1;
+            }
+            previous = Some(width);
+        }
+        Ok(changes)
+    }
+
+    /// Counts lines whose letters are all uppercase and lines whose letters are
+    /// all lowercase. Non-letters are ignored, and lines without any letters
+    /// fall in neither bucket.
+    fn count_case_lines_from_reader<R: BufRead>(reader: R) -> Result<(usize, usize), io::Error> {
+        let mut upper = 0;
+        let mut lower = 0;
+        for line in reader.lines() {
+            let line = line?;
+            let mut letters = line.chars().filter(|c| c.is_alphabetic()).peekable();
+            if letters.peek().is_none() {
+                continue;
+            }
+            let (mut all_upper, mut all_lower) = (true, true);
+            for c in letters {
+                all_upper &= c.is_uppercase();
+                all_lower &= c.is_lowercase();
+            }
+            upper += usize::from(all_upper);
+            lower += usize::from(all_lower);
+        }
+        Ok((upper, lower))
+    }
+
+    /// Counts the lines for which `predicate` holds, streaming one line at a
+    /// time. A final line without a newline is still a line here, unlike in
+    /// `-l`.
+    fn count_lines_where_from_reader<R: BufRead>(
+        reader: R,
+        predicate: impl Fn(&str) -> bool,
+    ) -> Result<usize, io::Error> {
+        let mut count = 0;
+        for line in reader.lines() {
+            count += usize::from(predicate(&line?));
+        }
+        Ok(count)
+    }
+
+    /// Tallies the first word of each line, like
+    /// `awk '{print $1}' | sort | uniq -c | sort -rn`. Words are split as in
+    /// `-w`, and blank lines have no first word. Most frequent first; equal
+    /// counts are ordered by token.
+    fn count_first_token_freq_from_reader<R: BufRead>(
+        reader: R,
+    ) -> Result<Vec<(String, usize)>, io::Error> {
+        let mut tally: BTreeMap<String, usize> = BTreeMap::new();
+        for line in reader.lines() {
+            let line = line?;
+            let first = line
+                .split(|c: char| c.is_ascii() && is_c_space(c as u8))
+                .find(|token| !token.is_empty());
+            if let Some(token) = first {
+                *tally.entry(token.to_string()).or_insert(0) += 1;
+            }
+        }
+        let mut freq: Vec<(String, usize)> = tally.into_iter().collect();
+        freq.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        Ok(freq)
+    }
+
+    /// How often each word occurs, with words split as in `-w` (or by
+    /// `tokenizer`). Words are compared exactly, so the count is
+    /// case-sensitive: `The` and `the` are different words.
+    fn count_word_freq_from_reader<R: BufRead>(
+        reader: R,
+        tokenizer: &Tokenizer,
+    ) -> Result<HashMap<String, usize>, io::Error> {
+        let mut tally = HashMap::new();
+        for line in reader.lines() {
+            for word in tokenizer.tokens(&line?) {
+                *tally.entry(word.into_owned()).or_insert(0) += 1;
+            }
+        }
+        Ok(tally)
+    }
+
+    /// Length in characters and 1-based number of the shortest line that is
+    /// not blank, the first one winning ties. `None` if every line is blank.
+    fn find_shortest_line_from_reader<R: BufRead>(
+        reader: R,
+    ) -> Result<Option<(usize, usize)>, io::Error> {
+        let mut shortest: Option<(usize, usize)> = None;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let length = line.chars().count();
+            if shortest.is_none_or(|(min, _)| length < min) {
+                shortest = Some((length, index + 1));
+            }
+        }
+        Ok(shortest)
+    }
+
+    /// Number of distinct input words found in `vocab`, and the number of
+    /// distinct input words. With `ignore_case` words are lowercased before
+    /// comparing, and `vocab` is expected to be lowercased already.
+    fn measure_vocab_coverage_from_reader<R: BufRead>(
+        reader: R,
+        vocab: &HashSet<String>,
+        tokenizer: &Tokenizer,
+        ignore_case: bool,
+    ) -> Result<(usize, usize), io::Error> {
+        let mut distinct = HashSet::new();
+        for line in reader.lines() {
+            for word in tokenizer.tokens(&line?) {
+                distinct.insert(fold_case(&word, ignore_case));
+            }
+        }
+        let matched = distinct.iter().filter(|word| vocab.contains(*word)).count();
+        Ok((matched, distinct.len()))
+    }
+
+    /// Display width of the widest line, not counting its `\n` or `\r\n`
+    /// terminator. A tab advances to the next multiple of the options' tab
+    /// width, which GNU `wc -L` fixes at 8. Every other character takes one
+    /// column, so wide CJK characters and zero-width combining marks are not
+    /// treated specially.
+    fn count_max_line_length_from_reader<R: BufRead>(
+        reader: R,
+        options: &CountOptions,
+    ) -> Result<usize, io::Error> {
+        let tab_width = options.tab_stop();
+        let (mut max, mut column) = (0, 0);
+        // A carriage return only counts if it doesn't end the line.
+        let mut carriage_return = false;
+        for_each_char(reader, |c| {
+            if std::mem::take(&mut carriage_return) && c != '\n' {
+                column += 1;
+            }
+            match c {
+                '\n' => max = max.max(std::mem::take(&mut column)),
+                '\r' => carriage_return = true,
+                '\t' => column += tab_width - column % tab_width,
+                _ => column += 1,
+            }
+        })?;
+        Ok(max.max(column + usize::from(carriage_return)))
+    }
+
+    /// Shortest, longest and mean line width in one pass, with widths as in
+    /// [`Counter::count_max_line_length_from_reader`]. A final line without
+    /// a newline is included. Empty input has no lines, and every figure is
+    /// 0.
+    fn measure_line_stats_from_reader<R: BufRead>(
+        reader: R,
+        options: &CountOptions,
+    ) -> Result<LineStats, io::Error> {
+        let mut stats = LineStats::default();
+        for line in reader.lines() {
+            let width = line_width(&line?, options.tab_stop());
+            stats.min = if stats.lines == 0 {
+                width
+            } else {
+                stats.min.min(width)
+            };
+            stats.max = stats.max.max(width);
+            stats.total += width;
+            stats.lines += 1;
+        }
+        Ok(stats)
+    }
+
+    /// Number of paragraphs: a paragraph starts at each non-blank line that
+    /// follows a blank line or the start of the input. Blank (empty or
+    /// whitespace-only) lines at either end, or several in a row, never make
+    /// an empty paragraph.
+    fn count_paragraphs_from_reader<R: BufRead>(reader: R) -> Result<usize, io::Error> {
+        let mut count = 0;
+        let mut after_blank = true;
+        for line in reader.lines() {
+            let blank = line?.trim().is_empty();
+            if !blank && after_blank {
+                count += 1;
+            }
+            after_blank = blank;
+        }
+        Ok(count)
+    }
+
+    /// Counts bytes, lines, words and characters in `input_data`, which is
+    /// expected to be valid UTF-8: characters are counted as the bytes that
+    /// don't continue a sequence. Newlines and characters are counted with
+    /// SIMD by `bytecount`, leaving only the word state to walk byte by byte.
+    /// Words follow the options' tokenizer, so anything but whitespace
+    /// splitting takes a second pass.
+    fn count_all_from_input(
+        input_data: &[u8],
+        options: &CountOptions,
+    ) -> Result<Counts, io::Error> {
+        let mut counts = Counts {
+            bytes: input_data.len(),
+            lines: counting::count_newlines(input_data),
+            words: counting::count_words(input_data),
+            chars: bytecount::num_chars(input_data),
+            ..Counts::default()
+        };
+        if !matches!(options.tokenizer, Tokenizer::Whitespace) {
+            counts.words =
+                Self::count_tokens_from_reader(Cursor::new(input_data), &options.tokenizer)?;
+        }
+        Ok(counts)
+    }
+}
+
+/// Orders a word tally most frequent first, equal counts alphabetically.
+fn sort_by_frequency(tally: HashMap<String, usize>) -> Vec<(String, usize)> {
+    let mut freq: Vec<(String, usize)> = tally.into_iter().collect();
+    freq.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    freq
+}
+
+/// The columns of a `--csv` header such as `lines,words,bytes,filename`:
+/// count names in output order, then `filename`. Anything else isn't a
+/// header gfwc writes.
+fn parse_csv_header(header: &str) -> Option<Columns> {
+    let names = header.strip_suffix("filename")?;
+    let mut columns = Columns::NONE;
+    for name in names.split_terminator(',') {
+        columns = columns.union(match name {
+            "lines" => Columns::LINES,
+            "words" => Columns::WORDS,
+            "chars" => Columns::CHARS,
+            "bytes" => Columns::BYTES,
+            _ => return None,
+        });
+    }
+    let expected: Vec<&str> = columns.names().chain(["filename"]).collect();
+    (columns != Columns::NONE && expected.join(",") == header).then_some(columns)
+}
+
+/// The counts and file name of each row of `--csv` output after its
+/// header, for `columns`. A quoted file name may hold commas, doubled
+/// quotes and line breaks.
+fn parse_csv_rows(mut rows: &str, columns: Columns) -> Result<Vec<(Counts, String)>, String> {
+    let mut parsed = Vec::new();
+    while !rows.is_empty() {
+        let row = parsed.len() + 1;
+        let mut counts = Counts::default();
+        for name in columns.names() {
+            let (field, rest) = rows
+                .split_once(',')
+                .ok_or_else(|| format!("row {} is missing the {} column", row, name))?;
+            let count = field
+                .parse()
+                .map_err(|_| format!("row {}: {:?} is not a {} count", row, field, name))?;
+            match name {
+                "lines" => counts.lines = count,
+                "words" => counts.words = count,
+                "chars" => counts.chars = count,
+                _ => counts.bytes = count,
+            }
+            rows = rest;
+        }
+        let name = match rows.strip_prefix('"') {
+            Some(quoted) => {
+                let mut name = String::new();
+                let mut rest = quoted;
+                loop {
+                    let (part, after) = rest
+                        .split_once('"')
+                        .ok_or_else(|| format!("row {} has an unterminated file name", row))?;
+                    name.push_str(part);
+                    match after.strip_prefix('"') {
+                        Some(after) => {
+                            name.push('"');
+                            rest = after;
+                        }
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+                rows = match rest.strip_prefix('\n') {
+                    Some(rest) => rest,
+                    None if rest.is_empty() => rest,
+                    None => return Err(format!("row {} has text after its file name", row)),
+                };
+                name
+            }
+            None => {
+                let (name, rest) = rows.split_once('\n').unwrap_or((rows, ""));
+                rows = rest;
+                name.to_string()
+            }
+        };
+        parsed.push((counts, name));
+    }
+    Ok(parsed)
+}
+
+/// Quotes `field` for CSV when it contains a comma, quote or line break,
+/// doubling any quotes inside, as RFC 4180 describes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Joins the count columns, then the hash and label when present, with
+/// `separator`. `hash` and `label` arrive with a leading space, which the
+/// separator replaces.
+fn join_columns(fields: &[String], hash: &str, label: &str, separator: &str) -> String {
+    let extras = [hash, label]
+        .into_iter()
+        .filter(|extra| !extra.is_empty())
+        .map(|extra| extra.strip_prefix(' ').unwrap_or(extra));
+    let columns: Vec<&str> = fields.iter().map(String::as_str).chain(extras).collect();
+    columns.join(separator)
+}
+
+/// Renders a byte count with 1000-based units, rounded to one decimal place.
+/// Counts below 1000 are printed unchanged.
+fn format_si(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["kB", "MB", "GB", "TB", "PB", "EB"];
+    if bytes < 1000 {
+        return bytes.to_string();
+    }
+    let mut value = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while value >= 999.95 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+/// How much of the input [`looks_binary`] samples.
+const BINARY_SAMPLE_LEN: usize = 8192;
+
+/// Whether the first few KB of `input` hold a NUL byte or invalid UTF-8. A
+/// character cut off by the end of the sample doesn't count.
+fn looks_binary(input: &[u8]) -> bool {
+    let sample = &input[..input.len().min(BINARY_SAMPLE_LEN)];
+    sample.contains(&0) || std::str::from_utf8(sample).is_err_and(|e| e.error_len().is_some())
+}
+
+/// Renders a byte count with 1024-based units, rounded to one decimal place.
+/// Counts below 1024 are printed unchanged.
+fn format_iec(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1023.95 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+/// Average number of bytes per character, to two decimal places. Pure ASCII
+/// input gives `1.00`; empty input gives `0` rather than dividing by zero.
+fn format_bytes_per_char(bytes: usize, chars: usize) -> String {
+    if chars == 0 {
+        return "0".to_string();
+    }
+    format!("{:.2}", bytes as f64 / chars as f64)
+}
+
+/// Passes each character of the UTF-8 text in `reader` to `f`, decoding it
+/// a buffer at a time, so nothing is held between buffers but a partial
+/// character.
+fn for_each_char<R: BufRead>(mut reader: R, mut f: impl FnMut(char)) -> Result<(), io::Error> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        )
+    };
+    let mut decoder = Utf8Decoder::default();
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        for &byte in buffer {
+            match decoder.push(byte) {
+                (false, Decoded::Char(c)) => f(c),
+                (false, Decoded::Pending) => {}
+                _ => return Err(invalid()),
+            }
+        }
+        let len = buffer.len();
+        reader.consume(len);
+    }
+    if decoder.finish() {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// Display width of `line`, with tabs advancing to the next multiple of
+/// `tab_width`.
+fn line_width(line: &str, tab_width: usize) -> usize {
+    line.chars().fold(0, |column, c| match c {
+        '\t' => column + tab_width - column % tab_width,
+        _ => column + 1,
+    })
+}
+
+/// Line widths gathered for `--line-stats`.
+#[derive(Debug, Default, PartialEq)]
+struct LineStats {
+    lines: usize,
+    min: usize,
+    max: usize,
+    total: usize,
+}
+
+/// Renders `min=.. max=.. avg=..`, the mean to two decimal places. Empty
+/// input prints `avg=0` rather than dividing by zero.
+impl fmt::Display for LineStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "min={} max={} avg=", self.min, self.max)?;
+        if self.lines == 0 {
+            return f.write_str("0");
+        }
+        write!(f, "{:.2}", self.total as f64 / self.lines as f64)
+    }
+}
+
+/// Line terminators counted by `--line-endings`.
+#[derive(Debug, Default, PartialEq)]
+struct LineEndings {
+    lf: usize,
+    crlf: usize,
+    cr: usize,
+}
+
+/// Renders `LF:.. CRLF:.. CR:..`.
+impl fmt::Display for LineEndings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LF:{} CRLF:{} CR:{}", self.lf, self.crlf, self.cr)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct BracketBalance {
+    parens: i64,
+    brackets: i64,
+    braces: i64,
+}
+
+impl BracketBalance {
+    fn is_balanced(&self) -> bool {
+        self.parens == 0 && self.brackets == 0 && self.braces == 0
+    }
+}
+
+/// Where a [`Counter`] reads the input it is counting.
+#[derive(Clone)]
+enum Source {
+    /// Standard input, `named` when it was given as `-` and so is labelled
+    /// like a file.
+    Stdin {
+        named: bool,
+    },
+    File(PathBuf),
+    /// Text given to `Counter::from(&str)`. Unlike a reader, it can be
+    /// counted more than once.
+    Memory(Arc<[u8]>),
+    /// Input given to [`Counter::from_reader`].
+    Reader(SharedReader),
+}
+
+impl Source {
+    /// The source for a FILE argument, where `-` means stdin.
+    fn from_path(path: &str) -> Source {
+        match path {
+            STDIN_PATH => Source::Stdin { named: true },
+            path => Source::File(PathBuf::from(path)),
+        }
+    }
+
+    /// The name results and errors are labelled with. Like `wc`, stdin has
+    /// none unless it was named, and nor does input supplied in code.
+    fn name(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Source::Stdin { named: true } => Some(Cow::Borrowed(STDIN_PATH)),
+            Source::File(path) => Some(path.to_string_lossy()),
+            _ => None,
+        }
+    }
+
+    /// The name to use where one is needed, with `-` for unnamed input.
+    fn display_name(&self) -> Cow<'_, str> {
+        self.name().unwrap_or(Cow::Borrowed(STDIN_PATH))
+    }
+}
+
+/// A caller-supplied input, shared so that a [`Counter`] stays cloneable.
+/// Every handle reads from the same underlying stream.
+#[derive(Clone)]
+struct SharedReader(Arc<Mutex<Box<dyn Read + Send>>>);
+
+impl Read for SharedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|_| io::Error::other("input reader was poisoned by a panic"))?
+            .read(buf)
+    }
+}
+
+/// Reader adapter that copies everything read through it to `out`, flushing
+/// after each chunk so downstream pipeline stages see data as it arrives.
+struct Tee<R, W> {
+    inner: R,
+    out: W,
+}
+
+impl<R: Read, W: Write> Tee<R, W> {
+    fn new(inner: R, out: W) -> Self {
+        Tee { inner, out }
+    }
+}
+
+impl<R: Read, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.out.write_all(&buf[..n])?;
+        self.out.flush()?;
+        Ok(n)
+    }
+}
+
+/// Reader adapter that hashes everything read through it for `--with-hash`,
+/// storing the digest in `digest` once `inner` is exhausted. The counts
+/// stream on as they would without it.
+#[cfg(feature = "hash")]
+struct Hashing<'a, R> {
+    inner: R,
+    hasher: Option<Hasher>,
+    digest: &'a RefCell<Option<String>>,
+}
+
+#[cfg(feature = "hash")]
+impl<'a, R: Read> Hashing<'a, R> {
+    fn new(inner: R, algo: HashAlgo, digest: &'a RefCell<Option<String>>) -> Self {
+        Hashing {
+            inner,
+            hasher: Some(algo.hasher()),
+            digest,
+        }
+    }
+}
+
+#[cfg(feature = "hash")]
+impl<R: Read> Read for Hashing<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            if let Some(hasher) = self.hasher.take() {
+                *self.digest.borrow_mut() = Some(hasher.finish());
+            }
+        } else if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// How much of the start of each input `--head-bytes` or `--head-lines`
+/// counts.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Head {
+    Bytes(u64),
+    Lines(usize),
+}
+
+/// Reader adapter that ends the input after its first `lines` newlines,
+/// without reading any further from `inner`.
+struct HeadLines<R> {
+    inner: R,
+    lines_left: usize,
+}
+
+impl<R: Read> HeadLines<R> {
+    fn new(inner: R, lines: usize) -> Self {
+        HeadLines {
+            inner,
+            lines_left: lines,
+        }
+    }
+}
+
+impl<R: Read> Read for HeadLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.lines_left == 0 {
+            return Ok(0);
+        }
+        let n = self.inner.read(buf)?;
+        // The rest of the buffer, after the last newline wanted, is dropped.
+        let mut end = n;
+        for (i, _) in buf[..n].iter().enumerate().filter(|(_, &b)| b == b'\n') {
+            self.lines_left -= 1;
+            if self.lines_left == 0 {
+                end = i + 1;
+                break;
+            }
+        }
+        Ok(end)
+    }
+}
+
+/// Reader adapter that passes UTF-8 text through, failing at the first
+/// invalid sequence with its [`Utf8Location`], and at the first line longer
+/// than `line_limit` bytes with [`CcwcError::TooLarge`]. The bytes before
+/// the failure are still returned first.
+struct CheckedUtf8<R> {
+    inner: R,
+    line_limit: Option<u64>,
+    decoder: Utf8Decoder,
+    /// Where the next byte goes, and how many bytes of the character being
+    /// decoded came before it.
+    location: Utf8Location,
+    pending: usize,
+    line_len: u64,
+    error: Option<io::Error>,
+}
+
+impl<R: Read> CheckedUtf8<R> {
+    fn new(inner: R, line_limit: Option<u64>) -> Self {
+        CheckedUtf8 {
+            inner,
+            line_limit,
+            decoder: Utf8Decoder::default(),
+            location: Utf8Location {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+            pending: 0,
+            line_len: 0,
+            error: None,
+        }
+    }
+
+    /// The error for an invalid sequence starting `pending` bytes back.
+    fn invalid(&self) -> io::Error {
+        let location = Utf8Location {
+            offset: self.location.offset - self.pending,
+            ..self.location
+        };
+        io::Error::new(io::ErrorKind::InvalidData, location.to_string())
+    }
+
+    /// Checks `byte`, failing if it ends the valid text.
+    fn check(&mut self, byte: u8) -> io::Result<()> {
+        match self.decoder.push(byte) {
+            (true, _) | (_, Decoded::Invalid) => return Err(self.invalid()),
+            (false, Decoded::Pending) => self.pending += 1,
+            (false, Decoded::Char(c)) => {
+                self.pending = 0;
+                if c == '\n' {
+                    self.location.line += 1;
+                    self.location.column = 1;
+                    self.line_len = 0;
+                } else {
+                    self.location.column += 1;
+                }
+            }
+        }
+        self.location.offset += 1;
+        if byte != b'\n' {
+            self.line_len += 1;
+            if let Some(limit) = self.line_limit.filter(|&limit| self.line_len > limit) {
+                return Err(io::Error::other(CcwcError::TooLarge { limit }));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for CheckedUtf8<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        let n = self.inner.read(buf)?;
+        if n == 0 && self.decoder.finish() {
+            return Err(self.invalid());
+        }
+        for (i, &byte) in buf[..n].iter().enumerate() {
+            if let Err(error) = self.check(byte) {
+                if i == 0 {
+                    return Err(error);
+                }
+                self.error = Some(error);
+                return Ok(i);
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Reader adapter that writes `alert lines=<total>` to `out` each time the
+/// running newline count crosses a multiple of `every`. Alerts are emitted as
+/// data is read, so they fire in real time on a live pipe.
+struct LineAlert<R, W> {
+    inner: R,
+    out: W,
+    every: usize,
+    lines: usize,
+}
+
+impl<R: Read, W: Write> LineAlert<R, W> {
+    fn new(inner: R, out: W, every: usize) -> Self {
+        LineAlert {
+            inner,
+            out,
+            every,
+            lines: 0,
+        }
+    }
+}
+
+impl<R: Read, W: Write> Read for LineAlert<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for _ in buf[..n].iter().filter(|&&b| b == b'\n') {
+            self.lines += 1;
+            if self.lines.is_multiple_of(self.every) {
+                writeln!(self.out, "alert lines={}", self.lines)?;
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// How often [`Progress`] redraws its line.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Reader adapter that keeps a `<label>: <read>/<total> bytes (<percent>%)`
+/// line up to date on `out` as data is read, redrawn in place at most every
+/// [`PROGRESS_INTERVAL`]. Without a known total only the bytes read are shown.
+/// The line is cleared at end of input so that it doesn't linger.
+struct Progress<R, W> {
+    inner: R,
+    out: W,
+    label: String,
+    total: Option<u64>,
+    read: u64,
+    interval: Duration,
+    last_drawn: Option<Instant>,
+}
+
+impl<R: Read, W: Write> Progress<R, W> {
+    fn new(inner: R, out: W, label: &str, total: Option<u64>) -> Self {
+        Progress {
+            inner,
+            out,
+            label: label.to_string(),
+            total,
+            read: 0,
+            interval: PROGRESS_INTERVAL,
+            last_drawn: None,
+        }
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        match self.total {
+            Some(total) => write!(
+                self.out,
+                "\r{}: {}/{} bytes ({}%)",
+                self.label,
+                self.read,
+                total,
+                (self.read * 100).checked_div(total).unwrap_or(100)
+            )?,
+            None => write!(self.out, "\r{}: {} bytes", self.label, self.read)?,
+        }
+        self.out.flush()
+    }
+}
+
+impl<R: Read, W: Write> Read for Progress<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            if self.last_drawn.take().is_some() {
+                // Erase the whole line and return to its start.
+                write!(self.out, "\r\x1b[2K")?;
+                self.out.flush()?;
+            }
+            return Ok(0);
+        }
+        self.read += n as u64;
+        if self
+            .last_drawn
+            .is_none_or(|drawn| drawn.elapsed() >= self.interval)
+        {
+            self.draw()?;
+            self.last_drawn = Some(Instant::now());
+        }
+        Ok(n)
+    }
+}
+
+/// Reader adapter that decodes standard base64 (RFC 4648, `+` and `/`) from
+/// `inner`. Whitespace anywhere in the input is skipped, so line-wrapped
+/// output from `base64` decodes as-is. Trailing `=` padding is optional.
+/// Anything else fails with `InvalidData`, naming the offending byte offset.
+struct Base64Decode<R> {
+    inner: R,
+    /// Sextets of the group being assembled.
+    group: [u8; 4],
+    filled: usize,
+    /// `=` seen in the current group; no more data may follow them.
+    padding: usize,
+    finished: bool,
+    /// Raw input bytes consumed so far, for error messages.
+    offset: u64,
+    decoded: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Base64Decode<R> {
+    fn new(inner: R) -> Self {
+        Base64Decode {
+            inner,
+            group: [0; 4],
+            filled: 0,
+            padding: 0,
+            finished: false,
+            offset: 0,
+            decoded: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn sextet(byte: u8) -> Option<u8> {
+        match byte {
+            b'A'..=b'Z' => Some(byte - b'A'),
+            b'a'..=b'z' => Some(byte - b'a' + 26),
+            b'0'..=b'9' => Some(byte - b'0' + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+
+    fn invalid(&self, what: String) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid base64 at byte {}: {}", self.offset, what),
+        )
+    }
+
+    /// Emits the bytes of the current group, which holds `filled` sextets.
+    fn flush_group(&mut self) -> io::Result<()> {
+        if self.filled == 1 {
+            return Err(self.invalid("truncated group".to_string()));
+        }
+        let [a, b, c, d] = self.group;
+        let bits = (a as u32) << 18 | (b as u32) << 12 | (c as u32) << 6 | d as u32;
+        let bytes = bits.to_be_bytes();
+        self.decoded.extend_from_slice(&bytes[1..self.filled]);
+        self.group = [0; 4];
+        self.filled = 0;
+        Ok(())
+    }
+
+    fn decode_byte(&mut self, byte: u8) -> io::Result<()> {
+        if is_c_space(byte) {
+            return Ok(());
+        }
+        if byte == b'=' {
+            if self.filled < 2 || self.filled + self.padding == 4 {
+                return Err(self.invalid("unexpected padding".to_string()));
+            }
+            self.padding += 1;
+            if self.filled + self.padding == 4 {
+                self.flush_group()?;
+            }
+            return Ok(());
+        }
+        let Some(sextet) = Self::sextet(byte) else {
+            return Err(self.invalid(format!("unexpected byte 0x{:02x}", byte)));
+        };
+        if self.padding > 0 {
+            return Err(self.invalid("data after padding".to_string()));
+        }
+        self.group[self.filled] = sextet;
+        self.filled += 1;
+        if self.filled == 4 {
+            self.flush_group()?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Base64Decode<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut chunk = [0; 4096];
+        while self.pos == self.decoded.len() && !self.finished {
+            self.decoded.clear();
+            self.pos = 0;
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                self.finished = true;
+                if self.padding > 0 && self.filled > 0 {
+                    return Err(self.invalid("incomplete padding".to_string()));
+                }
+                if self.filled > 0 {
+                    self.flush_group()?;
+                }
+            }
+            for &byte in &chunk[..n] {
+                self.decode_byte(byte)?;
+                self.offset += 1;
+            }
+        }
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Expands `\n`, `\r`, `\t` and `\\` in a `--split-at` marker so that a
+/// blank line can be given as `'\n\n'` (or `'\r\n\r\n'` for HTTP headers).
+/// Other backslashes are kept as-is.
+fn unescape_marker(marker: &str) -> String {
+    let mut out = String::with_capacity(marker.len());
+    let mut chars = marker.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Splits `input` around the first occurrence of `marker`, which belongs to
+/// neither half. Without a marker, everything is "before".
+fn split_at_marker<'a>(input: &'a str, marker: &str) -> (&'a str, &'a str) {
+    input.split_once(marker).unwrap_or((input, ""))
+}
+
+#[cfg(feature = "gzip")]
+#[derive(Debug, PartialEq)]
+struct CompressionSizes {
+    compressed: u64,
+    decompressed: Option<u64>,
+}
+
+/// Reader adapter that tallies how many bytes have been read through it.
+#[cfg(feature = "gzip")]
+struct ByteCounter<R> {
+    inner: R,
+    count: u64,
+}
+
+#[cfg(feature = "gzip")]
+impl<R> ByteCounter<R> {
+    fn new(inner: R) -> Self {
+        ByteCounter { inner, count: 0 }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read> Read for ByteCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: BufRead> BufRead for ByteCounter<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+/// Where the first invalid UTF-8 sequence in some input starts. Lines and
+/// columns are 1-based, with columns counted in characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Utf8Location {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Utf8Location {
+    /// `valid_up_to` is the length of the valid prefix, as reported by
+    /// [`std::str::Utf8Error::valid_up_to`].
+    fn of(bytes: &[u8], valid_up_to: usize) -> Utf8Location {
+        let valid = &bytes[..valid_up_to];
+        let line_start = valid.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        // The prefix is valid UTF-8, so counting non-continuation bytes
+        // counts characters.
+        let column = valid[line_start..]
+            .iter()
+            .filter(|&&b| b & 0xC0 != 0x80)
+            .count()
+            + 1;
+        Utf8Location {
+            offset: valid_up_to,
+            line: valid.iter().filter(|&&b| b == b'\n').count() + 1,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for Utf8Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid UTF-8 at line {}, column {} (byte offset {})",
+            self.line, self.column, self.offset
+        )
+    }
+}
+
+/// Replaces each argument holding glob metacharacters with the paths it
+/// matches, for shells that pass `*.txt` through unexpanded. A path that
+/// exists is kept as is, even if it contains `?` or `[`, and so is a pattern
+/// that can't be parsed. A pattern matching nothing is warned about and
+/// dropped.
+#[cfg(feature = "glob")]
+fn expand_globs(paths: Vec<String>, program: &str) -> Vec<String> {
+    let mut expanded = Vec::new();
+    for path in paths {
+        let is_pattern = path.contains(['*', '?', '[']);
+        if !is_pattern || path == STDIN_PATH || std::path::Path::new(&path).exists() {
+            expanded.push(path);
+            continue;
+        }
+        let Ok(matches) = glob::glob(&path) else {
+            expanded.push(path);
+            continue;
+        };
+        let before = expanded.len();
+        expanded.extend(
+            matches
+                .filter_map(Result::ok)
+                .map(|matched| matched.to_string_lossy().into_owned()),
+        );
+        if expanded.len() == before {
+            eprintln!("{}: {}: no matches", program, path);
+        }
+    }
+    expanded
+}
+
+/// Replaces each directory in `paths` with the regular files beneath it, in
+/// sorted order. A symlinked directory found on the way is reported and left
+/// out unless `follow_symlinks` is set, and even then a directory already
+/// visited is skipped, so a link back up the tree can't loop. Symlinks to
+/// files, and directories named in `paths`, are always followed. With a
+/// `max_depth`, subdirectories more than that many levels below a named
+/// directory are left out; 0 keeps only the files directly inside it. A
+/// directory that can't be listed is kept as is, so counting it reports the
+/// error.
+fn expand_directories(
+    paths: Vec<String>,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    program: &str,
+) -> Vec<String> {
+    use std::path::{Path, PathBuf};
+
+    fn walk(
+        dir: &Path,
+        depth: usize,
+        follow_symlinks: bool,
+        max_depth: Option<usize>,
+        program: &str,
+        visited: &mut HashSet<PathBuf>,
+        files: &mut Vec<String>,
+    ) {
+        let Ok(canonical) = std::fs::canonicalize(dir) else {
+            files.push(dir.to_string_lossy().into_owned());
+            return;
+        };
+        if !visited.insert(canonical) {
+            return;
+        }
+        let mut entries: Vec<PathBuf> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .collect(),
+            Err(_) => {
+                files.push(dir.to_string_lossy().into_owned());
+                return;
+            }
+        };
+        entries.sort();
+        for path in entries {
+            match std::fs::metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => {
+                    if max_depth.is_some_and(|max| depth >= max) {
+                        continue;
+                    }
+                    if follow_symlinks || !path.is_symlink() {
+                        walk(
+                            &path,
+                            depth + 1,
+                            follow_symlinks,
+                            max_depth,
+                            program,
+                            visited,
+                            files,
+                        );
+                    } else {
+                        eprintln!(
+                            "{}: {}: not following symlinked directory",
+                            program,
+                            path.display()
+                        );
+                    }
+                }
+                Ok(metadata) if metadata.is_file() => {
+                    files.push(path.to_string_lossy().into_owned())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut visited = HashSet::new();
+    let mut files = Vec::new();
+    for path in paths {
+        if path != STDIN_PATH && Path::new(&path).is_dir() {
+            walk(
+                Path::new(&path),
+                0,
+                follow_symlinks,
+                max_depth,
+                program,
+                &mut visited,
+                &mut files,
+            );
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Loads the NUL-separated paths of `--files0-from`, reading stdin for `-`.
+/// Empty names, such as the one after a trailing NUL, are skipped.
+fn read_files0(list: &str) -> Result<Vec<String>, io::Error> {
+    let contents = if list == STDIN_PATH {
+        let mut contents = Vec::new();
+        io::stdin().read_to_end(&mut contents)?;
+        contents
+    } else {
+        std::fs::read(list)?
+    };
+    Ok(contents
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect())
+}
+
+/// Loads the paths listed in an `@FILE` argument, one per line. Blank lines
+/// and lines starting with `#` are skipped; the rest are taken whole, so
+/// paths may contain spaces.
+fn read_argfile(path: &str) -> Result<Vec<String>, io::Error> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Loads whitespace-separated 1-based line numbers from `path`.
+fn read_line_numbers(path: &str) -> Result<BTreeSet<usize>, io::Error> {
+    let contents = std::fs::read_to_string(path)?;
+    contents
+        .split_whitespace()
+        .map(|number| {
+            number.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid line number '{}' in {}", number, path),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Loads the whitespace-separated words of a `--vocab-coverage` word list.
+fn read_vocabulary(path: &str, ignore_case: bool) -> Result<HashSet<String>, io::Error> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(contents
+        .split_whitespace()
+        .map(|word| fold_case(word, ignore_case))
+        .collect())
+}
+
+/// Appends `c` to `text`, lowercased with `ignore_case`.
+fn push_folded(text: &mut String, c: char, ignore_case: bool) {
+    if ignore_case {
+        text.extend(c.to_lowercase());
+    } else {
+        text.push(c);
+    }
+}
+
+fn fold_case(word: &str, ignore_case: bool) -> String {
+    if ignore_case {
+        word.to_lowercase()
+    } else {
+        word.to_string()
+    }
+}
+
+/// Keeps only the numbered lines of `input` (1-based), each with its own
+/// terminator. Also returns the requested numbers that don't exist in the
+/// input, in ascending order.
+fn select_lines(input: &str, wanted: &BTreeSet<usize>) -> (String, Vec<usize>) {
+    let mut selected = String::new();
+    let mut total = 0;
+    for (index, line) in input.split_inclusive('\n').enumerate() {
+        total = index + 1;
+        if wanted.contains(&total) {
+            selected.push_str(line);
+        }
+    }
+    let missing = wanted
+        .iter()
+        .copied()
+        .filter(|&n| n == 0 || n > total)
+        .collect();
+    (selected, missing)
+}
+
+/// Keeps the odd- or even-numbered lines of `input`, counting from 1, each
+/// with its own terminator.
+fn select_parity(input: &str, parity: LineParity) -> String {
+    let first = match parity {
+        LineParity::Odd => 0,
+        LineParity::Even => 1,
+    };
+    input.split_inclusive('\n').skip(first).step_by(2).collect()
+}
+
+/// Length in bytes of the whitespace at the end of `line`, which should not
+/// include its terminator.
+fn trailing_whitespace_len(line: &str) -> usize {
+    line.len() - line.trim_end().len()
+}
+
+/// Display width of the leading whitespace of `line`, with tabs advancing to
+/// the next multiple of `tab_width` columns.
+fn indent_width(line: &str, tab_width: usize) -> usize {
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            '\t' => width += tab_width - width % tab_width,
+            c if c.is_whitespace() => width += 1,
+            _ => break,
+        }
+    }
+    width
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, Default)]
+struct JsonLineCounts {
+    valid: usize,
+    invalid: usize,
+    invalid_lines: Vec<usize>,
+}
+
+/// Results of counting one input with a [`Counter`]. Only the fields that the
+/// selected mode computes are filled in; the rest stay 0.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Counts {
+    pub lines: usize,
+    pub words: usize,
+    pub bytes: usize,
+    pub chars: usize,
+    pub max_line_length: usize,
+}
+
+/// Accumulates a total line: counts are summed, while the maximum line
+/// length is the longest across inputs, as in `wc -L`.
+impl std::ops::AddAssign for Counts {
+    fn add_assign(&mut self, other: Counts) {
+        self.lines += other.lines;
+        self.words += other.words;
+        self.bytes += other.bytes;
+        self.chars += other.chars;
+        self.max_line_length = self.max_line_length.max(other.max_line_length);
+    }
+}
+
+impl Counts {
+    /// Displays just `columns`, e.g. `counts.display(Columns::LINES)`.
+    pub fn display(&self, columns: Columns) -> DisplayCounts {
+        DisplayCounts {
+            counts: *self,
+            columns,
+        }
+    }
+}
+
+/// Counts `input` without going through a [`Counter`] or any I/O. A plain
+/// count fills in its selected columns and `-L` fills in `max_line_length`,
+/// both as [`Counter::count_stats`] would; the other modes leave every field
+/// at 0.
+///
+/// ```
+/// use gfwc::{count_str, Columns, CountType};
+///
+/// assert_eq!(count_str("one two three", CountType::Plain(Columns::WORDS)).words, 3);
+/// assert_eq!(count_str("", CountType::Plain(Columns::DEFAULT)), Default::default());
+/// ```
+pub fn count_str(input: &str, count_type: CountType) -> Counts {
+    const INFALLIBLE: &str = "counting a string can't fail";
+    match count_type {
+        CountType::Plain(columns) => {
+            let all = Counter::count_all_from_input(input.as_bytes(), &CountOptions::default())
+                .expect(INFALLIBLE);
+            let pick = |selected: bool, count: usize| if selected { count } else { 0 };
+            Counts {
+                lines: pick(columns.lines, all.lines),
+                words: pick(columns.words, all.words),
+                bytes: pick(columns.bytes, all.bytes),
+                chars: pick(columns.chars, all.chars),
+                max_line_length: 0,
+            }
+        }
+        CountType::MaxLineLength => Counts {
+            max_line_length: Counter::count_max_line_length_from_reader(
+                Cursor::new(input),
+                &CountOptions::default(),
+            )
+            .expect(INFALLIBLE),
+            ..Counts::default()
+        },
+        _ => Counts::default(),
+    }
+}
+
+/// Counts each of `paths` in order, without printing anything, with every
+/// other option at its command-line default. Each input succeeds or fails on
+/// its own, as with [`Counter::count_stats`], so one missing file doesn't
+/// stop the rest. Unlike a FILE argument, `-` here is a file, not stdin.
+///
+/// ```no_run
+/// use std::path::PathBuf;
+/// use gfwc::{count_paths, total_counts, Columns, CountType};
+///
+/// let paths = [PathBuf::from("a.txt"), PathBuf::from("b.txt")];
+/// let results = count_paths(&paths, CountType::Plain(Columns::LINES));
+/// for (path, result) in &results {
+///     match result {
+///         Ok(counts) => println!("{} {}", counts.lines, path.display()),
+///         Err(error) => eprintln!("{}: {}", path.display(), error),
+///     }
+/// }
+/// println!("{} total", total_counts(&results).lines);
+/// ```
+pub fn count_paths(paths: &[PathBuf], count_type: CountType) -> Vec<CountedFile> {
+    let counter = Counter::from(Config::builder().count_type(count_type).build());
+    paths
+        .iter()
+        .map(|path| {
+            let mut counter = counter.clone();
+            counter.source = Source::File(path.clone());
+            (path.clone(), counter.count_stats())
+        })
+        .collect()
+}
+
+/// One input's outcome from [`count_paths`].
+pub type CountedFile = (PathBuf, Result<Counts, CcwcError>);
+
+/// Adds up the inputs of [`count_paths`] that were counted, skipping those
+/// that failed, the way the `total` line does.
+pub fn total_counts(results: &[CountedFile]) -> Counts {
+    let mut total = Counts::default();
+    for counts in results
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().ok())
+    {
+        total += *counts;
+    }
+    total
+}
+
+/// Counts `source` like [`Counter::count_stats`] and returns the [`Counts`]
+/// as a JSON object, ready to be merged into a larger document. Every field
+/// is present; those the mode doesn't compute may be 0.
+///
+/// ```
+/// use gfwc::{count_to_json, Columns, CountType};
+///
+/// let value = count_to_json(&b"one two\n"[..], CountType::Plain(Columns::WORDS)).unwrap();
+/// assert_eq!(value["words"], 2);
+/// ```
+#[cfg(feature = "serde")]
+pub fn count_to_json<R: Read + Send + 'static>(
+    source: R,
+    count_type: CountType,
+) -> Result<serde_json::Value, CcwcError> {
+    let counts = Counter::from_reader(source, count_type).count_stats()?;
+    Ok(serde_json::to_value(counts).map_err(io::Error::from)?)
+}
+
+/// Renders `wc`'s default columns: lines, words and bytes.
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.display(Columns::DEFAULT).fmt(f)
+    }
+}
+
+/// The selected counts, space-separated in `wc`'s column order. A width, as
+/// in `{:7}`, right-aligns each count rather than the whole line.
+pub struct DisplayCounts {
+    counts: Counts,
+    columns: Columns,
+}
+
+impl fmt::Display for DisplayCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = f.width().unwrap_or(0);
+        for (i, (_, count)) in self.columns.entries(self.counts).enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{:>width$}", count)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<CountSummary> for Counts {
+    fn from(summary: CountSummary) -> Self {
+        Counts {
+            lines: summary.lines as usize,
+            words: summary.words as usize,
+            bytes: summary.bytes as usize,
+            chars: summary.chars as usize,
+            max_line_length: 0,
+        }
+    }
+}
+
+/// A read error from [`CountState::count_reader`], along with the
+/// approximate counts gathered before it occurred.
+#[derive(Debug)]
+pub struct PartialCountError {
+    pub partial: CountSummary,
+    pub error: io::Error,
+}
+
+impl fmt::Display for PartialCountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} after {} bytes", self.error, self.partial.bytes)
+    }
+}
+
+impl Error for PartialCountError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl CountState {
+    /// Reads `reader` to the end, counting as it goes. If a read fails, the
+    /// error is returned together with the counts accumulated up to that
+    /// point. Those partial counts are approximate: a word or character cut
+    /// off by the failure may be counted as complete.
+    pub fn count_reader<R: Read>(reader: R) -> Result<CountSummary, PartialCountError> {
+        Self::count_reader_in_chunks(reader, 8192)
+    }
+
+    /// Like [`CountState::count_reader`], reading up to `chunk_size` bytes
+    /// at a time.
+    pub fn count_reader_in_chunks<R: Read>(
+        mut reader: R,
+        chunk_size: usize,
+    ) -> Result<CountSummary, PartialCountError> {
+        let mut state = CountState::new();
+        let mut buffer = vec![0; chunk_size.max(1)];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => return Ok(state.finish()),
+                Ok(n) => state.update(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    return Err(PartialCountError {
+                        partial: state.finish(),
+                        error,
+                    })
+                }
+            }
+        }
+    }
+}
+
+impl From<Config> for Counter {
+    fn from(config: Config) -> Self {
+        Counter {
+            count_type: config.get_count_type(),
+            source: config
+                .file_paths
+                .first()
+                .map_or(Source::Stdin { named: false }, |path| {
+                    Source::from_path(path)
+                }),
+            file_paths: config.get_file_paths(),
+            program: config.program,
+            byte_units: config.byte_units,
+            output_format: config.output_format,
+            separator: config.separator,
+            labels: config.labels,
+            total: config.total,
+            color: config.color,
+            null_terminated: config.null_terminated,
+            tee: config.tee,
+            base64_decode: config.base64_decode,
+            alert_lines: config.alert_lines,
+            progress: config.progress,
+            no_tty_stdin: config.no_tty_stdin,
+            check: config.check,
+            sort: config.sort,
+            no_align: config.no_align,
+            bare: config.bare,
+            diff: config.diff,
+            merge: config.merge,
+            split_marker: config.split_marker,
+            matcher: config.matcher,
+            vocab_path: config.vocab_path,
+            partial_on_error: config.partial_on_error,
+            recursive: config.recursive,
+            follow_symlinks: config.follow_symlinks,
+            max_depth: config.max_depth,
+            files0_from: config.files0_from,
+            line_numbers_from: config.line_numbers_from,
+            line_parity: config.line_parity,
+            raw_len: Cell::new(0),
+            encodings: Arc::default(),
+            top: config.top,
+            freq_top: config.freq_top,
+            min_count: config.min_count,
+            expectations: config.expectations,
+            #[cfg(feature = "hash")]
+            hash_algo: config.hash_algo,
+            #[cfg(feature = "hash")]
+            digest: RefCell::new(None),
+            #[cfg(feature = "json")]
+            show_invalid_lines: config.show_invalid_lines,
+            #[cfg(feature = "gzip")]
+            decompress: config.decompress,
+            options: config.options,
+            number_width: 1,
+        }
+    }
+}
+
+/// Counts `text` itself, with every option at its command-line default: the
+/// lines, words and bytes, unlabelled like stdin.
+impl From<&str> for Counter {
+    fn from(text: &str) -> Self {
+        Counter {
+            source: Source::Memory(text.as_bytes().into()),
+            ..Counter::from(Config::builder().build())
+        }
+    }
+}
+
+impl From<String> for Counter {
+    fn from(text: String) -> Self {
+        Counter {
+            source: Source::Memory(text.into_bytes().into()),
+            ..Counter::from(Config::builder().build())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::io::Cursor;
+
+    /// Wraps the system allocator to count allocations made by the current
+    /// thread, so tests running in parallel don't see each other's.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations_during<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATIONS.with(Cell::get);
+        let result = f();
+        (result, ALLOCATIONS.with(Cell::get) - before)
+    }
+
+    /// A counter for the command line `args` that reads `input` in place of
+    /// any file.
+    fn counter_reading<R: Read + Send + 'static>(args: &[String], input: R) -> Counter {
+        Counter {
+            source: Source::Reader(SharedReader(Arc::new(Mutex::new(Box::new(input))))),
+            ..Counter::from(Config::build(args).unwrap())
+        }
+    }
+
+    #[test]
+    fn test_count_bytes() {
+        let input_data = "Hello, world!";
+        let cursor = Cursor::new(input_data);
+        let byte_count = Counter::count_bytes_from_reader(cursor).unwrap();
+        assert_eq!(byte_count, input_data.len());
+    }
+
+    #[test]
+    fn test_count_chars() {
+        let input_data = "Hello, 🌍!";
+        let cursor = Cursor::new(input_data);
+        let char_count = Counter::count_chars_from_reader(cursor).unwrap();
+        assert_eq!(char_count, input_data.chars().count());
+    }
+
+    #[test]
+    fn test_count_words() {
+        let input_data = "Hello world, how are you?";
+        let cursor = Cursor::new(input_data);
+        let word_count = Counter::count_words_from_reader(cursor).unwrap();
+        assert_eq!(word_count, 5); // "Hello", "world,", "how", "are", "you?"
+    }
+
+    #[test]
+    fn test_count_lines() {
+        let input_data = "Line one\nLine two\nLine three";
+        let cursor = Cursor::new(input_data);
+        let line_count = Counter::count_lines_from_reader(cursor).unwrap();
+        // Like `wc -l`, only newlines are counted.
+        assert_eq!(line_count, 2);
+    }
+
+    #[test]
+    fn test_count_lines_counts_newline_bytes() {
+        let lines = |text: &str| Counter::count_lines_from_reader(Cursor::new(text)).unwrap();
+        assert_eq!(lines("a\nb\n"), 2);
+        assert_eq!(lines("a\nb"), 1);
+        assert_eq!(lines("no newline"), 0);
+        assert_eq!(lines("\n\n\n"), 3);
+        assert_eq!(lines(""), 0);
+    }
+
+    #[test]
+    fn test_count_all() {
+        let input_data = String::from("Hello, world!\nRust is fun.");
+
+        let counts =
+            Counter::count_all_from_input(input_data.as_bytes(), &CountOptions::default()).unwrap();
+
+        // Expected counts based on input
+        let expected_bytes = input_data.len();
+        let expected_lines = 1;
+        let expected_words = 5;
+
+        assert_eq!(counts.bytes, expected_bytes);
+        assert_eq!(counts.lines, expected_lines);
+        assert_eq!(counts.words, expected_words);
+    }
+
+    #[test]
+    fn test_config_build_with_flag_and_file_path() {
+        let args = vec!["gfwc".to_string(), "-l".to_string(), "test.txt".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::LINES));
+        assert_eq!(config.file_paths, ["test.txt".to_string()]);
+    }
+
+    #[test]
+    fn test_config_build_with_only_file_path() {
+        let args = vec!["gfwc".to_string(), "test.txt".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::DEFAULT));
+        assert_eq!(config.file_paths, ["test.txt".to_string()]);
+    }
+
+    #[test]
+    fn test_config_invalid_flag() {
+        let args = vec!["gfwc".to_string(), "-z".to_string()];
+        let config = Config::build(&args);
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_config_no_flag_defaults_to_all_count() {
+        let args = vec!["gfwc".to_string(), "text.txt".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::DEFAULT));
+        assert_eq!(config.file_paths, ["text.txt".to_string()]);
+    }
+
+    #[test]
+    fn test_config_valid_flag() {
+        let args = vec!["gfwc".to_string(), "-w".to_string(), "text.txt".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::WORDS));
+        assert_eq!(config.file_paths, ["text.txt".to_string()]);
+    }
+
+    #[test]
+    fn test_config_only_flag_no_file_path() {
+        let args = vec!["gfwc".to_string(), "-w".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::WORDS));
+        assert!(config.file_paths.is_empty());
+    }
+
+    #[test]
+    fn test_count_state_chunked_matches_single_chunk() {
+        let input_data = "Hello, 🌍!\n\n  Rust\tis fun.\nno trailing newline";
+        let bytes = input_data.as_bytes();
+
+        let mut whole = CountState::new();
+        whole.update(bytes);
+        let expected = whole.finish();
+
+        for chunk_size in 1..bytes.len() {
+            let mut state = CountState::new();
+            for chunk in bytes.chunks(chunk_size) {
+                state.update(chunk);
+            }
+            assert_eq!(state.finish(), expected, "chunk size {}", chunk_size);
+        }
+
+        // Overlong forms, surrogates and code points past U+10FFFF aren't
+        // characters, just as String::from_utf8 rejects them.
+        for invalid in [
+            &b"a\xe0\x80\x80b"[..],
+            b"\xf0\x80\x80\x80",
+            b"\xc0\xaf",
+            b"\xed\xa0\x80z",
+            b"\xf4\x90\x80\x80",
+            b"\xe0\xa0\x80\xf0\x90\x80\x80\xed\x9f\xbf\xf4\x8f\xbf\xbf",
+        ] {
+            let valid_chars = String::from_utf8_lossy(invalid)
+                .chars()
+                .filter(|&c| c != char::REPLACEMENT_CHARACTER)
+                .count() as u64;
+            for chunk_size in 1..=invalid.len() {
+                let mut state = CountState::new();
+                for chunk in invalid.chunks(chunk_size) {
+                    state.update(chunk);
+                }
+                let summary = state.finish();
+                assert_eq!(
+                    summary.chars, valid_chars,
+                    "{:?} in {}s",
+                    invalid, chunk_size
+                );
+            }
+        }
+        assert_eq!(counting::count_chars(b"a\xe0\x80\x80b"), 2);
+        assert_eq!(counting::count_chars(b"\xf0\x80\x80\x80"), 0);
+
+        assert_eq!(expected.bytes, input_data.len() as u64);
+        assert_eq!(expected.chars, input_data.chars().count() as u64);
+        assert_eq!(
+            expected.words,
+            Counter::count_words_from_reader(Cursor::new(input_data)).unwrap() as u64
+        );
+        assert_eq!(
+            expected.lines,
+            Counter::count_lines_from_reader(Cursor::new(input_data)).unwrap() as u64
+        );
+    }
+
+    #[test]
+    fn test_count_state_empty_input() {
+        assert_eq!(CountState::new().finish(), CountSummary::default());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_count_json_lines_skips_blank_lines() {
+        let input_data = "{\"a\": 1}\n\n   \n[1, 2\n\"ok\"\nnot json\n";
+        let counts = Counter::count_json_lines_from_reader(Cursor::new(input_data)).unwrap();
+        assert_eq!(counts.valid, 2);
+        assert_eq!(counts.invalid, 2);
+        assert_eq!(counts.invalid_lines, vec![4, 6]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_config_valid_json_lines_flag() {
+        let args = vec![
+            "gfwc".to_string(),
+            "--valid-json-lines".to_string(),
+            "--invalid-line-numbers".to_string(),
+            "data.ndjson".to_string(),
+        ];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::ValidJsonLines);
+        assert!(config.show_invalid_lines);
+        assert_eq!(config.file_paths, ["data.ndjson".to_string()]);
+    }
+
+    #[test]
+    fn test_format_si() {
+        assert_eq!(format_si(0), "0");
+        assert_eq!(format_si(999), "999");
+        assert_eq!(format_si(1000), "1.0kB");
+        assert_eq!(format_si(1500), "1.5kB");
+        assert_eq!(format_si(999_999), "1.0MB");
+        assert_eq!(format_si(2_340_000_000), "2.3GB");
+    }
+
+    #[test]
+    fn test_config_si_flag() {
+        let args = vec!["gfwc".to_string(), "-c".to_string(), "--si".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::BYTES));
+        assert_eq!(config.byte_units, ByteUnits::Si);
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        assert!(!looks_binary(b""));
+        assert!(!looks_binary("héllo\n".as_bytes()));
+        assert!(looks_binary(b"ab\0cd"));
+        assert!(looks_binary(b"ab\xffcd"));
+        // A character split by the end of the sample is still text.
+        let mut split = vec![b'a'; BINARY_SAMPLE_LEN - 1];
+        split.extend("é".as_bytes());
+        assert!(!looks_binary(&split));
+    }
+
+    #[test]
+    fn test_chars_rejects_binary_input() {
+        let binary = || Cursor::new(b"ab\0cd\n".to_vec());
+        for flags in [&["gfwc", "-m"][..], &["gfwc", "-lm"]] {
+            let args = flags
+                .iter()
+                .map(|flag| flag.to_string())
+                .collect::<Vec<_>>();
+            let err = counter_reading(&args, binary())
+                .count_into(Vec::new())
+                .unwrap_err();
+            assert!(matches!(&err, CcwcError::NotText(path) if path == STDIN_PATH));
+            assert!(err.to_string().contains("use -c"));
+        }
+
+        for (flag, expected) in [("-c", "6\n"), ("-l", "1\n")] {
+            let args = ["gfwc", flag].map(String::from);
+            let mut out = Vec::new();
+            counter_reading(&args, binary())
+                .count_into(&mut out)
+                .unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_separator_joins_columns_and_label() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("gfwc-separator-a-{}", std::process::id()));
+        let second = dir.join(format!("gfwc-separator-b-{}", std::process::id()));
+        std::fs::write(&first, "one two\nthree\n").unwrap();
+        std::fs::write(&second, "four\n").unwrap();
+        let first = first.to_string_lossy().into_owned();
+        let second = second.to_string_lossy().into_owned();
+
+        let args = ["gfwc", "-lw", "--separator", ",", &first, &second].map(String::from);
+        let mut out = Vec::new();
+        let result = Counter::from(Config::build(&args).unwrap()).count_into(&mut out);
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+        result.unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("2,3,{}\n1,1,{}\n3,4,total\n", first, second)
+        );
+
+        // Unlabelled stdin has no trailing separator.
+        let args = ["gfwc", "--separator", " | "].map(String::from);
+        let mut out = Vec::new();
+        counter_reading(&args, Cursor::new("one two\n"))
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1 | 2 | 8\n");
+    }
+
+    #[test]
+    fn test_keyval_format_prints_one_block_per_input() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("gfwc-keyval-a-{}", std::process::id()));
+        let second = dir.join(format!("gfwc-keyval-b-{}", std::process::id()));
+        std::fs::write(&first, "one two\nthree\n").unwrap();
+        std::fs::write(&second, "four\n").unwrap();
+        let first = first.to_string_lossy().into_owned();
+        let second = second.to_string_lossy().into_owned();
+
+        let args = ["gfwc", "--format=keyval", &first, &second].map(String::from);
+        let mut out = Vec::new();
+        let result = Counter::from(Config::build(&args).unwrap()).count_into(&mut out);
+        let args = ["gfwc", "-wm", "--format=keyval", &second].map(String::from);
+        let mut single = Vec::new();
+        let single_result = Counter::from(Config::build(&args).unwrap()).count_into(&mut single);
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+        result.unwrap();
+        single_result.unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "lines=2\nwords=3\nbytes=14\nfile={}\n\n\
+                 lines=1\nwords=1\nbytes=5\nfile={}\n\n\
+                 lines=3\nwords=4\nbytes=19\nfile=total\n",
+                first, second
+            )
+        );
+        assert_eq!(
+            String::from_utf8(single).unwrap(),
+            format!("words=1\nchars=5\nfile={}\n", second)
+        );
+
+        // Unlabelled stdin has no file line.
+        let args = ["gfwc", "-l", "--format=keyval"].map(String::from);
+        let mut out = Vec::new();
+        counter_reading(&args, Cursor::new("a\nb\n"))
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "lines=2\n");
+
+        for bad in [
+            &["--format=yaml"][..],
+            &["--format=keyval", "--freq"],
+            &["--format=keyval", "--separator", ","],
+        ] {
+            let args: Vec<String> = ["gfwc"].iter().chain(bad).map(|s| s.to_string()).collect();
+            assert!(Config::build(&args).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_labels_name_each_count() {
+        let output_for = |flags: &[&str]| {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .collect();
+            let mut out = Vec::new();
+            counter_reading(&args, Cursor::new("one two three\nfour\n"))
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(output_for(&["-lw", "--labels"]), "lines:2 words:4\n");
+        assert_eq!(output_for(&["--labels"]), "lines:2 words:4 bytes:19\n");
+        assert_eq!(
+            output_for(&["--labels", "-c", "--human-readable"]),
+            "bytes:19\n"
+        );
+        assert_eq!(
+            output_for(&["-m", "--labels", "--separator", ","]),
+            "chars:19\n"
+        );
+
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("gfwc-labels-{}", std::process::id()));
+        std::fs::write(&path, "one two\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let args = ["gfwc", "-lw", "--labels", &path].map(String::from);
+        let mut out = Vec::new();
+        let result = Counter::from(Config::build(&args).unwrap()).count_into(&mut out);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("lines:1 words:2 {}\n", path)
+        );
+
+        for bad in [&["--labels", "--csv"][..], &["--labels", "--freq"]] {
+            let args: Vec<String> = ["gfwc"].iter().chain(bad).map(|s| s.to_string()).collect();
+            assert!(Config::build(&args).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_config_separator_validation() {
+        let build = |flags: &[&str]| {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .collect();
+            Config::build(&args)
+        };
+        assert_eq!(
+            build(&["--separator", "|"]).unwrap().separator.as_deref(),
+            Some("|")
+        );
+        assert!(build(&["--separator"]).is_err());
+        assert!(build(&["--separator", ""]).is_err());
+        assert!(build(&["--separator", ",", "--csv"]).is_err());
+        assert!(build(&["--separator", ",", "--trim-savings"]).is_err());
+    }
+
+    #[test]
+    fn test_format_iec() {
+        assert_eq!(format_iec(0), "0");
+        assert_eq!(format_iec(1023), "1023");
+        assert_eq!(format_iec(1024), "1.0K");
+        assert_eq!(format_iec(1536), "1.5K");
+        assert_eq!(format_iec(1_048_575), "1.0M");
+        assert_eq!(format_iec(1_048_576), "1.0M");
+        assert_eq!(format_iec(24_117_248), "23.0M");
+        assert_eq!(format_iec(4_509_715_661), "4.2G");
+    }
+
+    #[test]
+    fn test_human_readable_only_changes_the_byte_column() {
+        let args = ["gfwc", "--human-readable"].map(String::from);
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.byte_units, ByteUnits::Iec);
+
+        let mut out = Vec::new();
+        counter_reading(&args, Cursor::new("x ".repeat(1024)))
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "      0    1024    2.0K\n");
+
+        for units in [["--si", "--human-readable"], ["--human-readable", "--si"]] {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(&units)
+                .map(|s| s.to_string())
+                .collect();
+            let error = Config::build(&args).err().unwrap();
+            assert_eq!(
+                error.to_string(),
+                "--si can't be combined with --human-readable."
+            );
+            assert_eq!(error.exit_code(), 2);
+        }
+    }
+
+    #[test]
+    fn test_format_bytes_per_char() {
+        assert_eq!(format_bytes_per_char(0, 0), "0");
+        assert_eq!(format_bytes_per_char(5, 5), "1.00");
+        let input_data = "héllo 🌍";
+        assert_eq!(
+            format_bytes_per_char(input_data.len(), input_data.chars().count()),
+            "1.57"
+        );
+    }
+
+    #[test]
+    fn test_column_range_slices_by_character() {
+        let range = ColumnRange::parse("2:4").unwrap();
+        assert_eq!(range.slice("héllo"), "éll");
+        assert_eq!(range.slice("hé"), "é");
+        assert_eq!(range.slice("h"), "");
+        assert_eq!(range.slice(""), "");
+        assert!(ColumnRange::parse("0:3").is_none());
+        assert!(ColumnRange::parse("4:2").is_none());
+        assert!(ColumnRange::parse("4").is_none());
+    }
+
+    #[test]
+    fn test_count_in_columns() {
+        let input_data = "ab cd ef\nx\n  🌍 z\n";
+        let range = ColumnRange::parse("3:5").unwrap();
+        let words = Counter::count_words_in_columns_from_reader(
+            Cursor::new(input_data),
+            range,
+            &Tokenizer::Whitespace,
+        )
+        .unwrap();
+        let chars =
+            Counter::count_chars_in_columns_from_reader(Cursor::new(input_data), range).unwrap();
+        // Slices are " cd", "" and "🌍 z".
+        assert_eq!(words, 3);
+        assert_eq!(chars, 6);
+    }
+
+    #[test]
+    fn test_config_column_range_requires_word_or_char_mode() {
+        let args = vec![
+            "gfwc".to_string(),
+            "-l".to_string(),
+            "--column-range".to_string(),
+            "1:4".to_string(),
+        ];
+        assert!(Config::build(&args).is_err());
+
+        let args = vec![
+            "gfwc".to_string(),
+            "-w".to_string(),
+            "--column-range".to_string(),
+            "1:4".to_string(),
+            "records.txt".to_string(),
+        ];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(
+            config.options.column_range,
+            Some(ColumnRange { start: 1, end: 4 })
+        );
+        assert_eq!(config.file_paths, ["records.txt".to_string()]);
+    }
+
+    #[test]
+    fn test_count_max_blank_run() {
+        let input_data = "a\n\nb\n\n  \n\t\nc\n\n";
+        let run = Counter::count_max_blank_run_from_reader(Cursor::new(input_data)).unwrap();
+        assert_eq!(run, 3);
+        let run = Counter::count_max_blank_run_from_reader(Cursor::new("a\nb")).unwrap();
+        assert_eq!(run, 0);
+    }
+
+    #[test]
+    fn test_config_max_blank_run_limit() {
+        let args = vec!["gfwc".to_string(), "--max-blank-run=1".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::MaxBlankRun(Some(1)));
+
+        let args = vec!["gfwc".to_string(), "--max-blank-run=x".to_string()];
+        assert!(Config::build(&args).is_err());
+    }
+
+    #[test]
+    fn test_count_state_counts_past_u32_max() {
+        // Streaming 4 GiB through a test is too slow, so start the state just
+        // below the 32-bit limit and push it over with a small chunk.
+        let near_limit = u64::from(u32::MAX) - 1;
+        let mut state = CountState::new();
+        state.summary = CountSummary {
+            bytes: near_limit,
+            chars: near_limit,
+            words: near_limit,
+            lines: near_limit,
+        };
+        state.update(b"one two\nthree\n");
+        let summary = state.finish();
+        assert_eq!(summary.bytes, near_limit + 14);
+        assert_eq!(summary.chars, near_limit + 14);
+        assert_eq!(summary.words, near_limit + 3);
+        assert_eq!(summary.lines, near_limit + 2);
+        assert!(summary.bytes > u64::from(u32::MAX));
+    }
+
+    #[test]
+    fn test_count_bracket_balance() {
+        let input_data = "fn main() {\n    let v = [1, (2];\n";
+        let balance = Counter::count_bracket_balance_from_reader(Cursor::new(input_data)).unwrap();
+        assert_eq!(
+            balance,
+            BracketBalance {
+                parens: 1,
+                brackets: 0,
+                braces: 1
+            }
+        );
+        assert!(!balance.is_balanced());
+
+        let balance = Counter::count_bracket_balance_from_reader(Cursor::new("{[()]})(")).unwrap();
+        assert!(balance.is_balanced());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_count_tokens_with_regex() {
+        let input_data = "don't stop-believing\n\n42 + x_1\n";
+        let tokenizer = Tokenizer::Regex(regex::Regex::new(r"\w+").unwrap());
+        let count = Counter::count_tokens_from_reader(Cursor::new(input_data), &tokenizer).unwrap();
+        assert_eq!(count, 6); // don, t, stop, believing, 42, x_1
+
+        // Empty matches are not counted as words.
+        let tokenizer = Tokenizer::Regex(regex::Regex::new(r"a*").unwrap());
+        let count = Counter::count_tokens_from_reader(Cursor::new("bab aa"), &tokenizer).unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_config_invalid_token_regex() {
+        let args = vec![
+            "gfwc".to_string(),
+            "--token-regex".to_string(),
+            "(".to_string(),
+        ];
+        assert!(Config::build(&args).is_err());
+    }
+
+    #[cfg(feature = "categories")]
+    #[test]
+    fn test_count_by_category() {
+        let input_data = "Hi 42!\n";
+        let tally = Counter::count_by_category_from_reader(Cursor::new(input_data)).unwrap();
+        let rows: Vec<_> = tally.into_iter().collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("Cc", 1),
+                ("Ll", 1),
+                ("Lu", 1),
+                ("Nd", 2),
+                ("Po", 1),
+                ("Zs", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_count_trailing_blank_lines() {
+        let count = |input: &str| {
+            Counter::count_trailing_blank_lines_from_reader(Cursor::new(input)).unwrap()
+        };
+        assert_eq!(count(""), 0);
+        assert_eq!(count("a\n"), 0);
+        assert_eq!(count("a"), 0);
+        assert_eq!(count("a\n\nb\n"), 0);
+        assert_eq!(count("a\n\n"), 1);
+        assert_eq!(count("a\n \n\t\n"), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_count_bytes_from_fifo() {
+        use std::io::Write;
+        use std::process::Command;
+
+        let fifo = std::env::temp_dir().join(format!("gfwc-fifo-{}", std::process::id()));
+        let _ = std::fs::remove_file(&fifo);
+        let status = Command::new("mkfifo").arg(&fifo).status().unwrap();
+        assert!(status.success());
+
+        // A FIFO reports a metadata length of 0, so correct counts require
+        // actually streaming its contents.
+        assert_eq!(std::fs::metadata(&fifo).unwrap().len(), 0);
+
+        let writer_path = fifo.clone();
+        let writer = std::thread::spawn(move || {
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(writer_path)
+                .unwrap();
+            file.write_all(b"one two\nthree\n").unwrap();
+        });
+
+        let args = vec![
+            "gfwc".to_string(),
+            "-c".to_string(),
+            fifo.to_string_lossy().into_owned(),
+        ];
+        let counter = Counter::from(Config::build(&args).unwrap());
+        let count = counter.count_bytes().unwrap();
+        writer.join().unwrap();
+        std::fs::remove_file(&fifo).unwrap();
+
+        assert_eq!(count, 14);
+    }
+
+    #[test]
+    fn test_tee_copies_input_through() {
+        let input_data = "one two\nthree\n";
+        let mut copied = Vec::new();
+        let mut text = String::new();
+        Tee::new(input_data.as_bytes(), &mut copied)
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, input_data);
+        assert_eq!(copied, input_data.as_bytes());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_ignore_case_applies_to_token_regex() {
+        let count_with = |extra: &[&str]| {
+            let mut args = vec!["gfwc".to_string(), "-w".to_string()];
+            args.extend(extra.iter().map(|s| s.to_string()));
+            let config = Config::build(&args).unwrap();
+            Counter::count_tokens_from_reader(
+                Cursor::new("Rust rust RUST go"),
+                &config.options.tokenizer,
+            )
+            .unwrap()
+        };
+        assert_eq!(count_with(&["--token-regex", "rust"]), 1);
+        assert_eq!(count_with(&["--token-regex", "rust", "--ignore-case"]), 3);
+        assert_eq!(count_with(&["--ignore-case", "--token-regex", "rust"]), 3);
+    }
+
+    #[test]
+    fn test_line_alert_fires_on_each_multiple() {
+        let input_data = "1\n2\n3\n4\n5\n";
+        let mut alerts = Vec::new();
+        let mut text = String::new();
+        LineAlert::new(input_data.as_bytes(), &mut alerts, 2)
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, input_data);
+        assert_eq!(
+            String::from_utf8(alerts).unwrap(),
+            "alert lines=2\nalert lines=4\n"
+        );
+    }
+
+    #[test]
+    fn test_progress_tracks_bytes_read_and_clears_at_end() {
+        let mut drawn = Vec::new();
+        let mut progress = Progress::new(&b"abcdef"[..], &mut drawn, "a.txt", Some(6));
+        progress.interval = Duration::ZERO;
+        let mut buf = [0; 4];
+        assert_eq!(progress.read(&mut buf).unwrap(), 4);
+        assert_eq!(progress.read(&mut buf).unwrap(), 2);
+        assert_eq!(progress.read(&mut buf).unwrap(), 0);
+        assert_eq!(
+            String::from_utf8(drawn).unwrap(),
+            "\ra.txt: 4/6 bytes (66%)\ra.txt: 6/6 bytes (100%)\r\x1b[2K"
+        );
+
+        let mut drawn = Vec::new();
+        let mut text = String::new();
+        Progress::new(&b"abc"[..], &mut drawn, "-", None)
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "abc");
+        assert_eq!(String::from_utf8(drawn).unwrap(), "\r-: 3 bytes\r\x1b[2K");
+    }
+
+    #[test]
+    fn test_progress_leaves_piped_output_unchanged() {
+        let args = ["gfwc", "--progress"].map(String::from);
+        assert!(Config::build(&args).unwrap().progress);
+        let mut out = Vec::new();
+        counter_reading(&args, Cursor::new("one two\n"))
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "      1       2       8\n");
+    }
+
+    #[test]
+    fn test_terminal_stdin_gets_a_hint_or_an_error() {
+        let args = ["gfwc"].map(String::from);
+        let counter = Counter::from(Config::build(&args).unwrap());
+        let mut hint = Vec::new();
+        counter.check_terminal_stdin(false, &mut hint).unwrap();
+        assert!(hint.is_empty());
+        counter.check_terminal_stdin(true, &mut hint).unwrap();
+        let hint = String::from_utf8(hint).unwrap();
+        assert!(
+            hint.starts_with("gfwc: reading from terminal; press "),
+            "{}",
+            hint
+        );
+
+        let args = ["gfwc", "--no-tty-stdin"].map(String::from);
+        let counter = Counter::from(Config::build(&args).unwrap());
+        let mut hint = Vec::new();
+        counter.check_terminal_stdin(false, &mut hint).unwrap();
+        assert!(counter.check_terminal_stdin(true, &mut hint).is_err());
+        assert!(hint.is_empty());
+    }
+
+    #[test]
+    fn test_head_options_stop_counting_at_the_limit() {
+        let output_for = |flags: &[&str], input: FailingReader<'static>| {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .collect();
+            let mut out = Vec::new();
+            counter_reading(&args, input).count_into(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        // Reading past the limit would hit the error at the end of the data.
+        let input = || FailingReader {
+            data: b"one two\nthree\nfour five\n",
+        };
+        assert_eq!(
+            output_for(&["--head-lines", "2"], input()),
+            "      2       3      14\n"
+        );
+        assert_eq!(
+            output_for(&["--head-bytes", "5"], input()),
+            "      0       2       5\n"
+        );
+        assert_eq!(output_for(&["-c", "--head-bytes", "9"], input()), "9\n");
+        assert_eq!(output_for(&["-l", "--head-lines", "1"], input()), "1\n");
+
+        // A file is read rather than measured.
+        let path = std::env::temp_dir().join(format!("gfwc-head-{}", std::process::id()));
+        std::fs::write(&path, "x\n".repeat(1000)).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let counter = |flags: &[&str]| {
+            let mut args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .collect();
+            args.push(path.clone());
+            Counter::from(Config::build(&args).unwrap())
+        };
+        assert_eq!(counter(&["--head-bytes", "10"]).count_bytes().unwrap(), 10);
+        assert_eq!(counter(&["--head-lines", "10"]).count_bytes().unwrap(), 20);
+        assert_eq!(
+            counter(&["--head-lines", "5000"]).count_lines().unwrap(),
+            1000
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        for flag in ["--head-bytes", "--head-lines"] {
+            for bad in ["0", "-3", "ten"] {
+                let args = ["gfwc", flag, bad].map(String::from);
+                assert!(
+                    matches!(Config::build(&args), Err(CcwcError::Usage(_))),
+                    "{} {}",
+                    flag,
+                    bad
+                );
+            }
+            let args = ["gfwc", flag].map(String::from);
+            assert!(Config::build(&args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_alert_lines_requires_positive_count() {
+        let args = vec![
+            "gfwc".to_string(),
+            "--alert-lines".to_string(),
+            "0".to_string(),
+        ];
+        assert!(Config::build(&args).is_err());
+        let args = vec![
+            "gfwc".to_string(),
+            "--alert-lines".to_string(),
+            "10".to_string(),
+        ];
+        assert_eq!(Config::build(&args).unwrap().alert_lines, Some(10));
+    }
+
+    #[test]
+    fn test_unescape_marker() {
+        assert_eq!(unescape_marker("\\n\\n"), "\n\n");
+        assert_eq!(unescape_marker("\\r\\n\\r\\n"), "\r\n\r\n");
+        assert_eq!(unescape_marker("--- end"), "--- end");
+        assert_eq!(unescape_marker("a\\\\b\\x\\"), "a\\b\\x\\");
+    }
+
+    #[test]
+    fn test_config_split_at() {
+        let args = vec![
+            "gfwc".to_string(),
+            "--split-at".to_string(),
+            "\\n\\n".to_string(),
+            "mail.eml".to_string(),
+        ];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::SplitAt);
+        assert_eq!(config.split_marker.as_deref(), Some("\n\n"));
+        assert_eq!(config.file_paths, ["mail.eml".to_string()]);
+    }
+
+    #[test]
+    fn test_split_at_marker() {
+        let input_data = "Subject: hi\n\nbody\n\nmore\n";
+        assert_eq!(
+            split_at_marker(input_data, "\n\n"),
+            ("Subject: hi", "body\n\nmore\n")
+        );
+        assert_eq!(split_at_marker("no marker", "\n\n"), ("no marker", ""));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_measure_compression() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let input_data = "hello hello hello hello\n".repeat(100);
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(input_data.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let sizes = Counter::measure_compression(compressed.as_slice()).unwrap();
+        assert_eq!(
+            sizes,
+            CompressionSizes {
+                compressed: compressed.len() as u64,
+                decompressed: Some(input_data.len() as u64),
+            }
+        );
+
+        let sizes = Counter::measure_compression(input_data.as_bytes()).unwrap();
+        assert_eq!(sizes.compressed, input_data.len() as u64);
+        assert_eq!(sizes.decompressed, None);
+    }
+
+    #[test]
+    fn test_count_words_matches_wc_c_locale() {
+        // Expected values are from `LC_ALL=C wc -w` (GNU coreutils 9.1).
+        let cases: [(&[u8], usize); 5] = [
+            (b"a\x0Bb\x0Cc\rd\te  f\n", 6),
+            (b"\x0B\x0C\r \t\n", 0),
+            (b"x\xC2\xA0y\x0B\x0Bz", 2),
+            (b"one\xE2\x80\x83two", 1),
+            (b"", 0),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                Counter::count_words_from_reader(input).unwrap(),
+                expected,
+                "{:?}",
+                input
+            );
+            assert_eq!(
+                Counter::count_all_from_input(
+                    String::from_utf8_lossy(input).as_bytes(),
+                    &CountOptions::default()
+                )
+                .unwrap()
+                .words,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_slice_counters_match_the_reader_counters() {
+        let cases: [(&[u8], usize, usize, usize); 6] = [
+            (b"", 0, 0, 0),
+            (b"no newline", 0, 2, 10),
+            (b"one two\nthree\n", 2, 3, 14),
+            (b"a\x0Bb\x0Cc\rd\n\n", 2, 4, 9),
+            ("héllo wörld 🌍\n".as_bytes(), 1, 3, 14),
+            (b"bad\xff\xfebytes \xe2\x82", 0, 2, 9),
+        ];
+        for (input, lines, words, chars) in cases {
+            assert_eq!(counting::count_newlines(input), lines, "{:?}", input);
+            assert_eq!(counting::count_words(input), words, "{:?}", input);
+            assert_eq!(counting::count_chars(input), chars, "{:?}", input);
+            assert_eq!(Counter::count_lines_from_reader(input).unwrap(), lines);
+            assert_eq!(Counter::count_words_from_reader(input).unwrap(), words);
+            assert_eq!(Counter::count_chars_from_reader(input).unwrap(), chars);
+        }
+    }
+
+    #[test]
+    fn test_word_counter_ignores_chunk_boundaries() {
+        let input_data = "  lorem ipsum\tdolor\n\nsit\x0Bamet, héllo wörld 🌍 end".as_bytes();
+        let expected = Counter::count_words_from_reader(input_data).unwrap();
+        assert_eq!(expected, 9);
+        for chunk_size in 1..=input_data.len() {
+            let mut wc = WordCounter::new();
+            for chunk in input_data.chunks(chunk_size) {
+                wc.push(chunk);
+            }
+            assert_eq!(wc.finish(), expected, "chunks of {}", chunk_size);
+        }
+        // Every split point, including inside multi-byte characters.
+        for split in 0..=input_data.len() {
+            let mut wc = WordCounter::new();
+            wc.push(&input_data[..split]);
+            wc.push(&[]);
+            wc.push(&input_data[split..]);
+            assert_eq!(wc.finish(), expected, "split at {}", split);
+        }
+        assert_eq!(WordCounter::new().finish(), 0);
+    }
+
+    #[test]
+    fn test_every_word_count_splits_on_vertical_tab_and_form_feed() {
+        let input_data = "one\x0Ctwo\x0Bthree\rfour\x0C\x0B five\n\x0C";
+        let mut out = Vec::new();
+        counter_reading(&["gfwc", "-w"].map(String::from), Cursor::new(input_data))
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "5\n");
+        assert_eq!(Tokenizer::Whitespace.count(input_data), 5);
+        assert_eq!(
+            Tokenizer::Normalized.tokens(input_data).collect::<Vec<_>>(),
+            ["one", "two", "three", "four", "five"]
+        );
+        // A boundary between every byte still leaves each separator whole.
+        let summary = CountState::count_reader_in_chunks(input_data.as_bytes(), 1).unwrap();
+        assert_eq!(summary.words, 5);
+        let columns = ColumnRange::parse("1:100").unwrap();
+        assert_eq!(
+            Counter::count_words_in_columns_from_reader(
+                Cursor::new(input_data),
+                columns,
+                &Tokenizer::Whitespace
+            )
+            .unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_count_all_single_pass_fills_every_field() {
+        let input_data = "Héllo, wörld! 🌍\nRust\x0Bis fun.\n\n  trailing";
+        let counts =
+            Counter::count_all_from_input(input_data.as_bytes(), &CountOptions::default()).unwrap();
+        assert_eq!(
+            counts,
+            Counts {
+                bytes: input_data.len(),
+                lines: 3,
+                words: 7,
+                chars: input_data.chars().count(),
+                ..Counts::default()
+            }
+        );
+
+        let mut state = CountState::new();
+        state.update(input_data.as_bytes());
+        assert_eq!(counts, Counts::from(state.finish()));
+    }
+
+    /// The byte-at-a-time pass `count_all_from_input` used before it counted
+    /// newlines and characters with `bytecount`, kept as a reference.
+    fn count_all_bytewise(input_data: &[u8]) -> Counts {
+        let mut counts = Counts {
+            bytes: input_data.len(),
+            ..Counts::default()
+        };
+        let mut after_space = true;
+        for &byte in input_data {
+            counts.lines += usize::from(byte == b'\n');
+            counts.chars += usize::from(byte & 0xC0 != 0x80);
+            let space = is_c_space(byte);
+            counts.words += usize::from(after_space && !space);
+            after_space = space;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_count_all_matches_bytewise_pass() {
+        let units = [
+            "The quick brown fox jumps over the lazy dog.\n",
+            "🦀 héllo 🌍 wörld ✨ 日本語\n",
+            "a\nbb\n\nccc\n",
+            "\t\x0B\x0C\r \n",
+            "no newline at all ",
+        ];
+        // Lengths either side of the SIMD block sizes, so that multi-byte
+        // characters and newlines straddle block boundaries.
+        for unit in units {
+            for repeat in [0, 1, 2, 3, 7, 31, 64, 257, 1000] {
+                let input_data = unit.repeat(repeat);
+                for end in [input_data.len(), input_data.len().saturating_sub(1)] {
+                    let input_data = &input_data.as_bytes()[..end];
+                    assert_eq!(
+                        Counter::count_all_from_input(input_data, &CountOptions::default())
+                            .unwrap(),
+                        count_all_bytewise(input_data),
+                        "{:?} x {}",
+                        unit,
+                        repeat
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_tab_after_space() {
+        let input_data = "\tok\n  \tmixed\n\t  tab then spaces\nx \tnot indentation\n \t\n";
+        let offending = Counter::find_tab_after_space_from_reader(Cursor::new(input_data)).unwrap();
+        assert_eq!(offending, vec![2, 5]);
+    }
+
+    #[test]
+    fn test_count_plane_chars() {
+        let input_data = "a€🌍𓀀\u{20000}";
+        let count =
+            |plane| Counter::count_plane_chars_from_reader(Cursor::new(input_data), plane).unwrap();
+        assert_eq!(count(0), 2);
+        assert_eq!(count(1), 2);
+        assert_eq!(count(2), 1);
+        assert_eq!(count(14), 0);
+    }
+
+    /// Yields `data` in small reads, then fails.
+    struct FailingReader<'a> {
+        data: &'a [u8],
+    }
+
+    impl Read for FailingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.data.is_empty() {
+                return Err(io::Error::other("device went away"));
+            }
+            let n = self.data.len().min(buf.len()).min(4);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_count_reader_keeps_partial_counts_on_error() {
+        let reader = FailingReader {
+            data: b"one two\nthree ",
+        };
+        let partial = CountState::count_reader(reader).unwrap_err();
+        assert_eq!(partial.error.to_string(), "device went away");
+        assert_eq!(
+            partial.partial,
+            CountSummary {
+                bytes: 14,
+                chars: 14,
+                words: 3,
+                lines: 1,
+            }
+        );
+
+        let summary = CountState::count_reader("one two\n".as_bytes()).unwrap();
+        assert_eq!(summary.words, 2);
+    }
+
+    #[test]
+    fn test_count_bytes_surfaces_read_errors() {
+        let reader = io::BufReader::new(FailingReader { data: b"one two\n" });
+        let err = Counter::count_bytes_from_reader(reader).unwrap_err();
+        assert_eq!(err.to_string(), "device went away");
+
+        let reader = FailingReader { data: b"one two\n" };
+        let err = Counter::from_reader(reader, CountType::Plain(Columns::BYTES))
+            .count_into(Vec::new())
+            .unwrap_err();
+        assert!(matches!(err, CcwcError::Io(ref e) if e.to_string() == "device went away"));
+    }
+
+    #[test]
+    fn test_config_count_subcommand_matches_bare_invocation() {
+        let args = vec![
+            "gfwc".to_string(),
+            "count".to_string(),
+            "-l".to_string(),
+            "test.txt".to_string(),
+        ];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::LINES));
+        assert_eq!(config.file_paths, ["test.txt".to_string()]);
+
+        let args = vec!["gfwc".to_string(), "count".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::DEFAULT));
+        assert!(config.file_paths.is_empty());
+
+        let args = vec!["gfwc".to_string(), "./count".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.file_paths, ["./count".to_string()]);
+    }
+
+    #[test]
+    fn test_command_help_and_version_win_over_other_arguments() {
+        let args: Vec<String> = ["gfwc", "-l", "missing.txt", "-V"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert!(matches!(Command::parse(&args), Ok(Command::Version)));
+
+        let args: Vec<String> = ["gfwc", "count", "--version", "--plane", "99"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert!(matches!(Command::parse(&args), Ok(Command::Version)));
+
+        let args = vec!["gfwc".to_string(), "-l".to_string()];
+        assert!(matches!(Command::parse(&args), Ok(Command::Count(_))));
+
+        let args: Vec<String> = ["gfwc", "missing.txt", "-h", "--version"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert!(matches!(Command::parse(&args), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn test_count_trailing_nulls() {
+        let count = |input: &[u8]| Counter::count_trailing_nulls_from_reader(input).unwrap();
+        assert_eq!(count(b""), 0);
+        assert_eq!(count(b"record\0\0\0"), 3);
+        assert_eq!(count(b"\0\0a\0"), 1);
+        assert_eq!(count(b"\xFF\0\0"), 2);
+        assert_eq!(count(b"no padding"), 0);
+
+        // The run must carry across buffer refills.
+        let reader = io::BufReader::with_capacity(2, &b"ab\0\0\0\0\0"[..]);
+        assert_eq!(
+            Counter::count_trailing_nulls_from_reader(reader).unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_count_distinct_lengths() {
+        let count = |input: &str, unit| {
+            Counter::count_distinct_lengths_from_reader(Cursor::new(input), unit).unwrap()
+        };
+        assert_eq!(count("abc\ndef\nghi\n", LengthUnit::Chars), 1);
+        assert_eq!(count("abc\nde\n\nxyz", LengthUnit::Chars), 3);
+        assert_eq!(count("", LengthUnit::Chars), 0);
+        // "é" is one char but two bytes.
+        assert_eq!(count("ab\né\n", LengthUnit::Chars), 2);
+        assert_eq!(count("ab\né\n", LengthUnit::Bytes), 1);
+    }
+
+    #[test]
+    fn test_utf8_location() {
+        let locate = |bytes: &[u8]| {
+            let valid_up_to = std::str::from_utf8(bytes).unwrap_err().valid_up_to();
+            Utf8Location::of(bytes, valid_up_to)
+        };
+        assert_eq!(
+            locate(b"\xFFabc"),
+            Utf8Location {
+                offset: 0,
+                line: 1,
+                column: 1
+            }
+        );
+        assert_eq!(
+            locate(b"h\xC3\xA9\xFF"),
+            Utf8Location {
+                offset: 3,
+                line: 1,
+                column: 3
+            }
+        );
+        let location = locate(b"ok\nfine\nab\xC3(");
+        assert_eq!(
+            location,
+            Utf8Location {
+                offset: 10,
+                line: 3,
+                column: 3
+            }
+        );
+        assert_eq!(
+            location.to_string(),
+            "invalid UTF-8 at line 3, column 3 (byte offset 10)"
+        );
+    }
+
+    #[test]
+    fn test_select_lines() {
+        let input_data = "one\ntwo words\nthree\nfour";
+        let wanted = BTreeSet::from([0, 2, 4, 9]);
+        let (selected, missing) = select_lines(input_data, &wanted);
+        assert_eq!(selected, "two words\nfour");
+        assert_eq!(missing, vec![0, 9]);
+    }
+
+    #[test]
+    fn test_read_line_numbers() {
+        let path = std::env::temp_dir().join(format!("gfwc-lines-{}", std::process::id()));
+        std::fs::write(&path, "3\n1\n  7 3\n").unwrap();
+        let numbers = read_line_numbers(path.to_str().unwrap()).unwrap();
+        assert_eq!(numbers, BTreeSet::from([1, 3, 7]));
+
+        std::fs::write(&path, "3\nx\n").unwrap();
+        assert!(read_line_numbers(path.to_str().unwrap()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_count_words_does_not_allocate_per_line() {
+        let input_data = "a b\nc\n".repeat(100_000);
+        let (count, allocations) = allocations_during(|| {
+            Counter::count_words_from_reader(Cursor::new(input_data.as_str())).unwrap()
+        });
+        assert_eq!(count, 300_000);
+        // A `lines()`-based counter makes one allocation per line (200,000 here).
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn test_count_words_on_large_input_across_buffer_boundaries() {
+        let input_data = "lorem  ipsum\tdolor\n\nsit\x0Bamet,\u{a0}consectetur \r\n".repeat(20_000);
+        let expected = input_data
+            .split(|c: char| c.is_ascii() && is_c_space(c as u8))
+            .filter(|word| !word.is_empty())
+            .count();
+        assert_eq!(expected, 100_000);
+        // Odd capacities leave words and runs of spaces split between reads.
+        for capacity in [1, 7, 4096, 65536] {
+            let reader = io::BufReader::with_capacity(capacity, input_data.as_bytes());
+            assert_eq!(
+                Counter::count_words_from_reader(reader).unwrap(),
+                expected,
+                "capacity {}",
+                capacity
+            );
+        }
+    }
+
+    #[test]
+    fn test_count_trim_savings() {
+        let input_data = "clean\ntrailing  \ntab\t \r\n   \nnbsp\u{a0}";
+        let savings = Counter::count_trim_savings_from_reader(Cursor::new(input_data)).unwrap();
+        assert_eq!(savings, 2 + 2 + 3 + 2);
+    }
+
+    #[test]
+    fn test_program_name() {
+        let name = |arg0: &str| program_name(&[arg0.to_string()]);
+        assert_eq!(name("/usr/local/bin/wc"), "wc");
+        assert_eq!(name("./target/debug/gfwc"), "gfwc");
+        assert_eq!(name("ccwc"), "ccwc");
+        assert_eq!(name(""), "gfwc");
+        assert_eq!(program_name(&[]), "gfwc");
+    }
+
+    #[test]
+    fn test_indent_width() {
+        assert_eq!(indent_width("x", 8), 0);
+        assert_eq!(indent_width("    x", 8), 4);
+        assert_eq!(indent_width("\tx", 8), 8);
+        assert_eq!(indent_width("  \tx", 8), 8);
+        assert_eq!(indent_width("\t  x", 8), 10);
+        assert_eq!(indent_width("\tx", 4), 4);
+        assert_eq!(indent_width("  \tx", 4), 4);
+        assert_eq!(indent_width("\t  x", 4), 6);
+    }
+
+    #[test]
+    fn test_count_indent_changes() {
+        let input_data = "fn a() {\n    x;\n\n    y;\n}\n";
+        let count = |include_blank| {
+            Counter::count_indent_changes_from_reader(Cursor::new(input_data), include_blank, 8)
+                .unwrap()
+        };
+        assert_eq!(count(false), 2);
+        assert_eq!(count(true), 4);
+
+        // A tab lines up with four spaces only at --tab-width 4.
+        let output_for = |flags: &[&str]| {
+            let mut args = vec!["gfwc".to_string(), "--indent-changes".to_string()];
+            args.extend(flags.iter().map(|s| s.to_string()));
+            let mut out = Vec::new();
+            counter_reading(&args, Cursor::new("{\n\tx;\n    y;\n}\n"))
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(output_for(&[]), "3\n");
+        assert_eq!(output_for(&["--tab-width", "4"]), "2\n");
+    }
+
+    #[test]
+    fn test_count_case_lines() {
+        let input_data = "HELLO, WORLD!\nquiet please\nMixed Case\n1234 !!\n\nÉTÉ 2024\n";
+        let counts = Counter::count_case_lines_from_reader(Cursor::new(input_data)).unwrap();
+        assert_eq!(counts, (2, 1));
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(Encoding::detect(b"plain text"), Encoding::Ascii);
+        assert_eq!(Encoding::detect(b""), Encoding::Ascii);
+        assert_eq!(Encoding::detect("café".as_bytes()), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b"\xEF\xBB\xBFhi"), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b"caf\xE9"), Encoding::Latin1);
+        assert_eq!(Encoding::detect(b"\xFF\xFEh\0i\0"), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(b"h\0e\0l\0l\0o\0"), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(b"\0h\0e\0l\0l\0o"), Encoding::Utf16Be);
+        assert_eq!(Encoding::detect(b"\xFE\xFF\0h"), Encoding::Utf16Be);
+    }
+
+    #[cfg(feature = "hash")]
+    #[test]
+    fn test_hash_algo_digests() {
+        assert_eq!(
+            HashAlgo::Sha256.hex_digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(HashAlgo::Xxh64.hex_digest(b""), "ef46db3751d8e999");
+        assert!(HashAlgo::parse("md5").is_none());
+    }
+
+    #[cfg(feature = "hash")]
+    #[test]
+    fn test_with_hash_records_digest_while_reading() {
+        let path = std::env::temp_dir().join(format!("gfwc-hash-{}", std::process::id()));
+        std::fs::write(&path, "abc").unwrap();
+        let args = vec![
+            "gfwc".to_string(),
+            "-c".to_string(),
+            "--with-hash".to_string(),
+            "--hash-algo".to_string(),
+            "xxh64".to_string(),
+            path.to_string_lossy().into_owned(),
+        ];
+        let counter = Counter::from(Config::build(&args).unwrap());
+        assert_eq!(counter.count_bytes().unwrap(), 3);
+        assert_eq!(
+            counter.hash_column(),
+            format!(" {}", HashAlgo::Xxh64.hex_digest(b"abc"))
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hash")]
+    #[test]
+    fn test_hash_column_matches_for_identical_content() {
+        let dir = std::env::temp_dir();
+        let paths: Vec<String> = ["one two\nthree\n", "one two\nthree\n", "one two\nthree!\n"]
+            .iter()
+            .enumerate()
+            .map(|(i, contents)| {
+                let path = dir.join(format!("gfwc-hash-same-{}-{}", i, std::process::id()));
+                std::fs::write(&path, contents).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        let args: Vec<String> = ["gfwc", "--hash", "--read-chunk-size", "3"]
+            .iter()
+            .map(|s| s.to_string())
+            .chain(paths.iter().cloned())
+            .collect();
+        let mut out = Vec::new();
+        Counter::from(Config::build(&args).unwrap())
+            .count_into(&mut out)
+            .unwrap();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let same = HashAlgo::Sha256.hex_digest(b"one two\nthree\n");
+        let changed = HashAlgo::Sha256.hex_digest(b"one two\nthree!\n");
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                " 2  3 14 {same} {}\n 2  3 14 {same} {}\n 2  3 15 {changed} {}\n 6  9 43 total\n",
+                paths[0], paths[1], paths[2]
+            )
+        );
+    }
+
+    #[test]
+    fn test_base64_decode_skips_wrapping_whitespace() {
+        let decode = |input: &str| {
+            let mut out = Vec::new();
+            Base64Decode::new(input.as_bytes())
+                .read_to_end(&mut out)
+                .map(|_| out)
+        };
+        assert_eq!(
+            decode("aGVsbG8g\nd29y\r\nbGQK\n").unwrap(),
+            b"hello world\n"
+        );
+        assert_eq!(decode("YQ==").unwrap(), b"a");
+        assert_eq!(decode("YWI=").unwrap(), b"ab");
+        assert_eq!(decode("YWI").unwrap(), b"ab");
+        assert_eq!(decode("").unwrap(), b"");
+
+        let err = decode("aGVs*G8=").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "invalid base64 at byte 4: unexpected byte 0x2a"
+        );
+        assert!(decode("YQ==YQ==").is_err());
+        assert!(decode("YQ=Q").is_err());
+        assert!(decode("Y").is_err());
+    }
+
+    #[test]
+    fn test_first_token_freq_sorted_by_count_then_token() {
+        let input_data = "git status\n  ls -la\ngit log\n\ncd ..\nls\ngit push\ncd\n";
+        let freq = Counter::count_first_token_freq_from_reader(Cursor::new(input_data)).unwrap();
+        let expected: Vec<(String, usize)> = [("git", 3), ("cd", 2), ("ls", 2)]
+            .iter()
+            .map(|&(token, count)| (token.to_string(), count))
+            .collect();
+        assert_eq!(freq, expected);
+    }
+
+    #[test]
+    fn test_count_bounds_open_ended() {
+        let at_least = CountBounds::parse("100:").unwrap();
+        assert_eq!(
+            at_least.check("lines", 5),
+            Err("expected at least 100 lines, found 5".to_string())
+        );
+        assert!(at_least.check("lines", 100).is_ok());
+
+        let at_most = CountBounds::parse(":500").unwrap();
+        assert!(at_most.check("bytes", 500).is_ok());
+        assert!(at_most.check("bytes", 501).is_err());
+
+        let between = CountBounds::parse("2:3").unwrap();
+        assert_eq!(
+            between.check("words", 4),
+            Err("expected between 2 and 3 words, found 4".to_string())
+        );
+
+        for bad in [":", "3:2", "x:1", "5"] {
+            assert_eq!(CountBounds::parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_select_parity_is_one_based() {
+        let input_data = "key1\nvalue one\nkey2\nvalue two\nkey3";
+        assert_eq!(
+            select_parity(input_data, LineParity::Odd),
+            "key1\nkey2\nkey3"
+        );
+        assert_eq!(
+            select_parity(input_data, LineParity::Even),
+            "value one\nvalue two\n"
+        );
+        assert_eq!(select_parity("", LineParity::Odd), "");
+    }
+
+    #[test]
+    fn test_shortest_line_skips_blank_and_keeps_first_tie() {
+        let input_data = "hello\n\n   \nab\nñé\nworld\n";
+        let shortest = Counter::find_shortest_line_from_reader(Cursor::new(input_data)).unwrap();
+        assert_eq!(shortest, Some((2, 4)));
+        let blank = Counter::find_shortest_line_from_reader(Cursor::new("\n \n")).unwrap();
+        assert_eq!(blank, None);
+    }
+
+    #[cfg(feature = "normalize")]
+    #[test]
+    fn test_normalize_nfc_makes_char_counts_agree() {
+        let composed = "caf\u{e9}\n";
+        let decomposed = "cafe\u{301}\n";
+        let chars = |text: &str| Counter::count_chars_from_reader(Cursor::new(text)).unwrap();
+        assert_ne!(chars(composed), chars(decomposed));
+        assert_eq!(
+            chars(&Normalization::Nfc.apply(composed)),
+            chars(&Normalization::Nfc.apply(decomposed))
+        );
+        assert_eq!(chars(&Normalization::Nfc.apply(decomposed)), 5);
+        assert_eq!(chars(&Normalization::Nfd.apply(composed)), 6);
+    }
+
+    #[test]
+    fn test_vocab_coverage_counts_distinct_words() {
+        let input_data = "The cat saw the Dog\nthe cat\n";
+        let vocab: HashSet<String> = ["the", "cat", "dog"]
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        let coverage = |ignore_case| {
+            Counter::measure_vocab_coverage_from_reader(
+                Cursor::new(input_data),
+                &vocab,
+                &Tokenizer::Whitespace,
+                ignore_case,
+            )
+            .unwrap()
+        };
+        // The, cat, saw, the, Dog
+        assert_eq!(coverage(false), (2, 5));
+        // the, cat, saw, dog
+        assert_eq!(coverage(true), (3, 4));
+    }
+
+    #[test]
+    fn test_config_build_collects_every_file_path() {
+        let args: Vec<String> = ["gfwc", "a.txt", "-l", "b.txt", "c.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::LINES));
+        assert_eq!(config.file_paths, ["a.txt", "b.txt", "c.txt"]);
+    }
+
+    #[test]
+    fn test_count_keeps_going_past_unreadable_files() {
+        let path = std::env::temp_dir().join(format!("gfwc-multi-{}", std::process::id()));
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        let existing = path.to_string_lossy().into_owned();
+        let args = vec![
+            "gfwc".to_string(),
+            "-l".to_string(),
+            existing.clone(),
+            "/nonexistent/gfwc-missing".to_string(),
+            existing,
+        ];
+        let err = Counter::from(Config::build(&args).unwrap())
+            .count()
+            .unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.to_string(), "1 of 3 files could not be counted");
+    }
+
+    #[test]
+    fn test_count_summary_add_assign() {
+        let mut total = CountSummary {
+            bytes: 4,
+            chars: 3,
+            words: 2,
+            lines: 1,
+        };
+        total += total;
+        assert_eq!(
+            total,
+            CountSummary {
+                bytes: 8,
+                chars: 6,
+                words: 4,
+                lines: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_combined_flags_select_a_set_of_columns() {
+        let count_type = |flags: &[&str]| {
+            let mut args = vec!["gfwc".to_string()];
+            args.extend(flags.iter().map(|s| s.to_string()));
+            Config::build(&args)
+                .map(|config| config.count_type)
+                .map_err(|e| e.to_string())
+        };
+        let lines_words = CountType::Plain(Columns::LINES.union(Columns::WORDS));
+        assert_eq!(count_type(&["-lw"]), Ok(lines_words));
+        assert_eq!(count_type(&["-wl"]), Ok(lines_words));
+        assert_eq!(count_type(&["-w", "-l"]), Ok(lines_words));
+        assert_eq!(
+            count_type(&["-lwc"]),
+            Ok(CountType::Plain(Columns::DEFAULT))
+        );
+        assert_eq!(
+            count_type(&["-c", "-l", "-w"]),
+            Ok(CountType::Plain(Columns::DEFAULT))
+        );
+        assert_eq!(count_type(&[]), Ok(CountType::Plain(Columns::DEFAULT)));
+        assert!(count_type(&["-lx"]).is_err());
+        assert!(count_type(&["-xl"]).is_err());
+    }
+
+    #[test]
+    fn test_long_count_options_mix_with_short_flags() {
+        let count_type = |flags: &[&str]| {
+            let mut args = vec!["gfwc".to_string()];
+            args.extend(flags.iter().map(|s| s.to_string()));
+            Config::build(&args)
+                .map(|config| config.count_type)
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            count_type(&["--lines"]),
+            Ok(CountType::Plain(Columns::LINES))
+        );
+        assert_eq!(
+            count_type(&["--chars"]),
+            Ok(CountType::Plain(Columns::CHARS))
+        );
+        assert_eq!(
+            count_type(&["--bytes", "-l", "--words"]),
+            Ok(CountType::Plain(Columns::DEFAULT))
+        );
+        let args = vec!["gfwc".to_string(), "--foo".to_string()];
+        assert!(matches!(
+            Config::build(&args),
+            Err(CcwcError::InvalidFlag(flag)) if flag == "--foo"
+        ));
+    }
+
+    #[test]
+    fn test_all_prints_every_plain_count() {
+        let output_for = |flags: &[&str]| {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .collect();
+            let mut out = Vec::new();
+            counter_reading(&args, Cursor::new("héllo wörld\nbye\n"))
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(output_for(&[]), "      2       3      18\n");
+        assert_eq!(output_for(&["--all"]), "      2       3      16      18\n");
+        assert_eq!(output_for(&["-l", "--all"]), output_for(&["--all"]));
+        assert_eq!(
+            output_for(&["--all", "--labels"]),
+            "lines:2 words:3 chars:16 bytes:18\n"
+        );
+    }
+
+    #[test]
+    fn test_default_flags_are_overridden_by_the_command_line() {
+        let build = |defaults: &str, flags: &[&str]| {
+            let args = flags.iter().map(|s| s.to_string());
+            Config::build_count(defaults, args).map_err(|e| e.to_string())
+        };
+        let count_type =
+            |defaults: &str, flags: &[&str]| build(defaults, flags).map(|config| config.count_type);
+        let lines_words_chars = Columns::LINES.union(Columns::WORDS).union(Columns::CHARS);
+        assert_eq!(
+            count_type("-l -w -m", &[]),
+            Ok(CountType::Plain(lines_words_chars))
+        );
+        assert_eq!(count_type("", &[]), Ok(CountType::Plain(Columns::DEFAULT)));
+        assert_eq!(
+            count_type("  ", &[]),
+            Ok(CountType::Plain(Columns::DEFAULT))
+        );
+        assert_eq!(
+            count_type("-l -w -m", &["-c"]),
+            Ok(CountType::Plain(Columns::BYTES))
+        );
+        assert_eq!(
+            count_type("-m", &["-l", "-w"]),
+            Ok(CountType::Plain(Columns::LINES.union(Columns::WORDS)))
+        );
+        assert_eq!(count_type("-m", &["-L"]), Ok(CountType::MaxLineLength));
+        assert_eq!(
+            count_type("-L", &["-w"]),
+            Ok(CountType::Plain(Columns::WORDS))
+        );
+        assert_eq!(
+            build("-l -L", &[]).err().as_deref(),
+            Some("-L can't be combined with -l, -w, -c or -m.")
+        );
+
+        // Modifiers in the defaults still apply alongside command-line counts.
+        let config = build("--csv -m", &["-l", "notes.txt"]).unwrap();
+        assert_eq!(config.output_format, OutputFormat::Csv);
+        assert_eq!(config.count_type, CountType::Plain(Columns::LINES));
+        assert_eq!(config.file_paths, vec!["notes.txt".to_string()]);
+
+        for defaults in ["-l notes.txt", "-", "-l -- -w"] {
+            assert_eq!(
+                build(defaults, &[]).err().as_deref(),
+                Some("GFWC_DEFAULT_FLAGS can only hold options, not files."),
+                "{}",
+                defaults
+            );
+        }
+        assert_eq!(
+            build("-z", &[]).err(),
+            Some(CcwcError::InvalidFlag("-z".to_string()).to_string())
+        );
+    }
+
+    #[test]
+    fn test_count_max_line_length_expands_tabs() {
+        let width = |text: &str| {
+            Counter::count_max_line_length_from_reader(Cursor::new(text), &CountOptions::default())
+                .unwrap()
+        };
+        assert_eq!(width("short\nthe longest\nmid\n"), 11);
+        assert_eq!(width("\tx\n"), 9);
+        assert_eq!(width("abc\tx"), 9);
+        assert_eq!(width("12345678\tx\n"), 17);
+        assert_eq!(width("né\r\n"), 2);
+        assert_eq!(width(""), 0);
+    }
+
+    #[test]
+    fn test_max_line_length_flags() {
+        for flag in ["-L", "--max-line-length"] {
+            let args = vec!["gfwc".to_string(), flag.to_string()];
+            let config = Config::build(&args).unwrap();
+            assert_eq!(config.count_type, CountType::MaxLineLength);
+        }
+
+        for flags in [
+            &["-l", "-L"][..],
+            &["-L", "-w"],
+            &["--max-line-length", "--bytes"],
+            &["-lL"],
+            &["-Lm"],
+        ] {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .collect();
+            let error = Config::build(&args).err().unwrap();
+            assert_eq!(
+                error.to_string(),
+                "-L can't be combined with -l, -w, -c or -m.",
+                "{:?}",
+                flags
+            );
+            assert_eq!(error.exit_code(), 2);
+        }
+    }
+
+    #[test]
+    fn test_count_bytes_does_not_decode_the_input() {
+        let path = std::env::temp_dir().join(format!("gfwc-binary-{}", std::process::id()));
+        std::fs::write(&path, [0xFF, 0xFE, b'\n', 0x00]).unwrap();
+        let args = vec![
+            "gfwc".to_string(),
+            "-c".to_string(),
+            path.to_string_lossy().into_owned(),
+        ];
+        let count = Counter::from(Config::build(&args).unwrap()).count_bytes();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(count.unwrap(), 4);
+
+        let streamed = Counter::count_bytes_from_reader(Cursor::new(vec![0xFF; 20_000])).unwrap();
+        assert_eq!(streamed, 20_000);
+    }
+
+    #[test]
+    fn test_count_bytes_from_reader_reports_read_errors() {
+        let reader = io::BufReader::new(FailingReader { data: b"abc" });
+        assert!(Counter::count_bytes_from_reader(reader).is_err());
+    }
+
+    #[test]
+    fn test_byte_and_line_counts_accept_invalid_utf8() {
+        let data: &[u8] = &[0xFF, 0xFE, b'\n', b'a', b' ', 0xFF, b'\n'];
+        let counts = Counter::count_all_from_input(data, &CountOptions::default()).unwrap();
+        assert_eq!((counts.bytes, counts.lines, counts.words), (7, 2, 3));
+
+        let path = std::env::temp_dir().join(format!("gfwc-invalid-{}", std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        let counter_for = |flag: &str| {
+            let args = vec![
+                "gfwc".to_string(),
+                flag.to_string(),
+                path.to_string_lossy().into_owned(),
+            ];
+            Counter::from(Config::build(&args).unwrap())
+        };
+        let lines = counter_for("-l").count_lines();
+        let words = counter_for("-w").count_words();
+        let chars = counter_for("-m").count_chars();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lines.unwrap(), 2);
+        assert_eq!(words.unwrap(), 3);
+        assert_eq!(chars.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_dash_is_a_stdin_path() {
+        let args: Vec<String> = ["gfwc", "-l", "a.txt", "-", "b.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::LINES));
+        assert_eq!(config.file_paths, ["a.txt", "-", "b.txt"]);
+
+        let args = ["gfwc", "-l", "-", "a.txt", "-"].map(String::from);
+        assert!(matches!(Config::build(&args), Err(CcwcError::Usage(_))));
+
+        let args = vec!["gfwc".to_string(), "-".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::DEFAULT));
+        assert_eq!(config.file_paths, ["-"]);
+    }
+
+    #[test]
+    fn test_stdin_output_has_no_trailing_space() {
+        let summary = Counts {
+            bytes: 12,
+            chars: 12,
+            words: 3,
+            lines: 2,
+            max_line_length: 5,
+        };
+        let counter_for = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            Counter::from(Config::build(&args).unwrap())
+        };
+
+        let stdin = counter_for(&["gfwc"]);
+        let line = stdin.format_summary(&summary, &stdin.hash_column(), &stdin.label());
+        assert_eq!(line, "2 3 12");
+
+        let file = counter_for(&["gfwc", "-l", "notes.txt"]);
+        let line = file.format_summary(&summary, &file.hash_column(), &file.label());
+        assert_eq!(line, "2 notes.txt");
+
+        let dash = counter_for(&["gfwc", "-w", "-"]);
+        assert_eq!(dash.format_summary(&summary, "", &dash.label()), "3 -");
+    }
+
+    #[test]
+    fn test_counts_total_keeps_longest_line() {
+        let mut total = Counts {
+            lines: 2,
+            words: 3,
+            bytes: 10,
+            chars: 9,
+            max_line_length: 7,
+        };
+        total += Counts {
+            lines: 1,
+            words: 1,
+            bytes: 4,
+            chars: 4,
+            max_line_length: 3,
+        };
+        assert_eq!(
+            total,
+            Counts {
+                lines: 3,
+                words: 4,
+                bytes: 14,
+                chars: 13,
+                max_line_length: 7,
+            }
+        );
+    }
+
+    #[test]
+    fn test_count_into_writes_exact_output() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("gfwc-out-a-{}", std::process::id()));
+        let second = dir.join(format!("gfwc-out-b-{}", std::process::id()));
+        std::fs::write(&first, "one two\nthree\n").unwrap();
+        std::fs::write(&second, "four\n").unwrap();
+        let first = first.to_string_lossy().into_owned();
+        let second = second.to_string_lossy().into_owned();
+        let output_for = |args: &[&str]| {
+            let mut args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            args.insert(0, "gfwc".to_string());
+            let mut out = Vec::new();
+            Counter::from(Config::build(&args).unwrap())
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(output_for(&[&first]), format!(" 2  3 14 {}\n", first));
+        assert_eq!(
+            output_for(&["-lc", &first, &second]),
+            format!(" 2 14 {}\n 1  5 {}\n 3 19 total\n", first, second)
+        );
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+    }
+
+    #[test]
+    fn test_count_into_keeps_argument_order_across_workers() {
+        let dir = std::env::temp_dir();
+        let mut args = vec!["gfwc".to_string(), "-l".to_string()];
+        let mut expected = String::new();
+        let mut paths = Vec::new();
+        for i in 0..24 {
+            let path = dir.join(format!("gfwc-pool-{}-{}", i, std::process::id()));
+            // Larger files first, so later paths tend to finish earlier.
+            std::fs::write(&path, "line\n".repeat((24 - i) * 2000)).unwrap();
+            let path = path.to_string_lossy().into_owned();
+            // The files total 3,000,000 bytes, so counts are 7 wide.
+            expected.push_str(&format!("{:>7} {}\n", (24 - i) * 2000, path));
+            args.push(path.clone());
+            paths.push(path);
+        }
+        let missing = dir.join(format!("gfwc-pool-missing-{}", std::process::id()));
+        args.insert(5, missing.to_string_lossy().into_owned());
+        expected.push_str(&format!("{:>7} total\n", (1..=24).sum::<usize>() * 2000));
+
+        let mut out = Vec::new();
+        let err = Counter::from(Config::build(&args).unwrap())
+            .count_into(&mut out)
+            .unwrap_err();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(err.to_string(), "1 of 25 files could not be counted");
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_streamed_counts_match_in_memory_counts_on_large_input() {
+        // Several megabytes, so the input spans many buffer refills.
+        let mut data = Vec::new();
+        for i in 0..200_000 {
+            data.extend_from_slice(format!("{} wörd\tx\x0b\x0cy  ", i).as_bytes());
+            if i % 7 == 0 {
+                data.push(b'\n');
+            }
+        }
+        data.extend_from_slice(b"no newline at the end");
+        let path = std::env::temp_dir().join(format!("gfwc-large-{}", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let output_for = |flag: &str| {
+            let args = vec!["gfwc".to_string(), flag.to_string(), path.clone()];
+            let mut out = Vec::new();
+            Counter::from(Config::build(&args).unwrap())
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let expected = Counter::count_all_from_input(&data, &CountOptions::default()).unwrap();
+        let w = data.len().to_string().len();
+        assert_eq!(
+            output_for("-lwc"),
+            format!(
+                "{:>w$} {:>w$} {:>w$} {}\n",
+                expected.lines, expected.words, expected.bytes, path
+            )
+        );
+        assert_eq!(
+            output_for("-lw"),
+            format!("{:>w$} {:>w$} {}\n", expected.lines, expected.words, path)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_count_chars_across_buffer_boundaries() {
+        let input = "abc\u{1F600}d".as_bytes();
+        // The 4-byte emoji starts at offset 3, so every capacity below splits
+        // it between reads.
+        for capacity in 1..=8 {
+            let reader = io::BufReader::with_capacity(capacity, input);
+            assert_eq!(Counter::count_chars_from_reader(reader).unwrap(), 5);
+        }
+
+        let reader = io::BufReader::with_capacity(2, &b"a\xF0\x9F\x98b\xFF"[..]);
+        assert_eq!(Counter::count_chars_from_reader(reader).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_counter_from_reader_counts_without_a_file() {
+        let output_for = |count_type| {
+            let mut out = Vec::new();
+            Counter::from_reader(Cursor::new("tab\there\nx\n"), count_type)
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(output_for(CountType::MaxLineLength), "12\n");
+        assert_eq!(output_for(CountType::Plain(Columns::CHARS)), "11\n");
+        assert_eq!(
+            output_for(CountType::Plain(Columns::LINES.union(Columns::BYTES))),
+            "      2      11\n"
+        );
+
+        // Once the reader is drained, a second count sees an empty input.
+        let counter =
+            Counter::from_reader(Cursor::new("a b\n"), CountType::Plain(Columns::DEFAULT));
+        assert_eq!(counter.count_all().unwrap().words, 2);
+        assert_eq!(counter.count_all().unwrap().words, 0);
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let config = Config::builder().build();
+        assert_eq!(config.count_type, CountType::Plain(Columns::DEFAULT));
+        assert!(config.file_paths.is_empty());
+        assert_eq!(config.program, "gfwc");
+
+        let config = Config::builder()
+            .count_type(CountType::Plain(Columns::LINES))
+            .file_path("a.txt")
+            .file_path(String::from("-"))
+            .build();
+        assert_eq!(config.count_type, CountType::Plain(Columns::LINES));
+        assert_eq!(config.file_paths, ["a.txt", "-"]);
+    }
+
+    #[test]
+    fn test_errors_can_be_told_apart() {
+        let args = vec!["gfwc".to_string(), "/nonexistent/gfwc-missing".to_string()];
+        let err = Counter::from(Config::build(&args).unwrap())
+            .count_into(Vec::new())
+            .unwrap_err();
+        let CcwcError::File { path, error } = &err else {
+            panic!("expected a file error, got {:?}", err);
+        };
+        assert_eq!(path, "/nonexistent/gfwc-missing");
+        assert!(matches!(**error, CcwcError::Io(ref e) if e.kind() == io::ErrorKind::NotFound));
+        assert_eq!(
+            err.to_string(),
+            "/nonexistent/gfwc-missing: No such file or directory"
+        );
+
+        let err = Counter::from_reader(Cursor::new("(]"), CountType::BracketBalance)
+            .count_into(Vec::new())
+            .unwrap_err();
+        assert!(matches!(err, CcwcError::Check(_)));
+        assert_eq!(err.to_string(), "brackets are not balanced");
+
+        let args = vec!["gfwc".to_string(), "--top".to_string()];
+        assert!(matches!(
+            Config::build(&args),
+            Err(CcwcError::Usage("--top requires a count"))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_recursive_counts_files_under_directories() {
+        let root = std::env::temp_dir().join(format!("gfwc-tree-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        std::fs::write(root.join("b.txt"), "one\n").unwrap();
+        std::fs::write(root.join("sub/a.txt"), "two\nthree\n").unwrap();
+        std::fs::write(root.join("sub/deeper/c.txt"), "four\n").unwrap();
+        // A link back to the root must not be walked again.
+        std::os::unix::fs::symlink(&root, root.join("sub/loop")).unwrap();
+        let elsewhere = std::env::temp_dir().join(format!("gfwc-elsewhere-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&elsewhere);
+        std::fs::create_dir_all(&elsewhere).unwrap();
+        std::fs::write(elsewhere.join("e.txt"), "five\nsix\nseven\n").unwrap();
+        std::os::unix::fs::symlink(&elsewhere, root.join("sub/linked")).unwrap();
+        let root_path = root.to_string_lossy().into_owned();
+
+        let output_for = |flags: &[&str]| {
+            let mut args = vec!["gfwc".to_string()];
+            args.extend(flags.iter().map(|s| s.to_string()));
+            args.push(root_path.clone());
+            let mut out = Vec::new();
+            let result = Counter::from(Config::build(&args).unwrap()).count_into(&mut out);
+            (String::from_utf8(out).unwrap(), result)
+        };
+
+        let (out, result) = output_for(&["-l", "-r"]);
+        assert!(result.is_ok());
+        assert_eq!(
+            out,
+            format!(
+                " 1 {root}/b.txt\n 2 {root}/sub/a.txt\n 1 {root}/sub/deeper/c.txt\n 4 total\n",
+                root = root_path
+            )
+        );
+
+        // Followed, the loop is still walked only once.
+        let (out, result) = output_for(&["-l", "-r", "--follow-symlinks"]);
+        assert!(result.is_ok());
+        assert_eq!(
+            out,
+            format!(
+                " 1 {root}/b.txt\n 2 {root}/sub/a.txt\n 1 {root}/sub/deeper/c.txt\n \
+                 3 {root}/sub/linked/e.txt\n 7 total\n",
+                root = root_path
+            )
+        );
+
+        let (out, result) = output_for(&["-l", "-r", "--max-depth", "0"]);
+        assert!(result.is_ok());
+        assert_eq!(out, format!("1 {}/b.txt\n", root_path));
+
+        let (out, result) = output_for(&["-l", "-r", "--max-depth", "1"]);
+        assert!(result.is_ok());
+        assert_eq!(
+            out,
+            format!(
+                " 1 {root}/b.txt\n 2 {root}/sub/a.txt\n 3 total\n",
+                root = root_path
+            )
+        );
+
+        let (out, result) = output_for(&["-l"]);
+        assert_eq!(out, "");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            format!("{}: Is a directory", root_path)
+        );
+
+        let args = ["gfwc", "--follow-symlinks", "."].map(String::from);
+        assert!(matches!(
+            Config::build(&args),
+            Err(CcwcError::Usage(
+                "--follow-symlinks only applies to --recursive (-r)."
+            ))
+        ));
+        let args = ["gfwc", "--max-depth", "1", "."].map(String::from);
+        assert!(matches!(
+            Config::build(&args),
+            Err(CcwcError::Usage(
+                "--max-depth only applies to --recursive (-r)."
+            ))
+        ));
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::remove_dir_all(&elsewhere).unwrap();
+    }
+
+    #[test]
+    fn test_count_each_passes_every_result_in_path_order() {
+        let dir = std::env::temp_dir().join(format!("gfwc-each-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths = Vec::new();
+        for (index, contents) in ["one\n", "", "two words\nand more\n", "x"]
+            .iter()
+            .enumerate()
+        {
+            let path = dir.join(format!("{}.txt", index));
+            std::fs::write(&path, contents).unwrap();
+            paths.push(path.to_string_lossy().into_owned());
+        }
+        paths.insert(2, dir.join("missing.txt").to_string_lossy().into_owned());
+
+        // Pooled by default, one path after another without `threads`: the
+        // results must come out the same either way.
+        let args = ["gfwc", "-l"].map(String::from);
+        let counter = Counter::from(Config::build(&args).unwrap());
+        let mut seen = Vec::new();
+        counter
+            .count_each(&paths, &mut |index, (_, outcome)| {
+                seen.push((index, outcome.map(|counts| counts.map(|c| c.lines))));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            seen,
+            vec![
+                (0, Ok(Some(1))),
+                (1, Ok(Some(0))),
+                (2, Err("No such file or directory".to_string())),
+                (3, Ok(Some(2))),
+                (4, Ok(Some(0))),
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_csv_rows_reads_back_quoted_names() {
+        assert_eq!(
+            parse_csv_header("lines,chars,filename"),
+            Some(Columns::LINES.union(Columns::CHARS))
+        );
+        for bad in [
+            "",
+            "filename",
+            "words,lines,filename",
+            "lines,words",
+            "lines,total,filename",
+        ] {
+            assert_eq!(parse_csv_header(bad), None, "{:?}", bad);
+        }
+
+        let rows = "1,2,a.txt\n3,4,\"b,\"\"c\"\"\nd\"\n5,6,total\n";
+        let counts = |lines, words| Counts {
+            lines,
+            words,
+            ..Counts::default()
+        };
+        assert_eq!(
+            parse_csv_rows(rows, Columns::LINES.union(Columns::WORDS)),
+            Ok(vec![
+                (counts(1, 2), "a.txt".to_string()),
+                (counts(3, 4), "b,\"c\"\nd".to_string()),
+                (counts(5, 6), "total".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse_csv_rows("1,x,a\n", Columns::LINES.union(Columns::WORDS)),
+            Err("row 1: \"x\" is not a words count".to_string())
+        );
+        assert_eq!(
+            parse_csv_rows("1,\"a\n", Columns::LINES),
+            Err("row 1 has an unterminated file name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_csv_output_quotes_awkward_filenames() {
+        let dir = std::env::temp_dir();
+        let plain = dir.join(format!("gfwc-csv-{}", std::process::id()));
+        let awkward = dir.join(format!("gfwc-csv-a,\"b\"-{}", std::process::id()));
+        std::fs::write(&plain, "one two\n").unwrap();
+        std::fs::write(&awkward, "three\n").unwrap();
+        let plain = plain.to_string_lossy().into_owned();
+        let awkward = awkward.to_string_lossy().into_owned();
+
+        let args: Vec<String> = ["gfwc", "--csv", &plain, &awkward]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let mut out = Vec::new();
+        Counter::from(Config::build(&args).unwrap())
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "lines,words,bytes,filename\n1,2,8,{}\n1,1,6,\"{}\"\n2,3,14,total\n",
+                plain,
+                awkward.replace('"', "\"\"")
+            )
+        );
+
+        let mut out = Vec::new();
+        let count_type = CountType::Plain(Columns::LINES.union(Columns::CHARS));
+        let counter = Counter {
+            output_format: OutputFormat::Csv,
+            ..Counter::from_reader(Cursor::new("héllo\n"), count_type)
+        };
+        counter.count_into(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "lines,chars,filename\n1,6,\n"
+        );
+
+        std::fs::remove_file(&plain).unwrap();
+        std::fs::remove_file(&awkward).unwrap();
+    }
+
+    #[cfg(feature = "segmentation")]
+    #[test]
+    fn test_graphemes_count_combining_sequences_once() {
+        let graphemes =
+            |text: &str| Counter::count_graphemes_from_reader(Cursor::new(text)).unwrap();
+        assert_eq!(graphemes("e\u{0301}"), 1);
+        assert_eq!(
+            Counter::count_chars_from_reader(Cursor::new("e\u{0301}")).unwrap(),
+            2
+        );
+        assert_eq!(graphemes("\u{1F1EB}\u{1F1F7} ok\n"), 5);
+        assert_eq!(graphemes("a\r\nb\n"), 4);
+        assert_eq!(graphemes(""), 0);
 
-impl Config {
-    pub fn build(args: &[String]) -> Result<Config, &'static str> {
-        // Case: only std input
-        if args.len() == 1 {
-            return Ok(Config {
-                count_type: CountType::AllCount,
-                file_path: None,
-            });
-        }
+        let mut out = Vec::new();
+        let args = ["gfwc", "--graphemes"].map(String::from);
+        counter_reading(&args, Cursor::new("cafe\u{0301}\n"))
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "5\n");
+    }
 
-        // Case: Only file path is provided
-        if args.len() == 2 && !args[1].starts_with('-') {
-            return Ok(Config {
-                count_type: CountType::AllCount,
-                file_path: Some(args[1].clone()),
-            });
+    #[cfg(feature = "segmentation")]
+    #[test]
+    fn test_words_unicode_segments_mixed_scripts() {
+        let text = "Hello, 世界! naïve café-au-lait\n";
+        assert_eq!(
+            Counter::count_words_from_reader(Cursor::new(text)).unwrap(),
+            4
+        );
+        assert_eq!(
+            Counter::count_unicode_words_from_reader(Cursor::new(text)).unwrap(),
+            7
+        );
+
+        let args: Vec<String> = ["gfwc", "-w", "--words-unicode"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let config = Config::build(&args).unwrap();
+        assert!(matches!(config.options.tokenizer, Tokenizer::UnicodeWords));
+    }
+
+    #[test]
+    fn test_crlf_and_lf_line_endings_count_alike() {
+        let mixed = "one\r\ntwo three\nfour\r\n";
+        let unix = mixed.replace("\r\n", "\n");
+        let output_for = |text: String, count_type| {
+            let mut out = Vec::new();
+            Counter::from_reader(Cursor::new(text), count_type)
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let all = CountType::Plain(Columns::DEFAULT);
+        assert_eq!(
+            output_for(mixed.to_string(), all),
+            "      3       4      21\n"
+        );
+        assert_eq!(output_for(unix.clone(), all), "      3       4      19\n");
+        assert_eq!(
+            output_for(mixed.to_string(), CountType::MaxLineLength),
+            output_for(unix, CountType::MaxLineLength)
+        );
+    }
+
+    #[test]
+    fn test_count_paragraphs() {
+        let count = |text: &str| Counter::count_paragraphs_from_reader(Cursor::new(text)).unwrap();
+        assert_eq!(count(""), 0);
+        assert_eq!(count("\n\n  \n"), 0);
+        assert_eq!(count("one line"), 1);
+        assert_eq!(count("a\nb\n\nc\n"), 2);
+        assert_eq!(count("\n\nfirst\n\n\n \t\nsecond\nstill second\n\n\n"), 2);
+
+        let args = vec!["gfwc".to_string(), "--paragraphs".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::ParagraphCount);
+    }
+
+    #[test]
+    fn test_unique_and_duplicate_lines() {
+        let count = |flag: &str, input: &'static str| {
+            let args = vec!["gfwc".to_string(), flag.to_string()];
+            let mut out = Vec::new();
+            counter_reading(&args, Cursor::new(input))
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        for (input, unique, duplicate) in [
+            ("", "0\n", "0\n"),
+            ("a\nb\nc\n", "3\n", "0\n"),
+            ("a\nb\na\nc\nb\na\n", "3\n", "2\n"),
+            // Whether the last line ends in a newline doesn't matter.
+            ("a\nb\na", "2\n", "1\n"),
+            ("a\r\na\n\n\n", "2\n", "2\n"),
+        ] {
+            assert_eq!(count("--unique-lines", input), unique, "{:?}", input);
+            assert_eq!(count("--duplicate-lines", input), duplicate, "{:?}", input);
         }
+    }
 
-        // Case: Flag is provided
-        if args.len() >= 3 && args[1].starts_with('-') {
-            if let Some(count_type) = Self::_parse_type(&args[1]) {
-                return Ok(Config {
-                    count_type,
-                    file_path: Some(args[2].clone()),
-                });
-            } else {
-                return Err("Invalid flag. Use 'c' for byte count, 'l' for line count, 'w' for word count, or 'm' for character count.");
-            }
+    #[test]
+    fn test_count_line_endings() {
+        let endings = |input: &[u8], capacity: usize| {
+            Counter::count_line_endings_from_reader(io::BufReader::with_capacity(capacity, input))
+                .unwrap()
+                .to_string()
+        };
+        for capacity in [1, 2, 64] {
+            assert_eq!(endings(b"", capacity), "LF:0 CRLF:0 CR:0");
+            assert_eq!(endings(b"a\nb\nc\n", capacity), "LF:3 CRLF:0 CR:0");
+            assert_eq!(endings(b"a\r\nb\r\nc", capacity), "LF:0 CRLF:2 CR:0");
+            assert_eq!(
+                endings(b"a\nb\r\nc\rd\r\r\ne\r", capacity),
+                "LF:1 CRLF:2 CR:3",
+                "capacity {}",
+                capacity
+            );
         }
 
-        // Case: Only a flag is provided without a file path
-        if args.len() == 2 && args[1].starts_with('-') {
-            if let Some(count_type) = Self::_parse_type(&args[1]) {
-                return Ok(Config {
-                    count_type,
-                    file_path: None,
-                });
-            } else {
-                return Err("Invalid flag. Use 'c' for byte count, 'l' for line count, 'w' for word count, or 'm' for character count.");
-            }
+        let args = vec!["gfwc".to_string(), "--line-endings".to_string()];
+        let mut out = Vec::new();
+        counter_reading(&args, Cursor::new("one\r\ntwo\n"))
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(out, b"LF:1 CRLF:1 CR:0\n");
+    }
+
+    #[test]
+    fn test_count_sentences() {
+        let count = counting::count_sentences;
+        assert_eq!(count(""), 0);
+        assert_eq!(count("No terminator here"), 0);
+        assert_eq!(count("One. Two! Three? Four."), 4);
+        assert_eq!(count("It costs 3.14 today. Not 2.5 tomorrow."), 2);
+        assert_eq!(count("Wait... Is it over... or not?"), 2);
+        assert_eq!(count("Really?! Yes."), 2);
+        assert_eq!(count("Ask Dr. Jones, e.g. Monday. Or Mrs. Lee."), 2);
+        assert_eq!(count("\"Stop.\" She did.\n"), 2);
+        assert_eq!(count("lower. case next"), 0);
+
+        let args = vec!["gfwc".to_string(), "--sentences".to_string()];
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Sentences);
+    }
+
+    #[test]
+    fn test_metadata_byte_count_matches_streamed_count() {
+        let path = std::env::temp_dir().join(format!("gfwc-metadata-{}", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        // A size that is a multiple of the page size is checked by reading.
+        for size in [100_003, 2 * PAGE_SIZE as usize] {
+            let data: Vec<u8> = (0..=255u8).cycle().take(size).collect();
+            std::fs::write(&path, &data).unwrap();
+
+            let args = vec!["gfwc".to_string(), "-c".to_string(), path.clone()];
+            let counter = Counter::from(Config::build(&args).unwrap());
+            let from_metadata = counter.count_bytes().unwrap();
+            let streamed =
+                Counter::count_bytes_from_reader(io::BufReader::new(File::open(&path).unwrap()))
+                    .unwrap();
+            assert_eq!(from_metadata, data.len());
+            assert_eq!(from_metadata, streamed);
         }
+        std::fs::remove_file(&path).unwrap();
+    }
 
-        Err("Incorrect usage. Usage: <program> <flag> <file_path>")
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_byte_count_reads_files_that_report_no_size() {
+        let path = "/proc/version";
+        assert_eq!(std::fs::metadata(path).unwrap().len(), 0);
+        let args = ["gfwc", "-c", path].map(String::from);
+        let counter = Counter::from(Config::build(&args).unwrap());
+        let bytes = counter.count_bytes().unwrap();
+        assert!(bytes > 0);
+        assert_eq!(bytes, std::fs::read(path).unwrap().len());
     }
 
-    fn _parse_type(arg: &str) -> Option<CountType> {
-        match arg.chars().last()? {
-            'c' => Some(CountType::ByteCount),
-            'l' => Some(CountType::LineCount),
-            'w' => Some(CountType::WordCount),
-            'm' => Some(CountType::CharCount),
-            _ => None, // Invalid flag case
+    #[test]
+    fn test_argfile_lists_paths_skipping_comments_and_blank_lines() {
+        let dir = std::env::temp_dir();
+        let list = dir.join(format!("gfwc-argfile-{}", std::process::id()));
+        std::fs::write(
+            &list,
+            "# inputs\nfirst.txt\n\n   \nwith space.txt\r\n  # indented comment\nlast.txt",
+        )
+        .unwrap();
+        let list = list.to_string_lossy().into_owned();
+        let file_paths = |args: &[&str]| {
+            let args: Vec<String> = ["gfwc"].iter().chain(args).map(|s| s.to_string()).collect();
+            Config::build(&args).map(|config| config.file_paths)
+        };
+
+        assert_eq!(
+            file_paths(&["before.txt", &format!("@{}", list), "after.txt"]).unwrap(),
+            [
+                "before.txt",
+                "first.txt",
+                "with space.txt",
+                "last.txt",
+                "after.txt"
+            ]
+        );
+        // A lone @, or one after --, is a file name.
+        let after_dashes = format!("@{}", list);
+        assert_eq!(
+            file_paths(&["@", "--", &after_dashes]).unwrap(),
+            ["@", after_dashes.as_str()]
+        );
+        let missing = format!("{}-missing", list);
+        assert_eq!(
+            file_paths(&[&format!("@{}", missing)])
+                .unwrap_err()
+                .to_string(),
+            format!("{}: No such file or directory", missing)
+        );
+        std::fs::remove_file(&list).unwrap();
+    }
+
+    #[test]
+    fn test_files0_from_counts_each_listed_path() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let first = dir.join(format!("gfwc-files0-a-{}", id));
+        let second = dir.join(format!("gfwc-files0-b-{}", id));
+        let list = dir.join(format!("gfwc-files0-list-{}", id));
+        let empty = dir.join(format!("gfwc-files0-empty-{}", id));
+        std::fs::write(&first, "one two\n").unwrap();
+        std::fs::write(&second, "three\n").unwrap();
+        let first = first.to_string_lossy().into_owned();
+        let second = second.to_string_lossy().into_owned();
+        std::fs::write(&list, format!("{}\0{}\0", first, second)).unwrap();
+        std::fs::write(&empty, "").unwrap();
+        let output_for = |args: &[String]| {
+            let mut args = args.to_vec();
+            args.insert(0, "gfwc".to_string());
+            let mut out = Vec::new();
+            Counter::from(Config::build(&args).unwrap())
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            output_for(&[
+                "-l".to_string(),
+                format!("--files0-from={}", list.to_string_lossy())
+            ]),
+            format!(" 1 {}\n 1 {}\n 2 total\n", first, second)
+        );
+        assert_eq!(
+            output_for(&[
+                "--files0-from".to_string(),
+                empty.to_string_lossy().into_owned()
+            ]),
+            ""
+        );
+
+        let args: Vec<String> = ["gfwc", "--files0-from=list", "extra.txt"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert!(Config::build(&args).is_err());
+        for path in [&first, &second] {
+            std::fs::remove_file(path).unwrap();
         }
+        std::fs::remove_file(&list).unwrap();
+        std::fs::remove_file(&empty).unwrap();
     }
 
-    fn get_count_type(&self) -> CountType {
-        self.count_type
+    #[test]
+    fn test_columns_align_across_inputs() {
+        let dir = std::env::temp_dir();
+        let small = dir.join(format!("gfwc-align-s-{}", std::process::id()));
+        let large = dir.join(format!("gfwc-align-l-{}", std::process::id()));
+        std::fs::write(&small, "x\n").unwrap();
+        std::fs::write(&large, "word ".repeat(2000) + "\n").unwrap();
+        let small = small.to_string_lossy().into_owned();
+        let large = large.to_string_lossy().into_owned();
+
+        let args = vec!["gfwc".to_string(), small.clone(), large.clone()];
+        let mut out = Vec::new();
+        Counter::from(Config::build(&args).unwrap())
+            .count_into(&mut out)
+            .unwrap();
+        // 10,003 bytes in all, so every count is 5 wide.
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "    1     1     2 {}\n    1  2000 10001 {}\n    2  2001 10003 total\n",
+                small, large
+            )
+        );
+        std::fs::remove_file(&small).unwrap();
+        std::fs::remove_file(&large).unwrap();
+    }
+
+    #[test]
+    fn test_total_modes() {
+        let dir = std::env::temp_dir();
+        let paths: Vec<String> = (0..3)
+            .map(|i| {
+                let path = dir.join(format!("gfwc-total-{}-{}", i, std::process::id()));
+                std::fs::write(&path, "a\n".repeat(i + 1)).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        let output_for = |when: &str, files: &[String]| {
+            let mut args = vec![
+                "gfwc".to_string(),
+                "-l".to_string(),
+                format!("--total={}", when),
+            ];
+            args.extend_from_slice(files);
+            let mut out = Vec::new();
+            Counter::from(Config::build(&args).unwrap())
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let one = &paths[..1];
+        let [a, b, c] = [&paths[0], &paths[1], &paths[2]];
+
+        assert_eq!(output_for("auto", one), format!("1 {}\n", a));
+        assert_eq!(output_for("always", one), format!("1 {}\n1 total\n", a));
+        assert_eq!(output_for("never", one), format!("1 {}\n", a));
+        assert_eq!(output_for("only", one), "1\n");
+
+        // 12 bytes in all, so counts are 2 wide.
+        let lines = format!(" 1 {}\n 2 {}\n 3 {}\n", a, b, c);
+        assert_eq!(output_for("auto", &paths), format!("{} 6 total\n", lines));
+        assert_eq!(output_for("always", &paths), format!("{} 6 total\n", lines));
+        assert_eq!(output_for("never", &paths), lines);
+        assert_eq!(output_for("only", &paths), " 6\n");
+
+        let args = vec!["gfwc".to_string(), "--total=sometimes".to_string()];
+        assert!(Config::build(&args).is_err());
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
     }
-    fn get_file_path(&self) -> Option<String> {
-        self.file_path.clone()
+
+    #[test]
+    fn test_summary_prints_only_the_labelled_totals() {
+        let dir = std::env::temp_dir();
+        let paths: Vec<String> = ["one\n", "two words\n", "three more words\n"]
+            .iter()
+            .enumerate()
+            .map(|(i, contents)| {
+                let path = dir.join(format!("gfwc-summary-{}-{}", i, std::process::id()));
+                std::fs::write(&path, contents).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        let output_for = |flags: &[&str]| {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .chain(paths.iter().cloned())
+                .collect();
+            let mut out = Vec::new();
+            Counter::from(Config::build(&args).unwrap())
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            output_for(&["--summary"]),
+            "files:3 lines:3 words:6 bytes:31\n"
+        );
+        assert_eq!(
+            output_for(&["--summary", "-lc"]),
+            "files:3 lines:3 bytes:31\n"
+        );
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        for bad in [&["--summary", "--csv"][..], &["--summary", "-L"]] {
+            let args: Vec<String> = ["gfwc"].iter().chain(bad).map(|s| s.to_string()).collect();
+            assert!(Config::build(&args).is_err(), "{:?}", bad);
+        }
     }
-}
 
-pub struct Counter {
-    count_type: CountType,
-    file_path: Option<String>,
-}
+    #[test]
+    fn test_sort_orders_inputs_regardless_of_argument_order() {
+        let dir = std::env::temp_dir();
+        // Names, sizes and line counts each put the files in a different order.
+        let contents = [("b", "x\nx\nx\n"), ("a", "longer line\n"), ("c", "x\nx\n")];
+        let paths: Vec<String> = contents
+            .iter()
+            .map(|(name, text)| {
+                let path = dir.join(format!("gfwc-sort-{}-{}", name, std::process::id()));
+                std::fs::write(&path, text).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        let output_for = |sort: &str, files: [&String; 3]| {
+            let mut args = vec!["gfwc".to_string(), "-lc".to_string(), sort.to_string()];
+            args.extend(files.iter().map(|path| path.to_string()));
+            let mut out = Vec::new();
+            Counter::from(Config::build(&args).unwrap())
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let [b, a, c] = [&paths[0], &paths[1], &paths[2]];
 
-impl Counter {
-    pub fn count(self) -> Result<(), Box<dyn Error>> {
-        let filename = match &self.file_path {
-            Some(file_path) => file_path,
-            None => &String::from(""),
+        let by_name = format!(" 1 12 {}\n 3  6 {}\n 2  4 {}\n 6 22 total\n", a, b, c);
+        for files in [[a, b, c], [c, b, a], [b, a, c]] {
+            assert_eq!(output_for("--sort=name", files), by_name);
+        }
+        assert_eq!(
+            output_for("--sort=size", [a, b, c]),
+            format!(" 2  4 {}\n 3  6 {}\n 1 12 {}\n 6 22 total\n", c, b, a)
+        );
+        assert_eq!(
+            output_for("--sort=lines", [b, c, a]),
+            format!(" 1 12 {}\n 2  4 {}\n 3  6 {}\n 6 22 total\n", a, c, b)
+        );
+
+        let build = |flags: &[&str]| {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .collect();
+            Config::build(&args).map(|_| ()).map_err(|e| e.to_string())
         };
-        match self.count_type {
-            CountType::AllCount => {
-                // Concurrently calculate bytes, lines, and words
-                let (byte_count, line_count, word_count) = self.count_all()?;
-                println!(
-                    "{}\t{}\t{} {}",
-                    line_count, word_count, byte_count, filename
-                )
-            }
-            CountType::ByteCount => {
-                let count = self.count_bytes()?;
-                println!("{} {}", count, filename);
-            }
-            CountType::LineCount => {
-                let count = self.count_lines()?;
-                println!("{} {}", count, filename);
-            }
-            CountType::WordCount => {
-                let count = self.count_words()?;
-                println!("{} {}", count, filename);
-            }
-            CountType::CharCount => {
-                let count = self.count_chars()?;
-                println!("{} {}", count, filename);
-            }
+        assert_eq!(
+            build(&["--sort=date"]),
+            Err("Unknown --sort. Use name, size or lines.".to_string())
+        );
+        assert_eq!(
+            build(&["-w", "--sort=lines"]),
+            Err("--sort=lines needs the line count in the output (default or -l).".to_string())
+        );
+        assert!(build(&["--sort=lines"]).is_ok());
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
         }
-        Ok(())
     }
 
-    pub fn count_all(&self) -> Result<(usize, usize, usize), io::Error> {
-        // Read entire input once to ensure safe concurrent access
-        let input_data = Arc::new(self.read_input()?);
-        Self::count_all_from_input(input_data)
+    #[test]
+    fn test_display_counts() {
+        let counts = Counts {
+            lines: 2,
+            words: 3,
+            bytes: 12,
+            chars: 11,
+            max_line_length: 5,
+        };
+        assert_eq!(counts.to_string(), "2 3 12");
+        assert_eq!(format!("{:3}", counts), "  2   3  12");
+        assert_eq!(counts.display(Columns::CHARS).to_string(), "11");
+        assert_eq!(
+            counts
+                .display(Columns::BYTES.union(Columns::LINES).union(Columns::CHARS))
+                .to_string(),
+            "2 11 12"
+        );
+        assert_eq!(counts.display(Columns::NONE).to_string(), "");
     }
 
-    pub fn count_bytes(&self) -> Result<usize, io::Error> {
-        let input_data = self.read_input()?;
-        Self::count_bytes_from_reader(Cursor::new(input_data.as_str()))
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_counts_round_trip_through_serde_json() {
+        let counts = Counts {
+            lines: 2,
+            words: 3,
+            bytes: 14,
+            chars: 13,
+            max_line_length: 7,
+        };
+        let json = serde_json::to_string(&counts).unwrap();
+        assert_eq!(
+            json,
+            r#"{"lines":2,"words":3,"bytes":14,"chars":13,"max_line_length":7}"#
+        );
+        assert_eq!(serde_json::from_str::<Counts>(&json).unwrap(), counts);
     }
 
-    pub fn count_lines(&self) -> Result<usize, io::Error> {
-        let input_data = self.read_input()?;
-        Self::count_lines_from_reader(Cursor::new(input_data.as_str()))
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_count_to_json_returns_an_object_of_counts() {
+        let value = count_to_json(
+            Cursor::new("one two\nthree\n"),
+            CountType::Plain(Columns::DEFAULT),
+        )
+        .unwrap();
+        let object = value.as_object().unwrap();
+        assert_eq!(
+            object.keys().collect::<Vec<_>>(),
+            ["bytes", "chars", "lines", "max_line_length", "words"]
+        );
+        assert!(object.values().all(serde_json::Value::is_u64));
+        assert_eq!(value["lines"], 2);
+        assert_eq!(value["words"], 3);
+        assert_eq!(value["bytes"], 14);
+
+        let value = count_to_json(Cursor::new("héllo\n"), CountType::MaxLineLength).unwrap();
+        assert_eq!(value["max_line_length"], 5);
+
+        assert!(matches!(
+            count_to_json(Cursor::new(""), CountType::BracketBalance),
+            Err(CcwcError::Usage(_))
+        ));
     }
 
-    pub fn count_words(&self) -> Result<usize, io::Error> {
-        let input_data = self.read_input()?;
-        Self::count_words_from_reader(Cursor::new(input_data.as_str()))
+    #[cfg(feature = "glob")]
+    #[test]
+    fn test_glob_patterns_expand_to_matching_files() {
+        let root = std::env::temp_dir().join(format!("gfwc-glob-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "one\n").unwrap();
+        std::fs::write(root.join("b.txt"), "two\nthree\n").unwrap();
+        std::fs::write(root.join("c.log"), "four\n").unwrap();
+        // A literal name with a metacharacter is counted, not expanded.
+        std::fs::write(root.join("?.txt"), "five\n").unwrap();
+        let root_path = root.to_string_lossy().into_owned();
+
+        let output_for = |pattern: &str| {
+            let args = ["gfwc", "-l", &format!("{}/{}", root_path, pattern)].map(String::from);
+            let mut out = Vec::new();
+            let result = Counter::from(Config::build(&args).unwrap()).count_into(&mut out);
+            (String::from_utf8(out).unwrap(), result)
+        };
+
+        let (out, result) = output_for("[ab].txt");
+        assert!(result.is_ok());
+        assert_eq!(
+            out,
+            format!(
+                " 1 {root}/a.txt\n 2 {root}/b.txt\n 3 total\n",
+                root = root_path
+            )
+        );
+
+        let (out, result) = output_for("?.txt");
+        assert!(result.is_ok());
+        assert_eq!(out, format!("1 {}/?.txt\n", root_path));
+
+        let (out, result) = output_for("*.md");
+        assert!(result.is_ok());
+        assert_eq!(out, "");
+        std::fs::remove_dir_all(&root).unwrap();
     }
 
-    pub fn count_chars(&self) -> Result<usize, io::Error> {
-        let input_data = self.read_input()?;
-        Self::count_chars_from_reader(Cursor::new(input_data.as_str()))
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gz_input_is_counted_decompressed() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let input_data = "Hello, World!\nBonjour\n";
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(input_data.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // The streaming readers take the decoder like any other reader.
+        let decoder = io::BufReader::new(flate2::read::GzDecoder::new(compressed.as_slice()));
+        assert_eq!(
+            Counter::count_bytes_from_reader(decoder).unwrap(),
+            input_data.len()
+        );
+
+        let dir = std::env::temp_dir();
+        let gz = dir.join(format!("gfwc-decompress-{}.gz", std::process::id()));
+        let raw = dir.join(format!("gfwc-decompress-{}", std::process::id()));
+        std::fs::write(&gz, &compressed).unwrap();
+        std::fs::write(&raw, &compressed).unwrap();
+        let count = |flags: &[&str], path: &std::path::Path| {
+            let mut args = vec!["gfwc".to_string()];
+            args.extend(flags.iter().map(|s| s.to_string()));
+            args.push(path.to_string_lossy().into_owned());
+            Counter::from(Config::build(&args).unwrap())
+                .count_all()
+                .unwrap()
+        };
+
+        let expected =
+            Counter::from_reader(input_data.as_bytes(), CountType::Plain(Columns::DEFAULT))
+                .count_all()
+                .unwrap();
+        assert_eq!(count(&[], &gz), expected);
+        assert_eq!(count(&["--decompress"], &raw), expected);
+        assert_eq!(count(&["-c"], &raw).bytes, compressed.len());
+        std::fs::remove_file(&gz).unwrap();
+        std::fs::remove_file(&raw).unwrap();
     }
 
-    fn read_input(&self) -> Result<String, io::Error> {
-        let mut buffer = String::new();
-        if let Some(ref path) = self.file_path {
-            let mut file = File::open(path)?;
-            file.read_to_string(&mut buffer)?;
-        } else {
-            io::stdin().read_to_string(&mut buffer)?;
-        }
-        Ok(buffer)
+    #[test]
+    fn test_word_freq_breaks_ties_alphabetically_and_keeps_case() {
+        let input_data = "the cat\nThe dog the\n\ndog cat  ant\n";
+        let tally =
+            Counter::count_word_freq_from_reader(Cursor::new(input_data), &Tokenizer::Whitespace)
+                .unwrap();
+        let expected: Vec<(String, usize)> =
+            [("cat", 2), ("dog", 2), ("the", 2), ("The", 1), ("ant", 1)]
+                .iter()
+                .map(|&(word, count)| (word.to_string(), count))
+                .collect();
+        assert_eq!(sort_by_frequency(tally), expected);
+
+        let args: Vec<String> = ["gfwc", "--freq-top", "2", "--freq"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let counter = counter_reading(&args, Cursor::new(input_data));
+        let mut out = Vec::new();
+        counter.count_into(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "2 cat\n2 dog\n");
+
+        let args = vec![
+            "gfwc".to_string(),
+            "--freq-top".to_string(),
+            "2".to_string(),
+        ];
+        assert!(Config::build(&args).is_err());
     }
 
-    fn count_bytes_from_reader<R: BufRead>(mut reader: R) -> Result<usize, io::Error> {
-        let mut total_bytes = 0;
-        let mut buffer = [0; 1024];
-        while let Ok(bytes_read) = reader.read(&mut buffer) {
-            if bytes_read == 0 {
-                break;
-            }
-            total_bytes += bytes_read;
+    #[test]
+    fn test_min_count_drops_rare_words_after_folding() {
+        let freq = |flags: &[&str]| {
+            let args: Vec<String> = ["gfwc", "--freq"]
+                .iter()
+                .chain(flags)
+                .map(|arg| arg.to_string())
+                .collect();
+            let counter =
+                counter_reading(&args, Cursor::new("the cat\nThe dog the\n\ndog cat  ant\n"));
+            let mut out = Vec::new();
+            counter.count_into(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(freq(&["--min-count", "2"]), "2 cat\n2 dog\n2 the\n");
+        assert_eq!(freq(&["--min-count", "3"]), "");
+        assert_eq!(freq(&["--min-count", "3", "--normalize-words"]), "3 the\n");
+        assert_eq!(freq(&["--min-count", "2", "--freq-top", "1"]), "2 cat\n");
+
+        for flags in [
+            &["gfwc", "--min-count", "2"][..],
+            &["gfwc", "--freq", "--min-count", "0"],
+        ] {
+            let args: Vec<String> = flags.iter().map(|arg| arg.to_string()).collect();
+            assert!(Config::build(&args).is_err());
         }
-        Ok(total_bytes)
     }
 
-    fn count_lines_from_reader<R: BufRead>(reader: R) -> Result<usize, io::Error> {
-        Ok(reader.lines().count())
+    #[test]
+    fn test_normalize_words_folds_case_and_punctuation() {
+        let tally = Counter::count_word_freq_from_reader(
+            Cursor::new("Hello, hello"),
+            &Tokenizer::Normalized,
+        )
+        .unwrap();
+        assert_eq!(tally, HashMap::from([("hello".to_string(), 2)]));
+
+        let count = |text: &str| {
+            Counter::count_tokens_from_reader(Cursor::new(text), &Tokenizer::Normalized).unwrap()
+        };
+        assert_eq!(count("\"Wait\" -- don't (stop)!\n"), 3);
+        assert_eq!(count("... --\n"), 0);
+
+        let args: Vec<String> = ["gfwc", "-w", "--normalize-words"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let config = Config::build(&args).unwrap();
+        assert!(matches!(config.options.tokenizer, Tokenizer::Normalized));
     }
 
-    fn count_words_from_reader<R: BufRead>(reader: R) -> Result<usize, io::Error> {
-        let mut count = 0;
-        for line in reader.lines() {
-            count += line?.split_whitespace().count();
+    #[test]
+    fn test_encoding_decodes_utf16le_with_and_without_bom() {
+        let text = "héllo wörld\n🌍\n";
+        let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let with_bom = [&[0xFF, 0xFE][..], &utf16].concat();
+        assert_eq!(Encoding::Utf16Le.decode(utf16.clone()).unwrap(), text);
+        assert_eq!(Encoding::Utf16Le.decode(with_bom.clone()).unwrap(), text);
+        assert!(Encoding::Utf16Le.decode(vec![b'a', 0, b'b']).is_err());
+
+        let args: Vec<String> = ["gfwc", "-lwmc", "--encoding", "utf16le"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        for input in [utf16, with_bom] {
+            let raw_len = input.len();
+            let counter = counter_reading(&args, Cursor::new(input));
+            let mut out = Vec::new();
+            counter.count_into(&mut out).unwrap();
+            // 2 lines, 3 words, 14 chars, and the raw bytes on disk.
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                format!("      2       3      14 {:>7}\n", raw_len)
+            );
         }
-        Ok(count)
+
+        let args = vec![
+            "gfwc".to_string(),
+            "--encoding".to_string(),
+            "ebcdic".to_string(),
+        ];
+        assert!(Config::build(&args).is_err());
     }
 
-    fn count_chars_from_reader<R: BufRead>(mut reader: R) -> Result<usize, io::Error> {
-        let mut total_chars = 0;
-        let mut buffer = String::new();
+    #[test]
+    fn test_null_terminates_every_record_including_total() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("gfwc-null-a b-{}", std::process::id()));
+        let second = dir.join(format!("gfwc-null-c\nd-{}", std::process::id()));
+        std::fs::write(&first, "one two\n").unwrap();
+        std::fs::write(&second, "three\n").unwrap();
+        let first = first.to_string_lossy().into_owned();
+        let second = second.to_string_lossy().into_owned();
 
-        while reader.read_to_string(&mut buffer)? > 0 {
-            total_chars += buffer.chars().count();
-            buffer.clear(); // Clear the buffer for the next chunk of data.
+        for flag in ["-0", "--null"] {
+            let args = ["gfwc", "-l", flag, &first, &second].map(String::from);
+            let mut out = Vec::new();
+            Counter::from(Config::build(&args).unwrap())
+                .count_into(&mut out)
+                .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            let records: Vec<&str> = out.split_terminator('\0').collect();
+            assert_eq!(
+                records,
+                [
+                    format!(" 1 {}", first),
+                    format!(" 1 {}", second),
+                    " 2 total".to_string()
+                ]
+            );
         }
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+    }
+
+    #[test]
+    fn test_flags_after_the_first_merge_rather_than_naming_files() {
+        let args = ["gfwc", "-l", "-w", "file.txt"].map(String::from);
+        let config = Config::build(&args).unwrap();
+        assert_eq!(
+            config.count_type,
+            CountType::Plain(Columns::LINES.union(Columns::WORDS))
+        );
+        assert_eq!(config.file_paths, ["file.txt"]);
 
-        Ok(total_chars)
+        let args = ["gfwc", "-w", "--", "-l"].map(String::from);
+        let config = Config::build(&args).unwrap();
+        assert_eq!(config.count_type, CountType::Plain(Columns::WORDS));
+        assert_eq!(config.file_paths, ["-l"]);
     }
 
-    fn count_all_from_input(input_data: Arc<String>) -> Result<(usize, usize, usize), io::Error> {
-        let byte_handle = {
-            let input_data = Arc::clone(&input_data);
-            thread::spawn(move || Self::count_bytes_from_reader(Cursor::new(input_data.as_str())))
+    #[test]
+    fn test_double_dash_ends_options() {
+        let parse = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            Command::parse(&args)
         };
-
-        let line_handle = {
-            let input_data = Arc::clone(&input_data);
-            thread::spawn(move || Self::count_lines_from_reader(Cursor::new(input_data.as_str())))
+        let Ok(Command::Count(config)) = parse(&["gfwc", "--", "-l", "--json", "-h", "--"]) else {
+            panic!("expected files after --");
         };
+        assert_eq!(config.count_type, CountType::Plain(Columns::DEFAULT));
+        assert_eq!(config.file_paths, ["-l", "--json", "-h", "--"]);
 
-        let word_handle = {
-            let input_data = Arc::clone(&input_data);
-            thread::spawn(move || Self::count_words_from_reader(Cursor::new(input_data.as_str())))
+        // Options before the separator still apply.
+        let Ok(Command::Count(config)) = parse(&["gfwc", "-c", "a.txt", "--", "--version"]) else {
+            panic!("expected files after --");
         };
+        assert_eq!(config.count_type, CountType::Plain(Columns::BYTES));
+        assert_eq!(config.file_paths, ["a.txt", "--version"]);
+        assert!(matches!(
+            parse(&["gfwc", "-h", "--", "x"]),
+            Ok(Command::Help)
+        ));
 
-        let byte_count = byte_handle.join().unwrap()?;
-        let line_count = line_handle.join().unwrap()?;
-        let word_count = word_handle.join().unwrap()?;
-
-        Ok((byte_count, line_count, word_count))
+        let dir = std::env::temp_dir().join(format!("gfwc-dashdash-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("-l"), "one two\n").unwrap();
+        let path = dir.join("-l").to_string_lossy().into_owned();
+        let args = ["gfwc", "-w", "--", &path].map(String::from);
+        let mut out = Vec::new();
+        Counter::from(Config::build(&args).unwrap())
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("2 {}\n", path));
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-}
 
-impl From<Config> for Counter {
-    fn from(config: Config) -> Self {
-        Counter {
-            count_type: config.get_count_type(),
-            file_path: config.get_file_path(),
+    #[test]
+    fn test_tab_width_sets_tab_stops_for_max_line_length() {
+        let output_for = |flags: &[&str]| {
+            let mut args = vec!["gfwc".to_string(), "-L".to_string()];
+            args.extend(flags.iter().map(|s| s.to_string()));
+            let mut out = Vec::new();
+            counter_reading(&args, Cursor::new("\tX\n"))
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(output_for(&[]), "9\n");
+        assert_eq!(output_for(&["--tab-width", "4"]), "5\n");
+
+        for bad in [
+            &["--tab-width", "0"][..],
+            &["--tab-width", "x"],
+            &["--tab-width"],
+        ] {
+            let mut args = vec!["gfwc".to_string(), "-L".to_string()];
+            args.extend(bad.iter().map(|s| s.to_string()));
+            assert!(Config::build(&args).is_err(), "{:?}", bad);
         }
+        let args = ["gfwc", "-w", "--tab-width", "4"].map(String::from);
+        assert!(Config::build(&args).is_err());
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
 
     #[test]
-    fn test_count_bytes() {
-        let input_data = "Hello, world!";
-        let cursor = Cursor::new(input_data);
-        let byte_count = Counter::count_bytes_from_reader(cursor).unwrap();
-        assert_eq!(byte_count, input_data.len());
+    fn test_count_options_apply_to_every_input_counted_with_them() {
+        let options = CountOptions::default()
+            .head_lines(2)
+            .tab_width(4)
+            .read_chunk_size(3);
+        let output_for = |input: &'static [u8], count_type| {
+            let mut out = Vec::new();
+            Counter::from_reader(input, count_type)
+                .with_options(options.clone())
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            output_for(
+                b"one two\nthree\nfour five six\n",
+                CountType::Plain(Columns::DEFAULT)
+            ),
+            "      2       3      14\n"
+        );
+        assert_eq!(
+            output_for(b"\tx\n\t\tab\nlonger than both\n", CountType::MaxLineLength),
+            "10\n"
+        );
+
+        let path = std::env::temp_dir().join(format!("gfwc-options-{}", std::process::id()));
+        std::fs::write(&path, "a\nb\nc\n").unwrap();
+        let config = Config::builder()
+            .count_type(CountType::Plain(Columns::LINES))
+            .options(options)
+            .file_path(path.to_string_lossy())
+            .build();
+        let mut out = Vec::new();
+        Counter::from(config).count_into(&mut out).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("2 {}\n", path.display())
+        );
+
+        let defaults = Counter::from_reader(&b"\tx\n"[..], CountType::MaxLineLength);
+        let mut out = Vec::new();
+        defaults.count_into(&mut out).unwrap();
+        assert_eq!(out, b"9\n");
     }
 
     #[test]
-    fn test_count_chars() {
-        let input_data = "Hello, 🌍!";
-        let cursor = Cursor::new(input_data);
-        let char_count = Counter::count_chars_from_reader(cursor).unwrap();
-        assert_eq!(char_count, input_data.chars().count());
+    fn test_max_memory_streams_large_input_and_refuses_to_buffer_it() {
+        let size = 8 * 1024 * 1024;
+        let large = || io::repeat(b'x').take(size);
+        let options = CountOptions::default().max_memory(4096);
+
+        let counter = Counter::from_reader(large(), CountType::Plain(Columns::DEFAULT))
+            .with_options(options.clone());
+        let counts = counter.count_stats().unwrap();
+        assert_eq!(
+            (counts.lines, counts.words, counts.bytes),
+            (0, 1, size as usize)
+        );
+
+        // -L keeps only widths, so even a single line bigger than the budget
+        // streams.
+        let counter =
+            Counter::from_reader(large(), CountType::MaxLineLength).with_options(options.clone());
+        assert_eq!(
+            counter.count_stats().unwrap().max_line_length,
+            size as usize
+        );
+
+        // The line-by-line modes hold one line at a time.
+        let short_lines = "x".repeat(99) + "\n\n";
+        let mut out = Vec::new();
+        Counter::from_reader(
+            Cursor::new(short_lines.repeat(size as usize / 100)),
+            CountType::ParagraphCount,
+        )
+        .with_options(options.clone())
+        .count_file(&mut out)
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", size / 100));
+        let counter =
+            Counter::from_reader(large(), CountType::ParagraphCount).with_options(options.clone());
+        assert!(matches!(
+            counter.count_file(&mut Vec::new()),
+            Err(CcwcError::TooLarge { limit: 4096 })
+        ));
+
+        // Every distinct line is kept to tell whether it comes back.
+        let distinct = (0..size / 8)
+            .map(|i| format!("{:07}\n", i))
+            .collect::<String>();
+        let counter = Counter::from_reader(Cursor::new(distinct), CountType::UniqueLines)
+            .with_options(options.clone());
+        assert!(matches!(
+            counter.count_file(&mut Vec::new()),
+            Err(CcwcError::TooLarge { limit: 4096 })
+        ));
+        let mut out = Vec::new();
+        Counter::from_reader(
+            Cursor::new(short_lines.repeat(1000)),
+            CountType::UniqueLines,
+        )
+        .with_options(options.clone())
+        .count_file(&mut out)
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "2\n");
+
+        // Characters, sentences and substrings stream a buffer or a line at
+        // a time, with the same results as without a budget.
+        let text = "Mr. Smith (née Jones) paid 3.14 dollars. Then he left!\n\
+                    He said \"héllo wörld 🌍.\" Nobody answered [at first].\n\n\
+                    « Encore ? » Non.\tFin. The end\u{2019}s near... Or not?\n"
+            .repeat(3);
+        for flags in [
+            &["-m"][..],
+            &["-lwm"],
+            &["-mc"],
+            &["--sentences"],
+            &["--match", "é"],
+            &["--match", "near... Or"],
+            &["--bracket-balance"],
+            &["--bytes-per-char"],
+            &["--plane", "1"],
+        ] {
+            let output_for = |budget: &[&str]| {
+                let args: Vec<String> = ["gfwc"]
+                    .iter()
+                    .chain(flags)
+                    .chain(budget)
+                    .map(|s| s.to_string())
+                    .collect();
+                let mut out = Vec::new();
+                counter_reading(&args, Cursor::new(text.clone()))
+                    .count_into(&mut out)
+                    .map(|()| String::from_utf8(out).unwrap())
+            };
+            assert_eq!(
+                output_for(&["--max-memory", "10"]).unwrap(),
+                output_for(&[]).unwrap(),
+                "{:?}",
+                flags
+            );
+        }
+        let counter =
+            Counter::from_reader(large(), CountType::Sentences).with_options(options.clone());
+        let mut out = Vec::new();
+        counter.count_file(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "0\n");
+
+        // Splitting at a marker holds both halves of the input.
+        let counter =
+            Counter::from_reader(large(), CountType::SplitAt).with_options(options.clone());
+        assert!(matches!(
+            counter.count_file(&mut Vec::new()),
+            Err(CcwcError::TooLarge { limit: 4096 })
+        ));
+
+        let small =
+            Counter::from_reader(&b"fits\n"[..], CountType::MaxLineLength).with_options(options);
+        assert_eq!(small.count_stats().unwrap().max_line_length, 4);
+
+        let args = ["gfwc", "--max-memory", "0"].map(String::from);
+        assert!(Config::build(&args).is_err());
     }
 
     #[test]
-    fn test_count_words() {
-        let input_data = "Hello world, how are you?";
-        let cursor = Cursor::new(input_data);
-        let word_count = Counter::count_words_from_reader(cursor).unwrap();
-        assert_eq!(word_count, 5); // "Hello", "world,", "how", "are", "you?"
+    fn test_read_chunk_size_gives_identical_counts() {
+        let input_data = "héllo wörld 🌍\none\ttwo  three\n\nlast".repeat(50);
+        let output_for = |flags: &[&str]| {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .collect();
+            let mut out = Vec::new();
+            counter_reading(&args, Cursor::new(input_data.clone()))
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        for counts in [
+            &[][..],
+            &["-c"],
+            &["-l"],
+            &["-w"],
+            &["-lmw"],
+            &["--partial-on-error"],
+        ] {
+            let expected = output_for(counts);
+            for size in ["1", "3", "4096"] {
+                let flags: Vec<&str> = counts
+                    .iter()
+                    .copied()
+                    .chain(["--read-chunk-size", size])
+                    .collect();
+                assert_eq!(output_for(&flags), expected, "{:?}", flags);
+            }
+        }
+
+        for bad in [
+            &["--read-chunk-size", "0"][..],
+            &["--read-chunk-size", "x"],
+            &["--read-chunk-size"],
+        ] {
+            let args: Vec<String> = ["gfwc"].iter().chain(bad).map(|s| s.to_string()).collect();
+            assert!(Config::build(&args).is_err(), "{:?}", bad);
+        }
+        let args = ["gfwc", "--read-chunk-size", "512"].map(String::from);
+        assert_eq!(
+            Config::build(&args).unwrap().options.read_chunk_size,
+            Some(512)
+        );
     }
 
     #[test]
-    fn test_count_lines() {
-        let input_data = "Line one\nLine two\nLine three";
-        let cursor = Cursor::new(input_data);
-        let line_count = Counter::count_lines_from_reader(cursor).unwrap();
-        assert_eq!(line_count, 3);
+    fn test_match_counts_non_overlapping_substrings() {
+        let count = |needle: &str, ignore_case, text: &str| {
+            let matcher = Matcher::substring(needle, ignore_case);
+            matcher.count(Cursor::new(text)).unwrap()
+        };
+        assert_eq!(count("aa", false, "aaaaa"), 2);
+        assert_eq!(count("TODO", false, "TODO: x // TODO\nTODOTODO todo"), 4);
+        assert_eq!(count("TODO", true, "TODO: x // TODO\nTODOTODO todo"), 5);
+        assert_eq!(count("\n\n", false, "a\n\n\nb\n\n"), 2);
+        assert_eq!(count("x", false, ""), 0);
+        // Matches spanning lines are found, and none are counted twice.
+        assert_eq!(count("é\né", false, "aé\néé\né"), 2);
+        assert_eq!(count("b\nab\na", false, "ab\nab\nab\nab\na"), 2);
+
+        let args = ["gfwc", "--match", "TODO"].map(String::from);
+        let mut out = Vec::new();
+        counter_reading(&args, Cursor::new("TODO one\nnone\nTODO TODO"))
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "3\n");
+
+        for bad in [&["--match"][..], &["--match", ""]] {
+            let args: Vec<String> = ["gfwc"].iter().chain(bad).map(|s| s.to_string()).collect();
+            assert!(Config::build(&args).is_err(), "{:?}", bad);
+        }
     }
 
+    #[cfg(feature = "regex")]
     #[test]
-    fn test_count_all() {
-        let input_data = String::from("Hello, world!\nRust is fun.");
+    fn test_match_regex_counts_pattern_matches() {
+        let source = "fn main() {\n    fn_helper(); fn inner() {}\n}\n// fn\n";
+        let count = |flags: &[&str]| {
+            let args: Vec<String> = ["gfwc"]
+                .iter()
+                .chain(flags)
+                .map(|s| s.to_string())
+                .collect();
+            let mut out = Vec::new();
+            counter_reading(&args, Cursor::new(source))
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(count(&["--match-regex", r"\bfn\b"]), "3\n");
+        assert_eq!(count(&["--match-regex", r"\bFN\b", "--ignore-case"]), "3\n");
+        // Empty matches aren't counted.
+        assert_eq!(count(&["--match-regex", "x*"]), "0\n");
 
-        // Use Cursor to simulate stdin with `input_data`
-        let mock_stdin = Arc::new(input_data.clone());
+        for bad in [
+            &["--match-regex", "("][..],
+            &["--match-regex", "a", "--match", "a"],
+        ] {
+            let args: Vec<String> = ["gfwc"].iter().chain(bad).map(|s| s.to_string()).collect();
+            assert!(Config::build(&args).is_err(), "{:?}", bad);
+        }
+    }
 
-        // Pass `Some(mock_stdin)` as the reader to `count_all`
-        let (byte_count, line_count, word_count) =
-            Counter::count_all_from_input(mock_stdin).unwrap();
+    #[test]
+    fn test_non_empty_and_blank_lines() {
+        let input_data = "code\n\n   \n\t\r\n  indented\n\nlast";
+        let output_for = |flag: &str| {
+            let args = ["gfwc", flag].map(String::from);
+            let mut out = Vec::new();
+            counter_reading(&args, Cursor::new(input_data))
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(output_for("--non-empty-lines"), "3\n");
+        assert_eq!(output_for("--blank-lines"), "4\n");
 
-        // Expected counts based on input
-        let expected_bytes = input_data.len();
-        let expected_lines = 2;
-        let expected_words = 5;
+        // An unterminated last line counts, whichever kind it is.
+        let non_empty = |line: &str| !line.trim().is_empty();
+        let count = |input: &str| {
+            Counter::count_lines_where_from_reader(Cursor::new(input), non_empty).unwrap()
+        };
+        assert_eq!(count("a\nb"), 2);
+        assert_eq!(count("a\nb\n"), 2);
+        assert_eq!(count(""), 0);
+        let blank = |line: &str| line.trim().is_empty();
+        assert_eq!(
+            Counter::count_lines_where_from_reader(Cursor::new("a\n  "), blank).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_line_stats() {
+        let stats = |input: &str| {
+            Counter::measure_line_stats_from_reader(Cursor::new(input), &CountOptions::default())
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(stats(""), "min=0 max=0 avg=0");
+        assert_eq!(stats("single"), "min=6 max=6 avg=6.00");
+        assert_eq!(stats("single\n"), "min=6 max=6 avg=6.00");
+        // The blank line counts, and the unterminated last line is included.
+        assert_eq!(stats("a\nabcd\n\nab"), "min=0 max=4 avg=1.75");
+        assert_eq!(stats("x\tyz\nhéllo\n"), "min=5 max=10 avg=7.50");
 
-        assert_eq!(byte_count, expected_bytes);
-        assert_eq!(line_count, expected_lines);
-        assert_eq!(word_count, expected_words);
+        let args = ["gfwc", "--line-stats", "--tab-width", "4"].map(String::from);
+        let mut out = Vec::new();
+        counter_reading(&args, Cursor::new("x\tyz\nhéllo\n"))
+            .count_into(&mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "min=5 max=6 avg=5.50\n");
     }
 
     #[test]
-    fn test_config_build_with_flag_and_file_path() {
-        let args = vec!["gfwc".to_string(), "-l".to_string(), "test.txt".to_string()];
-        let config = Config::build(&args).unwrap();
-        assert_eq!(config.count_type, CountType::LineCount);
-        assert_eq!(config.file_path, Some("test.txt".to_string()));
+    fn test_record_size_counts_complete_records_and_remainder() {
+        let output_for = |size: &str, input: &'static str| {
+            let args = ["gfwc", "--record-size", size].map(String::from);
+            let mut out = Vec::new();
+            counter_reading(&args, Cursor::new(input))
+                .count_into(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(output_for("4", "abcdefghijkl"), "3 0\n");
+        assert_eq!(output_for("4", "abcdefghijklmn"), "3 2\n");
+        assert_eq!(output_for("16", "short"), "0 5\n");
+        assert_eq!(output_for("1", ""), "0 0\n");
+
+        for bad in [
+            &["--record-size", "0"][..],
+            &["--record-size", "x"],
+            &["--record-size"],
+        ] {
+            let args: Vec<String> = ["gfwc"].iter().chain(bad).map(|s| s.to_string()).collect();
+            assert!(Config::build(&args).is_err(), "{:?}", bad);
+        }
     }
 
     #[test]
-    fn test_config_build_with_only_file_path() {
-        let args = vec!["gfwc".to_string(), "test.txt".to_string()];
-        let config = Config::build(&args).unwrap();
-        assert_eq!(config.count_type, CountType::AllCount);
-        assert_eq!(config.file_path, Some("test.txt".to_string()));
+    fn test_count_stats_returns_counts_without_printing() {
+        let input_data = "one two\n\tthree\n";
+        let counts =
+            Counter::from_reader(Cursor::new(input_data), CountType::Plain(Columns::DEFAULT))
+                .count_stats()
+                .unwrap();
+        assert_eq!(counts.lines, 2);
+        assert_eq!(counts.words, 3);
+        assert_eq!(counts.bytes, 15);
+        assert_eq!(counts.to_string(), "2 3 15");
+
+        let counts = Counter::from_reader(Cursor::new(input_data), CountType::MaxLineLength)
+            .count_stats()
+            .unwrap();
+        assert_eq!(counts.max_line_length, 13);
+
+        let err = Counter::from_reader(Cursor::new(input_data), CountType::BracketBalance)
+            .count_stats()
+            .unwrap_err();
+        assert!(matches!(err, CcwcError::Usage(_)));
     }
 
     #[test]
-    fn test_config_invalid_flag() {
-        let args = vec!["gfwc".to_string(), "-z".to_string()];
-        let config = Config::build(&args);
-        assert!(config.is_err());
+    fn test_empty_input_counts_zero_in_every_mode() {
+        let empty = || Cursor::new("");
+        assert_eq!(Counter::count_bytes_from_reader(empty()).unwrap(), 0);
+        assert_eq!(Counter::count_lines_from_reader(empty()).unwrap(), 0);
+        assert_eq!(Counter::count_words_from_reader(empty()).unwrap(), 0);
+        assert_eq!(Counter::count_chars_from_reader(empty()).unwrap(), 0);
+        assert_eq!(
+            Counter::count_tokens_from_reader(empty(), &Tokenizer::Normalized).unwrap(),
+            0
+        );
+        let range = ColumnRange { start: 1, end: 3 };
+        assert_eq!(
+            Counter::count_words_in_columns_from_reader(empty(), range, &Tokenizer::Whitespace)
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            Counter::count_chars_in_columns_from_reader(empty(), range).unwrap(),
+            0
+        );
+        assert_eq!(
+            Counter::count_max_blank_run_from_reader(empty()).unwrap(),
+            0
+        );
+        assert!(Counter::count_bracket_balance_from_reader(empty())
+            .unwrap()
+            .is_balanced());
+        assert_eq!(
+            Counter::count_trailing_blank_lines_from_reader(empty()).unwrap(),
+            0
+        );
+        assert!(Counter::find_tab_after_space_from_reader(empty())
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            Counter::count_plane_chars_from_reader(empty(), 0).unwrap(),
+            0
+        );
+        assert_eq!(
+            Counter::count_trailing_nulls_from_reader(empty()).unwrap(),
+            0
+        );
+        assert_eq!(
+            Counter::count_distinct_lengths_from_reader(empty(), LengthUnit::Chars).unwrap(),
+            0
+        );
+        assert_eq!(Counter::count_trim_savings_from_reader(empty()).unwrap(), 0);
+        assert_eq!(
+            Counter::count_indent_changes_from_reader(empty(), true, 8).unwrap(),
+            0
+        );
+        assert_eq!(
+            Counter::count_case_lines_from_reader(empty()).unwrap(),
+            (0, 0)
+        );
+        assert!(Counter::count_first_token_freq_from_reader(empty())
+            .unwrap()
+            .is_empty());
+        assert!(
+            Counter::count_word_freq_from_reader(empty(), &Tokenizer::Whitespace)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            Counter::find_shortest_line_from_reader(empty()).unwrap(),
+            None
+        );
+        assert_eq!(
+            Counter::measure_vocab_coverage_from_reader(
+                empty(),
+                &HashSet::new(),
+                &Tokenizer::Whitespace,
+                false
+            )
+            .unwrap(),
+            (0, 0)
+        );
+        assert_eq!(
+            Counter::count_max_line_length_from_reader(empty(), &CountOptions::default()).unwrap(),
+            0
+        );
+        assert_eq!(Counter::count_paragraphs_from_reader(empty()).unwrap(), 0);
+        assert_eq!(
+            Counter::count_all_from_input(b"", &CountOptions::default()).unwrap(),
+            Counts::default()
+        );
+
+        // Every mode that needs nothing but the input runs cleanly.
+        for count_type in [
+            CountType::Plain(Columns::DEFAULT),
+            CountType::Plain(Columns::CHARS),
+            CountType::Plain(Columns::LINES.union(Columns::CHARS)),
+            CountType::MaxLineLength,
+            CountType::BytesPerChar,
+            CountType::MaxBlankRun(Some(0)),
+            CountType::BracketBalance,
+            CountType::TrailingBlankLines,
+            CountType::SplitAt,
+            CountType::TabAfterSpace,
+            CountType::Plane(0),
+            CountType::TrailingNulls,
+            CountType::DistinctLengths(LengthUnit::Bytes),
+            CountType::TrimSavings,
+            CountType::IndentChanges {
+                include_blank: true,
+            },
+            CountType::AllUpperLines,
+            CountType::EncodingReport,
+            CountType::FirstTokenFreq,
+            CountType::WordFreq,
+            CountType::ShortestLine,
+            CountType::ParagraphCount,
+            CountType::Sentences,
+            CountType::LineEndings,
+            CountType::UniqueLines,
+            CountType::DuplicateLines,
+        ] {
+            let result = Counter::from_reader(empty(), count_type).count_into(Vec::new());
+            assert!(result.is_ok(), "{:?}: {:?}", count_type, result);
+        }
+
+        let path = std::env::temp_dir().join(format!("gfwc-empty-{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let args = ["gfwc", &path].map(String::from);
+        let mut out = Vec::new();
+        Counter::from(Config::build(&args).unwrap())
+            .count_into(&mut out)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("0 0 0 {}\n", path));
     }
 
     #[test]
-    fn test_config_no_flag_defaults_to_all_count() {
-        let args = vec!["gfwc".to_string(), "text.txt".to_string()];
-        let config = Config::build(&args).unwrap();
-        assert_eq!(config.count_type, CountType::AllCount);
-        assert_eq!(config.file_path, Some("text.txt".to_string()));
+    fn test_count_str() {
+        let input = "héllo wörld\nbye\n";
+        assert_eq!(
+            count_str(input, CountType::Plain(Columns::DEFAULT)),
+            Counts {
+                lines: 2,
+                words: 3,
+                bytes: 18,
+                ..Counts::default()
+            }
+        );
+        assert_eq!(
+            count_str(input, CountType::Plain(Columns::CHARS)),
+            Counts {
+                chars: 16,
+                ..Counts::default()
+            }
+        );
+        assert_eq!(
+            count_str(input, CountType::MaxLineLength).max_line_length,
+            11
+        );
+        assert_eq!(count_str(input, CountType::TrimSavings), Counts::default());
+        for count_type in [
+            CountType::Plain(Columns::DEFAULT.union(Columns::CHARS)),
+            CountType::MaxLineLength,
+        ] {
+            assert_eq!(count_str("", count_type), Counts::default());
+        }
     }
 
     #[test]
-    fn test_config_valid_flag() {
-        let args = vec!["gfwc".to_string(), "-w".to_string(), "text.txt".to_string()];
-        let config = Config::build(&args).unwrap();
-        assert_eq!(config.count_type, CountType::WordCount);
-        assert_eq!(config.file_path, Some("text.txt".to_string()));
+    fn test_counter_from_string() {
+        let counts = Counter::from("hello world").count_stats().unwrap();
+        assert_eq!(counts.lines, 0);
+        assert_eq!(counts.words, 2);
+        assert_eq!(counts.bytes, 11);
+
+        // The text is kept, so it can be counted more than once.
+        let counter = Counter::from(String::from("one\ntwo\n"));
+        assert_eq!(counter.count_stats().unwrap().lines, 2);
+        assert_eq!(counter.count_stats().unwrap().lines, 2);
+        let mut out = Vec::new();
+        counter.count_into(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "      2       2       8\n");
     }
 
     #[test]
-    fn test_config_only_flag_no_file_path() {
-        let args = vec!["gfwc".to_string(), "-w".to_string()];
-        let config = Config::build(&args).unwrap();
-        assert_eq!(config.count_type, CountType::WordCount);
-        assert!(config.file_path.is_none());
+    fn test_count_paths_reports_each_input_in_order() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("gfwc-count-paths-a-{}", std::process::id()));
+        let missing = dir.join(format!("gfwc-count-paths-missing-{}", std::process::id()));
+        let second = dir.join(format!("gfwc-count-paths-b-{}", std::process::id()));
+        std::fs::write(&first, "one two\nthree\n").unwrap();
+        std::fs::write(&second, "four\n").unwrap();
+
+        let paths = [first.clone(), missing.clone(), second.clone()];
+        let results = count_paths(&paths, CountType::Plain(Columns::DEFAULT));
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+
+        let names: Vec<&PathBuf> = results.iter().map(|(path, _)| path).collect();
+        assert_eq!(names, [&first, &missing, &second]);
+        let counts = |index: usize| {
+            let counts: &Counts = results[index].1.as_ref().unwrap();
+            (counts.lines, counts.words, counts.bytes)
+        };
+        assert_eq!(counts(0), (2, 3, 14));
+        assert!(matches!(
+            &results[1].1,
+            Err(CcwcError::Io(error)) if error.kind() == io::ErrorKind::NotFound
+        ));
+        assert_eq!(counts(2), (1, 1, 5));
+
+        let total = total_counts(&results);
+        assert_eq!((total.lines, total.words, total.bytes), (3, 4, 19));
+        assert_eq!(total_counts(&[]), Counts::default());
     }
 }
@@ -1,19 +1,39 @@
 use std::env;
+use std::io;
 use std::process;
 
-use gfwc::{Config, Counter};
+use gfwc::{program_name, CcwcError, Command, Counter, USAGE, VERSION};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let program = program_name(&args);
 
-    let config = Config::build(&args).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+    let command = Command::parse(&args).unwrap_or_else(|err| {
+        eprintln!("{}: {}", program, err);
+        process::exit(err.exit_code());
     });
 
-    let counter = Counter::from(config);
-    if let Err(e) = counter.count() {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
+    let config = match command {
+        Command::Count(config) => config,
+        Command::Help => {
+            print!("{}", USAGE.replace("<program>", &program));
+            return;
+        }
+        Command::Version => {
+            println!("{} {}", program, VERSION);
+            return;
+        }
+    };
+
+    let counter = Counter::from(*config);
+    match counter.count_into(io::stdout().lock()) {
+        Ok(()) => {}
+        // Like `wc`, each failed file has already been reported and only the
+        // exit status is left to set.
+        Err(e @ CcwcError::FilesFailed { .. }) => process::exit(e.exit_code()),
+        Err(e) => {
+            eprintln!("{}: {}", program, e);
+            process::exit(e.exit_code());
+        }
     }
 }
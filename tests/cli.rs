@@ -0,0 +1,582 @@
+//! Runs the built `gfwc` binary end to end and checks exactly what it prints
+//! and how it exits.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// A scratch directory under the system temp dir, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("gfwc-cli-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    /// Writes `contents` to `name` and returns its path as a string.
+    fn file(&self, name: &str, contents: &str) -> String {
+        let path = self.0.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn gfwc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_gfwc"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+fn gfwc_with_stdin(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gfwc"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // A usage error exits before reading stdin, closing the pipe early.
+    let written = child.stdin.take().unwrap().write_all(input.as_bytes());
+    if let Err(error) = written {
+        assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn single_file_prints_default_counts() {
+    let dir = TempDir::new("single");
+    let path = dir.file("a.txt", "one two\nthree\n");
+
+    let output = gfwc(&[&path]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), format!(" 2  3 14 {}\n", path));
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn multiple_files_print_a_total() {
+    let dir = TempDir::new("multiple");
+    let first = dir.file("a.txt", "one two\nthree\n");
+    let second = dir.file("b.txt", "four\n");
+
+    let output = gfwc(&["-lc", &first, &second]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        format!(" 2 14 {}\n 1  5 {}\n 3 19 total\n", first, second)
+    );
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn bad_flag_fails_before_reading_input() {
+    let output = gfwc(&["-z"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        "gfwc: Invalid flag -z. Use 'c' for byte count, 'l' for line count, 'w' for word count, or 'm' for character count.\n"
+    );
+}
+
+#[test]
+fn missing_file_is_reported_and_fails() {
+    let dir = TempDir::new("missing");
+    let missing = dir.0.join("missing.txt").to_string_lossy().into_owned();
+
+    let output = gfwc(&[&missing]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        format!("gfwc: {}: No such file or directory\n", missing)
+    );
+
+    // The other inputs are still counted.
+    let present = dir.file("present.txt", "x\n");
+    let output = gfwc(&["-l", &present, &missing]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), format!("1 {}\n1 total\n", present));
+    assert_eq!(
+        stderr(&output),
+        format!("gfwc: {}: No such file or directory\n", missing)
+    );
+}
+
+#[test]
+fn stdin_is_counted_without_a_label() {
+    let output = gfwc_with_stdin(&[], "one two\nthree\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "      2       3      14\n");
+
+    let output = gfwc_with_stdin(&["-w", "-"], "one two\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "2 -\n");
+}
+
+#[test]
+fn crlf_line_endings_count_one_line_each() {
+    let dir = TempDir::new("crlf");
+    let path = dir.file("crlf.txt", "one two\r\nthree\r\n\r\nlast");
+
+    let output = gfwc(&["-lwc", &path]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), format!(" 3  4 22 {}\n", path));
+
+    // A lone \r doesn't end a line.
+    let path = dir.file("cr.txt", "one\rtwo\r");
+    let output = gfwc(&["-l", &path]);
+    assert_eq!(stdout(&output), format!("0 {}\n", path));
+}
+
+#[test]
+fn nested_path_is_opened_with_the_platform_separator() {
+    let dir = TempDir::new("nested");
+    std::fs::create_dir_all(dir.0.join("sub").join("deeper")).unwrap();
+    let path = dir
+        .0
+        .join("sub")
+        .join("deeper")
+        .join("a.txt")
+        .to_string_lossy()
+        .into_owned();
+    std::fs::write(&path, "x\r\ny\r\n").unwrap();
+
+    let output = gfwc(&["-l", &path]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), format!("2 {}\n", path));
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_paths_open_with_either_separator() {
+    let dir = TempDir::new("separators");
+    std::fs::create_dir_all(dir.0.join("sub")).unwrap();
+    std::fs::write(dir.0.join("sub").join("a.txt"), "x\r\ny\r\n").unwrap();
+    let backslashed = format!("{}\\sub\\a.txt", dir.0.display());
+    let forward = backslashed.replace('\\', "/");
+
+    for path in [&backslashed, &forward] {
+        let output = gfwc(&["-l", path]);
+        assert_eq!(output.status.code(), Some(0), "{}", path);
+        assert_eq!(stdout(&output), format!("2 {}\n", path));
+    }
+}
+
+#[test]
+fn default_flags_come_from_the_environment() {
+    let gfwc_with_defaults = |defaults: &str, args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_gfwc"))
+            .args(args)
+            .env("GFWC_DEFAULT_FLAGS", defaults)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // A usage error exits before reading stdin, closing the pipe early.
+        let written = child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all("héllo wörld\n".as_bytes());
+        if let Err(error) = written {
+            assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+        }
+        child.wait_with_output().unwrap()
+    };
+
+    let output = gfwc_with_defaults("-l -w -m", &[]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "      1       2      12\n");
+
+    let output = gfwc_with_defaults("-l -w -m", &["-c"]);
+    assert_eq!(stdout(&output), "14\n");
+
+    let output = gfwc_with_defaults("", &[]);
+    assert_eq!(stdout(&output), "      1       2      14\n");
+
+    let output = gfwc_with_defaults("notes.txt", &[]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "gfwc: GFWC_DEFAULT_FLAGS can only hold options, not files.\n"
+    );
+}
+
+#[test]
+fn check_reports_unreadable_files_without_counting() {
+    let dir = TempDir::new("check");
+    let present = dir.file("present.txt", "one two\n");
+    let missing = dir.0.join("missing.txt").to_string_lossy().into_owned();
+    let directory = dir.0.to_string_lossy().into_owned();
+
+    let output = gfwc(&["--check", &present]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(stderr(&output), "");
+
+    let output = gfwc(&["--check", &present, &missing, &directory]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "gfwc: {}: No such file or directory\ngfwc: {}: Is a directory\n",
+            missing, directory
+        )
+    );
+}
+
+#[test]
+fn fail_on_empty_rejects_empty_files() {
+    let dir = TempDir::new("fail-on-empty");
+    let empty = dir.file("empty.txt", "");
+    let full = dir.file("full.txt", "x\n");
+    let unterminated = dir.file("unterminated.txt", "x");
+
+    let output = gfwc(&[&empty]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), format!("0 0 0 {}\n", empty));
+
+    let output = gfwc(&["--fail-on-empty", &full, &empty]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        format!("1 1 2 {}\n0 0 0 {}\n1 1 2 total\n", full, empty)
+    );
+    assert_eq!(
+        stderr(&output),
+        format!("gfwc: {}: expected at least 1 bytes, found 0\n", empty)
+    );
+
+    // Counting lines instead, a file without a newline is empty too.
+    let output = gfwc(&["-l", "--fail-on-empty=lines", &unterminated]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), format!("0 {}\n", unterminated));
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "gfwc: {}: expected at least 1 lines, found 0\n",
+            unterminated
+        )
+    );
+    let output = gfwc(&["-c", "--fail-on-empty", &unterminated]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = gfwc(&["-l", "--fail-on-empty", &full]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "gfwc: --expect-*-between and --fail-on-empty need that count in the output (default, -l, -w or -c).\n"
+    );
+}
+
+#[cfg(all(unix, feature = "threads"))]
+#[test]
+fn no_align_prints_each_file_as_soon_as_it_is_counted() {
+    use std::io::{BufRead, BufReader, Read};
+
+    // The slow input is counted on a thread of its own while the fast one
+    // finishes.
+    if std::thread::available_parallelism().map_or(1, |n| n.get()) < 2 {
+        return;
+    }
+    let dir = TempDir::new("no-align");
+    let fast = dir.file("fast.txt", "one\ntwo\n");
+    let slow = dir.0.join("slow").to_string_lossy().into_owned();
+    assert!(Command::new("mkfifo")
+        .arg(&slow)
+        .status()
+        .unwrap()
+        .success());
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gfwc"))
+        .args(["-l", "--no-align", &slow, &fast])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Should the fast file wait after all, unblock the pipe so that the
+    // assertion below fails rather than hangs.
+    let watchdog = slow.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_secs(10));
+        let _ = std::fs::write(watchdog, "");
+    });
+    let mut output = BufReader::new(child.stdout.take().unwrap());
+    let mut first = String::new();
+    output.read_line(&mut first).unwrap();
+    assert_eq!(first, format!("2 {}\n", fast));
+
+    std::fs::write(&slow, "x\n").unwrap();
+    let mut rest = String::new();
+    output.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, format!("1 {}\n3 total\n", slow));
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn diff_prints_how_the_second_file_differs() {
+    let dir = TempDir::new("diff");
+    let before = dir.file("before.txt", "one two three\n");
+    let after = dir.file("after.txt", "one two\nthree\nfour fivé\n");
+
+    let output = gfwc(&["--diff", &before, &after]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "Δlines:+2 Δwords:+2 Δbytes:+11 Δchars:+10\n"
+    );
+
+    let output = gfwc(&["--diff", &after, &before]);
+    assert_eq!(
+        stdout(&output),
+        "Δlines:-2 Δwords:-2 Δbytes:-11 Δchars:-10\n"
+    );
+
+    let output = gfwc(&["--diff", &before, &before]);
+    assert_eq!(stdout(&output), "Δlines:+0 Δwords:+0 Δbytes:+0 Δchars:+0\n");
+
+    for args in [
+        &["--diff", &before][..],
+        &["--diff", &before, &after, &after],
+    ] {
+        let output = gfwc(args);
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(stdout(&output), "");
+        assert_eq!(stderr(&output), "gfwc: --diff needs exactly two files.\n");
+    }
+    let output = gfwc(&["--diff", "-l", &before, &after]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "gfwc: --diff always compares lines, words, bytes and chars, so it can't be combined with count flags or another mode.\n"
+    );
+}
+
+#[test]
+fn usage_errors_exit_2_and_file_errors_exit_1() {
+    let dir = TempDir::new("exit-codes");
+    let present = dir.file("present.txt", "x\n");
+    let missing = dir.0.join("missing.txt").to_string_lossy().into_owned();
+
+    assert_eq!(gfwc(&["-z", &present]).status.code(), Some(2));
+    assert_eq!(
+        gfwc(&["--total=sometimes", &present]).status.code(),
+        Some(2)
+    );
+    assert_eq!(gfwc(&["--top"]).status.code(), Some(2));
+
+    assert_eq!(gfwc(&[&missing]).status.code(), Some(1));
+    assert_eq!(gfwc(&[&present, &missing]).status.code(), Some(1));
+    assert_eq!(gfwc(&[&present]).status.code(), Some(0));
+}
+
+#[test]
+fn stdin_is_counted_in_place_among_files() {
+    let dir = TempDir::new("mixed-stdin");
+    let first = dir.file("first.txt", "one\ntwo\n");
+    let last = dir.file("last.txt", "three\n");
+
+    let output = gfwc_with_stdin(&["-l", &first, "-", &last], "a\nb\nc\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "      2 {}\n      3 -\n      1 {}\n      6 total\n",
+            first, last
+        )
+    );
+
+    let output = gfwc_with_stdin(&["-l", "-", &first, "-"], "a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        "gfwc: - can only be given once, as standard input can only be read once.\n"
+    );
+}
+
+#[test]
+fn total_line_is_only_colored_when_asked_or_on_a_terminal() {
+    let dir = TempDir::new("color");
+    let first = dir.file("a.txt", "one two\nthree\n");
+    let second = dir.file("b.txt", "four\n");
+    let per_file = format!(" 2 14 {}\n 1  5 {}\n", first, second);
+
+    // Piped, so auto leaves the output plain.
+    for args in [
+        &["-lc"][..],
+        &["-lc", "--color=auto"],
+        &["-lc", "--color=never"],
+    ] {
+        let output = gfwc(&[args, &[&first, &second]].concat());
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(stdout(&output), format!("{} 3 19 total\n", per_file));
+    }
+
+    let output = gfwc(&["-lc", "--color=always", &first, &second]);
+    assert_eq!(
+        stdout(&output),
+        format!("{}\x1b[1m 3 19 total\x1b[0m\n", per_file)
+    );
+
+    let output = gfwc(&["--color=sometimes", &first]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn bare_prints_only_the_numbers() {
+    let dir = TempDir::new("bare");
+    let first = dir.file("a.txt", "one two\nthree\n");
+    let second = dir.file("b.txt", "four\n");
+
+    let output = gfwc(&["-c", "--bare", &first]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "14\n");
+
+    let output = gfwc(&["--bare", &first, &second]);
+    assert_eq!(stdout(&output), "2 3 14\n1 1 5\n3 4 19\n");
+
+    let output = gfwc(&["-L", "--bare", &first]);
+    assert_eq!(stdout(&output), "7\n");
+
+    let output = gfwc(&["--bare", "--csv", &first]);
+    assert_eq!(output.status.code(), Some(2));
+
+    for labelled in ["--labels", "--summary"] {
+        let output = gfwc(&["--bare", labelled, &first, &second]);
+        assert_eq!(output.status.code(), Some(2), "{}", labelled);
+        assert_eq!(
+            stderr(&output),
+            format!("gfwc: --bare can't be combined with {}.\n", labelled)
+        );
+        assert_eq!(stdout(&output), "");
+    }
+}
+
+#[test]
+fn split_at_labels_each_half_with_the_file_name() {
+    let dir = TempDir::new("split-at");
+    let path = dir.file("mail.eml", "Subject: hi\n\nbody text\nmore\n");
+
+    let output = gfwc(&["--split-at", "\\n\\n", &path]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        format!("before\t0\t2\t11 {}\nafter\t2\t3\t15 {}\n", path, path)
+    );
+
+    let output = gfwc_with_stdin(
+        &["--split-at", "\\n\\n"],
+        "Subject: hi\n\nbody text\nmore\n",
+    );
+    assert_eq!(stdout(&output), "before\t0\t2\t11\nafter\t2\t3\t15\n");
+}
+
+#[test]
+fn encoding_report_tallies_every_input() {
+    let dir = TempDir::new("encoding-report");
+    let ascii = dir.file("ascii.txt", "plain\n");
+    let more_ascii = dir.file("more.txt", "also plain\n");
+    let utf8 = dir.file("utf8.txt", "héllo\n");
+
+    let output = gfwc(&["--encoding-report", &ascii, &utf8, &more_ascii]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "ASCII 2\nUTF-8 1\n");
+
+    let output = gfwc(&["--encoding-report", &utf8]);
+    assert_eq!(stdout(&output), "UTF-8 1\n");
+
+    let missing = dir.0.join("missing.txt");
+    let output = gfwc(&["--encoding-report", &ascii, &missing.to_string_lossy()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "ASCII 1\n");
+}
+
+#[test]
+fn merge_totals_earlier_csv_outputs() {
+    let dir = TempDir::new("merge");
+    let first = dir.file("a.txt", "one two\nthree\n");
+    let second = dir.file("b,c.txt", "four\n");
+    let third = dir.file("d.txt", "five six\n");
+
+    let csv = |name: &str, args: &[&str]| dir.file(name, &stdout(&gfwc(args)));
+    // One output with its own total row, and one without.
+    let both = csv("both.csv", &["--csv", &first, &second]);
+    let one = csv("one.csv", &["--csv", &third]);
+
+    let output = gfwc(&["--merge", &both, &one]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "4 6 28 total\n");
+
+    let output = gfwc(&["--merge", "--csv", &both, &one]);
+    assert_eq!(
+        stdout(&output),
+        "lines,words,bytes,filename\n4,6,28,total\n"
+    );
+
+    let chars = csv("chars.csv", &["--csv", "-lm", &third]);
+    let output = gfwc(&["--merge", &both, &chars]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "gfwc: {}: columns lines,chars,filename don't match lines,words,bytes,filename in {}\n",
+            chars, both
+        )
+    );
+
+    let output = gfwc(&["--merge", &first]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "gfwc: {}: not gfwc --csv output: header \"one two\"\n",
+            first
+        )
+    );
+
+    let output = gfwc(&["--merge", "-l", &both]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "gfwc: --merge takes its columns from the CSV header, so it can't be combined with count flags or another mode.\n"
+    );
+}
+
+#[test]
+fn missing_selected_lines_are_warned_about_with_the_program_name() {
+    let dir = TempDir::new("line-numbers-from");
+    let numbers = dir.file("numbers.txt", "2\n5\n");
+
+    let output = gfwc_with_stdin(
+        &["-l", "--line-numbers-from", &numbers],
+        "one\ntwo\nthree\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "1\n");
+    assert_eq!(stderr(&output), "gfwc: warning: input has no line 5\n");
+}